@@ -0,0 +1,159 @@
+use crate::{Location, PtBuffer};
+
+/// A position in the buffer remembering its piece, so that moving by a few elements
+/// doesn't go through the `O(p)` index lookup again.
+pub struct PtCursor<'a, T: 'a> {
+    table: &'a PtBuffer<'a, T>,
+    idx: usize,
+    piece_idx: usize,
+    /// Offset of `idx` in the piece, the cursor sits past the last piece at the end.
+    offset: usize,
+}
+
+impl<'a, T: 'a> PtBuffer<'a, T> {
+    /// A cursor before the element at `idx`, `idx` being clamped to the buffer length.
+    pub fn cursor_at(&'a self, idx: usize) -> PtCursor<'a, T> {
+        let idx = idx.min(self.length);
+        let (piece_idx, offset) = match self.index_to_piece_loc(idx) {
+            Location::Head(piece_idx) => (piece_idx, 0),
+            Location::Middle(piece_idx, offset) | Location::Tail(piece_idx, offset) => {
+                (piece_idx, offset)
+            }
+            Location::Eof => (self.pieces.len(), 0),
+        };
+
+        PtCursor {
+            table: self,
+            idx,
+            piece_idx,
+            offset,
+        }
+    }
+}
+
+impl<'a, T: 'a> PtCursor<'a, T> {
+    pub fn idx(&self) -> usize {
+        self.idx
+    }
+
+    /// The element after the cursor, `None` at the end of the buffer.
+    pub fn get(&self) -> Option<&'a T> {
+        let piece = self.table.pieces.get(self.piece_idx)?;
+        Some(&self.table.get_buffer(piece)[piece.start + self.offset])
+    }
+
+    /// Move before the previous element and return it.
+    pub fn prev(&mut self) -> Option<&'a T> {
+        if self.idx == 0 {
+            return None;
+        }
+
+        if self.offset == 0 {
+            self.piece_idx -= 1;
+            self.offset = self.table.pieces[self.piece_idx].length;
+        }
+        self.idx -= 1;
+        self.offset -= 1;
+
+        self.get()
+    }
+
+    /// Move by `delta` elements, stopping at the buffer edges. This only walks the pieces
+    /// crossed on the way.
+    pub fn seek(&mut self, delta: isize) {
+        let mut remaining = delta.unsigned_abs();
+        if delta > 0 {
+            while remaining > 0 && self.piece_idx < self.table.pieces.len() {
+                let available = self.table.pieces[self.piece_idx].length - self.offset;
+                if remaining < available {
+                    self.offset += remaining;
+                    self.idx += remaining;
+                    return;
+                }
+
+                remaining -= available;
+                self.idx += available;
+                self.piece_idx += 1;
+                self.offset = 0;
+            }
+        } else {
+            while remaining > 0 && self.idx > 0 {
+                if self.offset == 0 {
+                    self.piece_idx -= 1;
+                    self.offset = self.table.pieces[self.piece_idx].length;
+                }
+
+                let step = remaining.min(self.offset);
+                remaining -= step;
+                self.idx -= step;
+                self.offset -= step;
+            }
+        }
+    }
+}
+
+impl<'a, T> Iterator for PtCursor<'a, T> {
+    type Item = &'a T;
+
+    /// Return the element after the cursor and move past it.
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.get()?;
+        self.idx += 1;
+        self.offset += 1;
+        if self.offset == self.table.pieces[self.piece_idx].length {
+            self.piece_idx += 1;
+            self.offset = 0;
+        }
+
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::PtBuffer;
+
+    fn scattered() -> PtBuffer<'static, u8> {
+        let mut buf = PtBuffer::new(b"Hello world");
+        buf.insert(5, b',');
+        buf.insert(0, b'>');
+        buf.insert(8, b'_');
+        buf.push(b'!');
+        buf
+    }
+
+    #[test]
+    fn should_move_cursor_across_pieces() {
+        let buf = scattered();
+        assert!(buf.pieces.len() > 4);
+
+        let mut cursor = buf.cursor_at(0);
+        for idx in 0..buf.len() {
+            assert_eq!(cursor.idx(), idx);
+            assert_eq!(cursor.next(), Some(&buf[idx]));
+        }
+        assert_eq!(cursor.next(), None);
+        assert_eq!(cursor.idx(), buf.len());
+
+        for idx in (0..buf.len()).rev() {
+            assert_eq!(cursor.prev(), Some(&buf[idx]));
+            assert_eq!(cursor.idx(), idx);
+        }
+        assert_eq!(cursor.prev(), None);
+    }
+
+    #[test]
+    fn should_seek_cursor() {
+        let buf = scattered();
+        for start in 0..=buf.len() {
+            for delta in -20..20isize {
+                let mut cursor = buf.cursor_at(start);
+                cursor.seek(delta);
+
+                let expected = (start as isize + delta).clamp(0, buf.len() as isize) as usize;
+                assert_eq!(cursor.idx(), expected, "{start} + {delta}");
+                assert_eq!(cursor.get(), buf.range(expected..).next());
+            }
+        }
+    }
+}
@@ -1,7 +1,11 @@
+use std::borrow::Cow;
+use std::collections::VecDeque;
 use std::iter::Rev;
-use std::ops::{Bound, Index, RangeBounds};
+use std::ops::{Index, RangeBounds};
 
-use crate::{Location, PtBuffer, WithBuffer};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{LineEnding, Location, PtBuffer, WithBuffer};
 
 pub struct Iter<'a, T: 'a> {
     table: &'a PtBuffer<'a, T>,
@@ -27,27 +31,81 @@ pub struct Range<'a, T: 'a> {
     to: usize,
 }
 
+/// Ranges between occurrences of a separator, see [`PtBuffer::split`].
+pub struct Split<'a, T: 'a> {
+    table: &'a PtBuffer<'a, T>,
+    iter: Iter<'a, T>,
+    sep: &'a T,
+    start: usize,
+    done: bool,
+}
+
+/// Ranges between occurrences of a separator from the end, see [`PtBuffer::rsplit`].
+pub struct RSplit<'a, T: 'a> {
+    table: &'a PtBuffer<'a, T>,
+    iter: RevIter<'a, T>,
+    sep: &'a T,
+    end: usize,
+    done: bool,
+}
+
+/// Overlapping runs of consecutive elements, see [`PtBuffer::windows`].
+pub struct Windows<'a, T: 'a> {
+    iter: Iter<'a, T>,
+    window: VecDeque<&'a T>,
+    size: usize,
+}
+
+/// Overlapping runs of `N` consecutive elements, see [`PtBuffer::array_windows`].
+pub struct ArrayWindows<'a, T: 'a, const N: usize> {
+    iter: Iter<'a, T>,
+    window: Option<[&'a T; N]>,
+}
+
+/// Grapheme clusters of a byte buffer, see [`PtBuffer::graphemes`].
+pub struct Graphemes<'a> {
+    iter: Iter<'a, u8>,
+    /// Bytes of the line being segmented
+    line: Vec<u8>,
+    pending: VecDeque<String>,
+}
+
+/// Lines without their line ending, see [`PtBuffer::line_chunks`].
+pub struct LineChunks<'a, T: 'a> {
+    table: &'a PtBuffer<'a, T>,
+    piece_idx: usize,
+    offset: usize,
+    /// Start of a line straddling pieces
+    pending: Vec<T>,
+    done: bool,
+}
+
 impl<'a, T: 'a> PtBuffer<'a, T> {
     pub fn iter(&'a self) -> Iter<'a, T> {
         self.make_iter(0)
     }
 
+    /// Iterate from the start of the piece `piece_idx` to the end of the buffer, without
+    /// resolving a document index first. Past the last piece the iterator is empty.
+    pub fn iter_from_piece(&'a self, piece_idx: usize) -> Iter<'a, T> {
+        let it = match self.pieces.get(piece_idx) {
+            Some(piece) => self.get_buffer(piece)[piece.start..piece.start + piece.length].iter(),
+            None => self.add_buffer[0..0].iter(),
+        };
+
+        Iter {
+            table: self,
+            piece_idx: piece_idx.min(self.pieces.len()),
+            it,
+        }
+    }
+
     pub fn rev_iter(&'a self) -> RevIter<'a, T> {
-        self.make_rev_iter(0..self.length - 1)
+        self.make_rev_iter(self.length)
     }
 
     pub fn range(&'a self, range: impl RangeBounds<usize>) -> Range<'a, T> {
-        let from = match range.start_bound() {
-            Bound::Included(x) => *x,
-            Bound::Excluded(x) => *x + 1,
-            Bound::Unbounded => 0,
-        };
-
-        let to = match range.end_bound() {
-            Bound::Included(x) => *x + 1,
-            Bound::Excluded(x) => *x,
-            Bound::Unbounded => self.length,
-        };
+        let (from, to) = self.bounds(range);
 
         Range {
             iter: self.make_iter(from),
@@ -56,49 +114,121 @@ impl<'a, T: 'a> PtBuffer<'a, T> {
         }
     }
 
-    pub fn rev_range(&'a self, range: impl RangeBounds<usize>) -> RevRange<'a, T> {
-        let from = match range.start_bound() {
-            Bound::Included(x) => *x,
-            Bound::Excluded(x) => *x + 1,
-            Bound::Unbounded => 0,
-        };
+    /// Append the content of `range` to `out`, letting callers reuse a scratch buffer.
+    pub fn extend_range_into(&'a self, range: impl RangeBounds<usize>, out: &mut Vec<T>)
+    where
+        T: Clone,
+    {
+        out.extend(self.range(range).cloned());
+    }
 
-        let to = match range.end_bound() {
-            Bound::Included(x) => *x + 1,
-            Bound::Excluded(x) => *x,
-            Bound::Unbounded => self.length,
-        };
+    /// Ranges separated by elements equal to `sep`, like `slice::split`: leading, trailing
+    /// and consecutive separators produce empty ranges.
+    pub fn split(&'a self, sep: &'a T) -> Split<'a, T>
+    where
+        T: PartialEq,
+    {
+        Split {
+            table: self,
+            iter: self.iter(),
+            sep,
+            start: 0,
+            done: false,
+        }
+    }
+
+    /// Same ranges as [`PtBuffer::split`], last one first.
+    pub fn rsplit(&'a self, sep: &'a T) -> RSplit<'a, T>
+    where
+        T: PartialEq,
+    {
+        RSplit {
+            table: self,
+            iter: self.rev_iter(),
+            sep,
+            end: self.length,
+            done: false,
+        }
+    }
+
+    /// `range` without its leading and trailing elements matching `f`, empty when they
+    /// all match.
+    pub fn trim_range_matches(
+        &'a self,
+        range: impl RangeBounds<usize>,
+        f: impl Fn(&T) -> bool,
+    ) -> Range<'a, T> {
+        let (from, to) = self.bounds(range);
+        let start = self
+            .range(from..to)
+            .position(|item| !f(item))
+            .map_or(to, |offset| from + offset);
+        let end = self
+            .rev_range(self.length - to..self.length - start)
+            .position(|item| !f(item))
+            .map_or(start, |offset| to - offset);
+
+        self.range(start..end)
+    }
+
+    /// The whole buffer without its leading and trailing elements matching `f`.
+    pub fn trim_matches(&'a self, f: impl Fn(&T) -> bool) -> Range<'a, T> {
+        self.trim_range_matches(.., f)
+    }
+
+    /// Every run of `size` consecutive elements, like `slice::windows` but across pieces.
+    /// Empty when `size` is zero or larger than the buffer.
+    pub fn windows(&'a self, size: usize) -> Windows<'a, T> {
+        Windows {
+            iter: self.iter(),
+            window: VecDeque::with_capacity(size),
+            size,
+        }
+    }
+
+    /// Same runs as [`PtBuffer::windows`] with a size known at compile time, shifting
+    /// references in place rather than allocating each window.
+    pub fn array_windows<const N: usize>(&'a self) -> ArrayWindows<'a, T, N> {
+        ArrayWindows {
+            iter: self.iter(),
+            window: None,
+        }
+    }
+
+    /// Iterate backward, `range` being expressed as a distance from the end of the buffer:
+    /// `rev_range(0..2)` yields the last two elements.
+    pub fn rev_range(&'a self, range: impl RangeBounds<usize>) -> RevRange<'a, T> {
+        let (from, to) = self.bounds(range);
 
         RevRange {
-            iter: self.make_rev_iter(from..to),
+            iter: self.make_rev_iter(self.length.saturating_sub(from)),
             idx: from,
             to,
         }
     }
 
-    fn make_rev_iter(&'a self, range: std::ops::Range<usize>) -> RevIter<'a, T> {
-        let (piece_idx, piece, range) = match self.index_to_piece_loc(range.end) {
-            Location::Head(piece_idx) => {
-                let piece = self.pieces[piece_idx];
-                (piece_idx, piece, piece.length..piece.start + piece.length)
-            }
+    // Build an iterator yielding `end - 1` first, down to the buffer start
+    fn make_rev_iter(&'a self, end: usize) -> RevIter<'a, T> {
+        let end = end.min(self.length);
+        if end == 0 {
+            return RevIter {
+                table: self,
+                piece_idx: 0,
+                it: self.add_buffer[0..0].iter().rev(),
+            };
+        }
+
+        let (piece_idx, norm_idx) = match self.index_to_piece_loc(end - 1) {
+            Location::Head(piece_idx) => (piece_idx, 0),
             Location::Middle(piece_idx, norm_idx) | Location::Tail(piece_idx, norm_idx) => {
-                let piece = self.pieces[piece_idx];
-                (
-                    piece_idx,
-                    piece,
-                    piece.length - norm_idx..piece.start + piece.length,
-                )
-            }
-            Location::Eof => {
-                let idx = self.pieces.len() - 1;
-                let piece = self.pieces[idx];
-                (idx, piece, 0..range.end - range.start)
+                (piece_idx, norm_idx)
             }
+            Location::Eof => unreachable!("{} is within bounds", end - 1),
         };
 
+        let piece = self.pieces[piece_idx];
         let buf = self.get_buffer(&piece);
-        let it = buf[range].iter().rev();
+        let it = buf[piece.start..piece.start + norm_idx + 1].iter().rev();
 
         RevIter {
             table: self,
@@ -148,6 +278,17 @@ impl<'a, T> Iterator for Range<'a, T> {
     }
 }
 
+impl<'a> Range<'a, String> {
+    /// Concatenate the elements left in the range into a single string.
+    pub fn collect_string(self) -> String {
+        let mut out = String::new();
+        for item in self {
+            out.push_str(item);
+        }
+        out
+    }
+}
+
 impl<'a, T> Iterator for RevRange<'a, T> {
     type Item = &'a T;
 
@@ -161,6 +302,206 @@ impl<'a, T> Iterator for RevRange<'a, T> {
     }
 }
 
+impl<'a> PtBuffer<'a, u8> {
+    /// `range` without its leading and trailing ASCII whitespace.
+    pub fn trim(&'a self, range: impl RangeBounds<usize>) -> Range<'a, u8> {
+        self.trim_range_matches(range, u8::is_ascii_whitespace)
+    }
+
+    /// Decode the bytes as UTF-8 and iterate over their grapheme clusters, one line at a
+    /// time since no cluster goes past a line feed. Invalid sequences come out as U+FFFD.
+    pub fn graphemes(&'a self) -> Graphemes<'a> {
+        Graphemes {
+            iter: self.iter(),
+            line: vec![],
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<'a> PtBuffer<'a, String> {
+    /// `range` without its leading and trailing whitespace elements.
+    pub fn trim(&'a self, range: impl RangeBounds<usize>) -> Range<'a, String> {
+        self.trim_range_matches(range, |item| item.chars().all(char::is_whitespace))
+    }
+}
+
+impl<'a, T: LineEnding + Clone + 'a> PtBuffer<'a, T> {
+    /// Iterate over lines, without their line ending. Lines lying within a single piece
+    /// are borrowed, only the ones straddling pieces are copied.
+    pub fn line_chunks(&'a self) -> LineChunks<'a, T> {
+        LineChunks {
+            table: self,
+            piece_idx: 0,
+            offset: 0,
+            pending: vec![],
+            done: false,
+        }
+    }
+}
+
+impl<'a, T: PartialEq> Iterator for Split<'a, T> {
+    type Item = Range<'a, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let start = self.start;
+        let mut end = start;
+        for item in self.iter.by_ref() {
+            if item == self.sep {
+                self.start = end + 1;
+                return Some(self.table.range(start..end));
+            }
+            end += 1;
+        }
+
+        self.done = true;
+        Some(self.table.range(start..end))
+    }
+}
+
+impl<'a, T: PartialEq> Iterator for RSplit<'a, T> {
+    type Item = Range<'a, T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let end = self.end;
+        let mut start = end;
+        for item in self.iter.by_ref() {
+            if item == self.sep {
+                self.end = start - 1;
+                return Some(self.table.range(start..end));
+            }
+            start -= 1;
+        }
+
+        self.done = true;
+        Some(self.table.range(start..end))
+    }
+}
+
+impl<'a, T> Iterator for Windows<'a, T> {
+    type Item = Vec<&'a T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.size == 0 {
+            return None;
+        }
+
+        while self.window.len() < self.size {
+            self.window.push_back(self.iter.next()?);
+        }
+
+        let window = self.window.iter().copied().collect();
+        self.window.pop_front();
+        Some(window)
+    }
+}
+
+impl<'a, T, const N: usize> Iterator for ArrayWindows<'a, T, N> {
+    type Item = [&'a T; N];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if N == 0 {
+            return None;
+        }
+
+        let window = match self.window {
+            Some(mut window) => {
+                window.rotate_left(1);
+                window[N - 1] = self.iter.next()?;
+                window
+            }
+            None => {
+                let first: Vec<&T> = self.iter.by_ref().take(N).collect();
+                first.try_into().ok()?
+            }
+        };
+
+        self.window = Some(window);
+        Some(window)
+    }
+}
+
+impl<'a> Iterator for Graphemes<'a> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(grapheme) = self.pending.pop_front() {
+            return Some(grapheme);
+        }
+
+        for &byte in self.iter.by_ref() {
+            self.line.push(byte);
+            if byte == b'\n' {
+                break;
+            }
+        }
+
+        let line = String::from_utf8_lossy(&self.line);
+        self.pending
+            .extend(line.graphemes(true).map(str::to_string));
+        self.line.clear();
+        self.pending.pop_front()
+    }
+}
+
+impl<'a, T: LineEnding + Clone> Iterator for LineChunks<'a, T> {
+    type Item = Cow<'a, [T]>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some(piece) = self.table.pieces.get(self.piece_idx) else {
+                // The last line, possibly empty after a trailing line ending
+                if self.done {
+                    return None;
+                }
+
+                self.done = true;
+                return Some(Cow::Owned(std::mem::take(&mut self.pending)));
+            };
+
+            let buf = self.table.get_buffer(piece);
+            let rest = &buf[piece.start + self.offset..piece.start + piece.length];
+            match rest.iter().position(T::is_line_ending) {
+                Some(len) => {
+                    self.offset += len + 1;
+                    if self.offset == piece.length {
+                        self.piece_idx += 1;
+                        self.offset = 0;
+                    }
+
+                    let line = &rest[..len];
+                    if self.pending.is_empty() {
+                        return Some(Cow::Borrowed(line));
+                    }
+
+                    self.pending.extend_from_slice(line);
+                    return Some(Cow::Owned(std::mem::take(&mut self.pending)));
+                }
+                None if self.pending.is_empty()
+                    && self.piece_idx + 1 == self.table.pieces.len() =>
+                {
+                    self.piece_idx += 1;
+                    self.done = true;
+                    return Some(Cow::Borrowed(rest));
+                }
+                None => {
+                    self.pending.extend_from_slice(rest);
+                    self.piece_idx += 1;
+                    self.offset = 0;
+                }
+            }
+        }
+    }
+}
+
 impl<'a, T> Iterator for Iter<'a, T> {
     type Item = &'a T;
 
@@ -209,7 +550,8 @@ impl<'a, T> Iterator for RevIter<'a, T> {
 impl<'a, T> Index<usize> for PtBuffer<'a, T> {
     type Output = T;
 
-    /// Note: Reading an index takes `O(p)` time, use iterators for fast sequential access.
+    /// Note: Reading an index takes up to `O(p)` time, only the pieces between it and the
+    /// previously read index being stepped over. Use iterators for fast sequential access.
     fn index(&self, idx: usize) -> &T {
         let (piece_idx, norm_idx) = match self.index_to_piece_loc(idx) {
             Location::Head(piece_idx) => (piece_idx, 0),
@@ -229,8 +571,34 @@ impl<'a, T> Index<usize> for PtBuffer<'a, T> {
 
 #[cfg(test)]
 mod test {
+    use std::borrow::Cow;
+
     use crate::PtBuffer;
 
+    #[test]
+    fn should_iter_graphemes_of_bytes() {
+        let src = "e\u{301}t\u{e9} 日本\r\n👋🏽!".as_bytes();
+        let mut buf = PtBuffer::new(src);
+        // Split a code point and a cluster over pieces
+        buf.remove(1);
+        buf.insert(1, src[1]);
+        let crab = "🦀".as_bytes();
+        buf.insert_slice(buf.len() - 1, crab);
+
+        let graphemes: Vec<String> = buf.graphemes().collect();
+        assert_eq!(
+            graphemes,
+            ["e\u{301}", "t", "\u{e9}", " ", "日", "本", "\r\n", "👋🏽", "🦀", "!"]
+        );
+
+        buf.remove(buf.len() - 1);
+        buf.remove(buf.len() - 1);
+        let graphemes: Vec<String> = buf.graphemes().collect();
+        assert_eq!(graphemes.last().map(String::as_str), Some("\u{fffd}"));
+        assert_eq!(PtBuffer::new(b"").graphemes().next(), None);
+        assert_eq!(PtBuffer::new(b"\n\n").graphemes().count(), 2);
+    }
+
     #[test]
     fn should_iter_piece_table() {
         let mut buf = PtBuffer::new(b"Hello ");
@@ -312,6 +680,168 @@ mod test {
         assert_eq!(c1, "ole Hbac");
     }
 
+    #[test]
+    fn should_iter_from_piece() {
+        let mut buf = PtBuffer::new(b"Hello world");
+        buf.insert(5, b',');
+        buf.insert(0, b'>');
+
+        let mut start = 0;
+        for piece_idx in 0..buf.pieces.len() {
+            let from_piece: Vec<u8> = buf.iter_from_piece(piece_idx).copied().collect();
+            let range: Vec<u8> = buf.range(start..).copied().collect();
+            assert_eq!(from_piece, range);
+            start += buf.pieces[piece_idx].length;
+        }
+
+        assert_eq!(buf.iter_from_piece(buf.pieces.len()).count(), 0);
+        assert_eq!(buf.iter_from_piece(usize::MAX).count(), 0);
+    }
+
+    #[test]
+    fn should_borrow_contiguous_lines() {
+        let src: Vec<String> = "ab\ncd\nef".chars().map(String::from).collect();
+        let mut buf = PtBuffer::new(&src);
+        let lines = |buf: &PtBuffer<String>| {
+            buf.line_chunks()
+                .map(|line| {
+                    let borrowed = matches!(line, Cow::Borrowed(_));
+                    (line.concat(), borrowed)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let expected = [("ab", true), ("cd", true), ("ef", true)];
+        assert_eq!(lines(&buf), expected.map(|(l, b)| (l.to_string(), b)));
+
+        // Split the second line across three pieces and end with a line ending
+        buf.insert(3, "x".to_string());
+        buf.push("\n".to_string());
+        let expected = [("ab", true), ("xcd", false), ("ef", false), ("", false)];
+        assert_eq!(lines(&buf), expected.map(|(l, b)| (l.to_string(), b)));
+
+        let empty: PtBuffer<String> = PtBuffer::new(&[]);
+        assert_eq!(empty.line_chunks().count(), 1);
+
+        let mut buf = PtBuffer::new(b"ab\ncd");
+        buf.insert(4, b'x');
+        let lines: Vec<Cow<[u8]>> = buf.line_chunks().collect();
+        assert_eq!(lines, [&b"ab"[..], b"cxd"]);
+        assert!(matches!(lines[0], Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn should_collect_a_range_into_a_string() {
+        let src: Vec<String> = ["h", "é", "l", "👋🏽", "\r\n", "o"]
+            .map(String::from)
+            .to_vec();
+        let mut buf = PtBuffer::new(&src);
+        buf.insert(3, "!".to_string());
+
+        assert_eq!(buf.range(1..6).collect_string(), "él!👋🏽\r\n");
+        assert_eq!(buf.range(..).collect_string(), "hél!👋🏽\r\no");
+        assert_eq!(buf.range(2..2).collect_string(), "");
+    }
+
+    #[test]
+    fn should_split_like_slices() {
+        let mut buf = PtBuffer::new(b"a,b,c");
+        buf.insert(3, b',');
+        let split = |buf: &PtBuffer<u8>| -> Vec<String> {
+            buf.split(&b',')
+                .map(|range| range.map(|b| *b as char).collect())
+                .collect()
+        };
+        let rsplit = |buf: &PtBuffer<u8>| -> Vec<String> {
+            buf.rsplit(&b',')
+                .map(|range| range.map(|b| *b as char).collect())
+                .collect()
+        };
+
+        assert_eq!(split(&buf), ["a", "b", "", "c"]);
+        assert_eq!(rsplit(&buf), ["c", "", "b", "a"]);
+
+        for src in [&b",a,"[..], b",", b"", b"abc"] {
+            let buf = PtBuffer::new(src);
+            let expected: Vec<String> = src
+                .split(|b| *b == b',')
+                .map(|part| String::from_utf8_lossy(part).into_owned())
+                .collect();
+            assert_eq!(split(&buf), expected);
+            assert_eq!(rsplit(&buf), expected.into_iter().rev().collect::<Vec<_>>());
+        }
+    }
+
+    #[test]
+    fn should_trim_both_ends_of_a_range() {
+        let mut buf = PtBuffer::new(b" \n ab c\n ");
+        buf.insert(0, b'\t');
+        buf.insert(7, b' ');
+        let trim = |range: std::ops::Range<usize>| -> String {
+            buf.trim(range).map(|b| *b as char).collect()
+        };
+
+        assert_eq!(trim(0..buf.len()), "ab  c");
+        assert_eq!(trim(5..buf.len()), "b  c");
+        assert_eq!(trim(0..6), "ab");
+        // Nothing left when everything is whitespace
+        assert_eq!(trim(0..4), "");
+        assert_eq!(trim(6..8), "");
+        assert_eq!(trim(3..3), "");
+
+        let only_c: String = buf
+            .trim_matches(|b| *b != b'c')
+            .map(|b| *b as char)
+            .collect();
+        assert_eq!(only_c, "c");
+
+        let src: Vec<String> = ["\n", " ", "é", "\u{3000}", "x", "\r\n"]
+            .map(String::from)
+            .to_vec();
+        let buf = PtBuffer::new(&src);
+        assert_eq!(buf.trim(..).collect_string(), "é\u{3000}x");
+        assert_eq!(buf.trim(..2).count(), 0);
+    }
+
+    #[test]
+    fn should_slide_windows_across_pieces() {
+        let mut buf = PtBuffer::new(b"acef");
+        buf.insert(1, b'b');
+        buf.insert(3, b'd');
+        buf.push(b'g');
+        let flat: Vec<u8> = buf.iter().copied().collect();
+        assert_eq!(flat, b"abcdefg");
+
+        for size in 1..=8 {
+            let windows: Vec<Vec<u8>> = buf
+                .windows(size)
+                .map(|window| window.into_iter().copied().collect())
+                .collect();
+            let expected: Vec<Vec<u8>> = flat.windows(size).map(<[u8]>::to_vec).collect();
+            assert_eq!(windows, expected, "size {size}");
+        }
+        assert_eq!(buf.windows(0).count(), 0);
+
+        let windows: Vec<[u8; 3]> = buf
+            .array_windows::<3>()
+            .map(|window| window.map(|b| *b))
+            .collect();
+        let expected: Vec<[u8; 3]> = flat.windows(3).map(|w| w.try_into().unwrap()).collect();
+        assert_eq!(windows, expected);
+        assert_eq!(buf.array_windows::<8>().count(), 0);
+        assert_eq!(buf.array_windows::<0>().count(), 0);
+        assert_eq!(PtBuffer::new(b"").array_windows::<1>().count(), 0);
+    }
+
+    #[test]
+    fn should_rev_iter_single_piece() {
+        let buf = PtBuffer::new(b"abcd");
+        let b1: Vec<u8> = buf.rev_iter().copied().collect();
+        let c1 = String::from_utf8_lossy(&b1);
+
+        assert_eq!(c1, "dcba");
+    }
+
     #[test]
     fn should_rev_iter_range_pt_rev() {
         let buf = PtBuffer::new(b"abcd");
@@ -1,13 +1,71 @@
+use std::cell::Cell;
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::ops::{Bound, RangeBounds};
+
+use unicode_segmentation::UnicodeSegmentation;
+
+pub mod cursor;
 pub mod iter;
+#[cfg(feature = "regex")]
+pub mod search;
+pub mod text_object;
+
+/// Split `s` into grapheme clusters, the elements a `PtBuffer<String>` holds text as.
+pub fn graphemes(s: &str) -> Vec<String> {
+    s.graphemes(true).map(str::to_string).collect()
+}
 
+/// Elements telling where lines end, for line and column lookups over any buffer.
+pub trait LineEnding {
+    fn is_line_ending(&self) -> bool;
+}
+
+impl LineEnding for String {
+    fn is_line_ending(&self) -> bool {
+        self == "\n"
+    }
+}
+
+impl LineEnding for u8 {
+    fn is_line_ending(&self) -> bool {
+        *self == b'\n'
+    }
+}
+
+/// Index of every line ending of `src`, to find the line starts of a buffer over it with
+/// [`PtBuffer::line_starts_from`]. It only needs the original buffer, so it can run
+/// while the buffer is being edited elsewhere.
+pub fn line_endings<T: LineEnding>(src: &[T]) -> Vec<usize> {
+    src.iter()
+        .enumerate()
+        .filter(|(_, item)| item.is_line_ending())
+        .map(|(idx, _)| idx)
+        .collect()
+}
+
+/// A piece table over the elements of a borrowed original buffer.
+///
+/// Index lookups remember the piece they ended on in a [`Cell`], which makes a buffer `Send`
+/// but not `Sync`. Reading it from several threads at once needs a lock around it.
 #[derive(Debug)]
 pub struct PtBuffer<'a, T: 'a> {
     file_buffer: &'a [T],
     add_buffer: Vec<T>,
     pieces: Vec<Piece>,
     length: usize,
+    /// Index right after the last edit, where typing continues: `at + 1` after inserting
+    /// at `at`, `at` after removing at `at`. `reusable_edit` is only valid relative to it.
     last_edit_idx: usize,
     reusable_edit: ReusableEdit,
+    /// Bumped on every change to the content
+    revision: u64,
+    /// Edits not taken yet, only recorded once [`PtBuffer::track_edits`] is called
+    pending_edits: Option<Vec<Edit>>,
+    /// Add buffer length when the last checkpoint was taken. Elements before it may be
+    /// pointed at by a checkpoint, the ones after are only in the current pieces.
+    pinned_add_len: usize,
+    index_cache: Cell<IndexCache>,
 }
 
 pub type PieceIdx = usize;
@@ -35,14 +93,173 @@ struct Piece {
     length: usize,
 }
 
+#[cfg(test)]
+thread_local! {
+    /// Pieces stepped over by index lookups on this thread
+    static SCANNED_PIECES: Cell<usize> = const { Cell::new(0) };
+}
+
+fn count_scanned_piece() {
+    #[cfg(test)]
+    SCANNED_PIECES.with(|scanned| scanned.set(scanned.get() + 1));
+}
+
+/// Piece holding the last looked up index, the next lookup walking the pieces from there
+/// rather than from the first one. Only valid at the revision it was taken.
+///
+/// The default, the first piece, is a valid start for any buffer.
+#[derive(Debug, Default, Copy, Clone)]
+struct IndexCache {
+    revision: u64,
+    piece_idx: PieceIdx,
+    /// Index of the first element of the piece
+    piece_start: usize,
+}
+
+/// Cached state letting the next edit skip the `O(p)` piece lookup.
 #[derive(Debug, Copy, Clone)]
 enum ReusableEdit {
-    Insert(usize, bool),
+    /// Add piece whose last element sits right before `last_edit_idx`.
+    /// It can grow in place as long as it also ends the add buffer.
+    Insert(PieceIdx),
+    /// Location of the element right before `last_edit_idx`, the next one to go on backspace.
     Remove(Location),
     None,
 }
 
-impl<'a> PtBuffer<'a, String> {
+/// The content of a buffer at some point, as taken by [`PtBuffer::checkpoint`].
+///
+/// Only the piece list is copied: the add buffer is never rewritten below the length it
+/// had when the checkpoint was taken, so the elements the pieces point at are still there
+/// when restoring to the same buffer.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    pieces: Vec<Piece>,
+    length: usize,
+    add_len: usize,
+    revision: u64,
+}
+
+impl Checkpoint {
+    /// Revision of the buffer when the checkpoint was taken.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+}
+
+/// A change made to a buffer, used to keep indexes tracked outside of it in sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edit {
+    /// `len` elements inserted at `at`.
+    Insert { at: usize, len: usize },
+    /// Elements in `start..end` removed.
+    Remove { start: usize, end: usize },
+}
+
+impl Edit {
+    /// Where the index `idx` lands once the edit is applied: an insertion at `at` pushes
+    /// indexes from `at` onward, a removal pulls back the ones after the removed range
+    /// and collapses the ones inside it to its start.
+    pub fn adjust(&self, idx: usize) -> usize {
+        match *self {
+            Edit::Insert { at, len } if idx >= at => idx + len,
+            Edit::Remove { start, end } if idx >= end => idx - (end - start),
+            Edit::Remove { start, .. } if idx > start => start,
+            _ => idx,
+        }
+    }
+}
+
+/// Counts over a buffer of text, as returned by [`PtBuffer::stats`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DocStats {
+    /// Line endings plus one, like [`PtBuffer::line_count`]
+    pub lines: usize,
+    /// Runs of non whitespace elements
+    pub words: usize,
+    /// Unicode scalar values
+    pub chars: usize,
+    /// UTF-8 length
+    pub bytes: usize,
+}
+
+impl DocStats {
+    fn count<'a>(items: impl IntoIterator<Item = &'a String>) -> Self {
+        let mut stats = DocStats {
+            lines: 1,
+            ..DocStats::default()
+        };
+        let mut in_word = false;
+        for item in items {
+            let whitespace = item.chars().all(char::is_whitespace);
+            if !whitespace && !in_word {
+                stats.words += 1;
+            }
+            in_word = !whitespace;
+
+            if item == "\n" {
+                stats.lines += 1;
+            }
+            stats.chars += item.chars().count();
+            stats.bytes += item.len();
+        }
+
+        stats
+    }
+}
+
+/// A broken piece table invariant, as reported by [`PtBuffer::validate`].
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum InvariantError {
+    /// The piece lengths don't add up to the buffer length.
+    LengthMismatch { pieces: usize, length: usize },
+    /// A piece spans past the end of the buffer it points into.
+    PieceOutOfBounds {
+        piece_idx: PieceIdx,
+        end: usize,
+        buffer_len: usize,
+    },
+    /// A zero-length piece was left behind by an edit.
+    EmptyPiece { piece_idx: PieceIdx },
+    /// The cached edit refers to a piece that doesn't exist or can't be reused.
+    StaleReusableEdit { piece_idx: PieceIdx },
+    /// The last edit index is past the end of the buffer.
+    LastEditOutOfBounds { last_edit_idx: usize, length: usize },
+}
+
+impl fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InvariantError::LengthMismatch { pieces, length } => write!(
+                f,
+                "pieces hold {pieces} elements but the buffer length is {length}"
+            ),
+            InvariantError::PieceOutOfBounds {
+                piece_idx,
+                end,
+                buffer_len,
+            } => write!(
+                f,
+                "piece {piece_idx} ends at {end}, past its buffer length {buffer_len}"
+            ),
+            InvariantError::EmptyPiece { piece_idx } => write!(f, "piece {piece_idx} is empty"),
+            InvariantError::StaleReusableEdit { piece_idx } => {
+                write!(f, "reusable edit points to invalid piece {piece_idx}")
+            }
+            InvariantError::LastEditOutOfBounds {
+                last_edit_idx,
+                length,
+            } => write!(
+                f,
+                "last edit index {last_edit_idx} is past the buffer length {length}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InvariantError {}
+
+impl<'a, T: LineEnding + 'a> PtBuffer<'a, T> {
     pub fn line_column_to_idx(&self, column: usize, line: usize) -> usize {
         let mut l_count = 0;
         let mut c_count = 0;
@@ -56,35 +273,468 @@ impl<'a> PtBuffer<'a, String> {
                 c_count += 1;
             }
 
-            if *c == "\n" && l_count < line {
+            if c.is_line_ending() && l_count < line {
                 l_count += 1;
             }
         }
 
+        // End of the document
+        if column == c_count && line == l_count {
+            return self.length;
+        }
+
         panic!("x: {c_count}, y: {l_count}, (x:{column}, y:{line}) out of bound");
     }
+
+    /// Insert `item` at `column` of `line`, returning the index right after it.
+    pub fn insert_at(&mut self, column: usize, line: usize, item: T) -> usize {
+        let idx = self.line_column_to_idx(column, line);
+        self.insert(idx, item);
+        idx + 1
+    }
+
+    /// Start index of every line, `original_endings` being the [`line_endings`] of the
+    /// original buffer. Only the added elements are scanned.
+    pub fn line_starts_from(&self, original_endings: &[usize]) -> Vec<usize> {
+        let mut starts = vec![0];
+        let mut offset = 0;
+        for piece in &self.pieces {
+            let end = piece.start + piece.length;
+            match piece.with_buffer {
+                WithBuffer::Original => {
+                    let from = original_endings.partition_point(|idx| *idx < piece.start);
+                    let to = original_endings.partition_point(|idx| *idx < end);
+                    let ends = &original_endings[from..to];
+                    starts.extend(ends.iter().map(|idx| offset + idx - piece.start + 1));
+                }
+                WithBuffer::Add => {
+                    let slice = &self.add_buffer[piece.start..end];
+                    let ends = slice.iter().enumerate().filter(|(_, c)| c.is_line_ending());
+                    starts.extend(ends.map(|(idx, _)| offset + idx + 1));
+                }
+            }
+            offset += piece.length;
+        }
+
+        starts
+    }
+}
+
+impl<'a> PtBuffer<'a, String> {
+    /// Index of `column` in `line`, the end of the line included, or `None` when the
+    /// coordinates are past the line end or the last line.
+    pub fn try_line_column_to_idx(&self, column: usize, line: usize) -> Option<usize> {
+        self.line_range(line)
+            .filter(|range| column <= range.len())
+            .map(|range| range.start + column)
+    }
+
+    /// Element at `column` of `line`, the line ending included, or `None` out of range.
+    pub fn at_line_column(&'a self, column: usize, line: usize) -> Option<&'a String> {
+        self.try_line_column_to_idx(column, line)
+            .and_then(|idx| self.get(idx))
+    }
+
+    /// Insert the graphemes of `s` at `column` of `line` as a single piece,
+    /// returning the index right after the inserted text.
+    pub fn insert_str_at_line_column(&mut self, column: usize, line: usize, s: &str) -> usize {
+        let idx = self.line_column_to_idx(column, line);
+        let items = graphemes(s);
+        self.insert_slice(idx, &items);
+        idx + items.len()
+    }
+
+    /// Append the graphemes of `s` at the end, growing the last piece when it already
+    /// ends the add buffer instead of adding one piece per grapheme.
+    pub fn append_str(&mut self, s: &str) {
+        let reuse = self
+            .pieces
+            .len()
+            .checked_sub(1)
+            .is_some_and(|last| self.ends_add_buffer(last));
+
+        let start = self.add_buffer.len();
+        self.add_buffer
+            .extend(s.graphemes(true).map(str::to_string));
+        let len = self.add_buffer.len() - start;
+        if len == 0 {
+            return;
+        }
+
+        if reuse {
+            self.pieces.last_mut().unwrap().length += len;
+        } else {
+            self.pieces.push(Piece {
+                start,
+                length: len,
+                with_buffer: WithBuffer::Add,
+            });
+        }
+
+        self.reusable_edit = ReusableEdit::Insert(self.pieces.len() - 1);
+        self.record(Edit::Insert {
+            at: self.length,
+            len,
+        });
+        self.length += len;
+        self.last_edit_idx = self.length;
+        self.revision += 1;
+        self.debug_validate();
+    }
+
+    /// Append the text of `range` to `out`, letting callers reuse a scratch string.
+    pub fn extend_str_into(&self, range: impl RangeBounds<usize>, out: &mut String) {
+        for g in self.range(range) {
+            out.push_str(g);
+        }
+    }
+
+    /// Whether the elements joined together are `other`, stopping at the first mismatch.
+    pub fn eq_str(&self, other: &str) -> bool {
+        let mut rest = other;
+        for item in self.iter() {
+            match rest.strip_prefix(item.as_str()) {
+                Some(tail) => rest = tail,
+                None => return false,
+            }
+        }
+
+        rest.is_empty()
+    }
+
+    /// Byte offset of element `elem` in the text, the text length in bytes past the end.
+    pub fn element_to_byte(&self, elem: usize) -> usize {
+        self.range_slices(..elem.min(self.length))
+            .flatten()
+            .map(String::len)
+            .sum()
+    }
+
+    /// Element holding the byte at offset `byte`, which starts before it when `byte` lands
+    /// inside a grapheme. The length of the buffer past the end of the text.
+    pub fn byte_to_element(&self, byte: usize) -> usize {
+        let mut end = 0;
+        for (idx, item) in self.iter().enumerate() {
+            end += item.len();
+            if end > byte {
+                return idx;
+            }
+        }
+
+        self.length
+    }
+
+    /// Line, word, char and byte counts, in a single pass.
+    pub fn stats(&self) -> DocStats {
+        DocStats::count(self.iter())
+    }
+
+    /// Same counts as [`PtBuffer::stats`] over `range` only.
+    pub fn range_stats(&self, range: impl RangeBounds<usize>) -> DocStats {
+        DocStats::count(self.range(range))
+    }
+
+    /// Line endings within `range`, only walking the pieces it touches.
+    pub fn count_lines_in_range(&self, range: impl RangeBounds<usize>) -> usize {
+        self.range_slices(range)
+            .map(|slice| slice.iter().filter(|c| *c == "\n").count())
+            .sum()
+    }
+
+    /// Start index of every line, in one pass over the piece slices. Lookups into it
+    /// don't need to rescan the buffer like [`PtBuffer::line_column_to_idx`] does.
+    pub fn line_starts(&self) -> Vec<usize> {
+        let mut starts = vec![0];
+        let mut offset = 0;
+        for slice in self.range_slices(..) {
+            let ends = slice.iter().enumerate().filter(|(_, c)| *c == "\n");
+            starts.extend(ends.map(|(idx, _)| offset + idx + 1));
+            offset += slice.len();
+        }
+
+        starts
+    }
+
+    /// Number of lines, a trailing line ending starting a last empty line.
+    pub fn line_count(&self) -> usize {
+        self.iter().filter(|c| *c == "\n").count() + 1
+    }
+
+    /// Element range of `line`, excluding its line ending, or `None` past the last line.
+    pub fn line_range(&self, line: usize) -> Option<std::ops::Range<usize>> {
+        let mut l_count = 0;
+        let mut start = 0;
+
+        for (idx, c) in self.iter().enumerate() {
+            if *c == "\n" {
+                if l_count == line {
+                    return Some(start..idx);
+                }
+
+                l_count += 1;
+                start = idx + 1;
+            }
+        }
+
+        (l_count == line).then_some(start..self.length)
+    }
+
+    /// Whether `line` is empty or only holds spaces and tabs. `false` past the last line.
+    pub fn is_blank_line(&self, line: usize) -> bool {
+        self.line_range(line)
+            .is_some_and(|range| self.range(range).all(|c| c == " " || c == "\t"))
+    }
+
+    /// First blank line after `from_line`, if any.
+    pub fn next_blank_line(&self, from_line: usize) -> Option<usize> {
+        self.blank_lines().find(|&line| line > from_line)
+    }
+
+    /// Last blank line before `from_line`, if any.
+    pub fn prev_blank_line(&self, from_line: usize) -> Option<usize> {
+        self.blank_lines()
+            .take_while(|&line| line < from_line)
+            .last()
+    }
+
+    /// Blank lines in document order, found in a single pass over the elements.
+    fn blank_lines(&self) -> impl Iterator<Item = usize> + '_ {
+        let mut blank = true;
+        // The last line has no line ending, one more closes it
+        self.iter()
+            .map(String::as_str)
+            .chain(std::iter::once("\n"))
+            .filter_map(move |c| match c {
+                "\n" => Some(std::mem::replace(&mut blank, true)),
+                " " | "\t" => None,
+                _ => {
+                    blank = false;
+                    None
+                }
+            })
+            .enumerate()
+            .filter_map(|(line, blank)| blank.then_some(line))
+    }
+
+    /// Start of the line holding `idx`: right after the previous line ending, or 0.
+    /// A line ending belongs to the line it ends.
+    pub fn line_start(&self, idx: usize) -> usize {
+        let idx = idx.min(self.length);
+        self.rev_range(self.length - idx..self.length)
+            .position(|c| c == "\n")
+            .map_or(0, |offset| idx - offset)
+    }
+
+    /// End of the line holding `idx`: the index of its line ending, or the buffer length.
+    pub fn line_end(&self, idx: usize) -> usize {
+        let idx = idx.min(self.length);
+        self.range(idx..)
+            .position(|c| c == "\n")
+            .map_or(self.length, |offset| idx + offset)
+    }
+
+    /// Strip spaces and tabs preceding each line ending and at the end of the document.
+    /// Returns the removed ranges, in document order and expressed in pre-edit indices.
+    pub fn trim_trailing_whitespace(&mut self) -> Vec<std::ops::Range<usize>> {
+        let removed = self.trailing_whitespace();
+        // Remove from the end so earlier ranges stay valid
+        for range in removed.iter().rev() {
+            self.remove_range(range.clone());
+        }
+
+        removed
+    }
+
+    /// Runs of spaces and tabs preceding each line ending and at the end of the document,
+    /// in document order.
+    pub fn trailing_whitespace(&self) -> Vec<std::ops::Range<usize>> {
+        let mut removed = vec![];
+        let mut run_start = None;
+
+        for (idx, c) in self.iter().enumerate() {
+            match c.as_str() {
+                " " | "\t" => {
+                    run_start.get_or_insert(idx);
+                }
+                "\n" => {
+                    if let Some(start) = run_start.take() {
+                        removed.push(start..idx);
+                    }
+                }
+                _ => run_start = None,
+            }
+        }
+
+        if let Some(start) = run_start {
+            removed.push(start..self.length);
+        }
+
+        removed
+    }
+
+    /// Insert a line ending followed by `indent` at `at` as a single piece, returning the
+    /// index right after the indent.
+    pub fn insert_newline(&mut self, at: usize, indent: &[String]) -> usize {
+        let newline = std::iter::once("\n".to_string());
+        self.insert_iter(at, newline.chain(indent.iter().cloned()));
+        at + 1 + indent.len()
+    }
+
+    /// Make sure a non-empty document ends with exactly one line ending.
+    pub fn ensure_final_newline(&mut self) {
+        if self.is_empty() {
+            return;
+        }
+
+        match self.trailing_line_endings() {
+            0 => self.push("\n".to_string()),
+            1 => {}
+            n => self.remove_range(self.length - n + 1..),
+        }
+    }
+
+    /// Number of line endings the document ends with.
+    pub fn trailing_line_endings(&self) -> usize {
+        self.rev_iter().take_while(|c| *c == "\n").count()
+    }
+}
+
+impl<'a> PtBuffer<'a, u8> {
+    /// Insert a line ending followed by `indent` at `at` as a single piece, returning the
+    /// index right after the indent.
+    pub fn insert_newline(&mut self, at: usize, indent: &[u8]) -> usize {
+        self.insert_iter(at, std::iter::once(b'\n').chain(indent.iter().copied()));
+        at + 1 + indent.len()
+    }
+
+    /// Line endings within `range`, only walking the pieces it touches.
+    pub fn count_lines_in_range(&self, range: impl RangeBounds<usize>) -> usize {
+        self.range_slices(range)
+            .map(|slice| slice.iter().filter(|b| **b == b'\n').count())
+            .sum()
+    }
+
+    /// Whether the content is `other`, comparing piece by piece without collecting it.
+    pub fn eq_bytes(&self, other: &[u8]) -> bool {
+        if self.length != other.len() {
+            return false;
+        }
+
+        let mut rest = other;
+        self.pieces.iter().all(|piece| {
+            let (head, tail) = rest.split_at(piece.length);
+            rest = tail;
+            head == &self.get_buffer(piece)[piece.start..piece.start + piece.length]
+        })
+    }
+
+    pub fn is_valid_utf8(&self) -> bool {
+        self.find_invalid_utf8().is_none()
+    }
+
+    /// Offset of the first byte not part of a valid UTF-8 sequence, sequences spanning
+    /// pieces being validated as a whole.
+    pub fn find_invalid_utf8(&self) -> Option<usize> {
+        // Start of a sequence cut by the end of a piece
+        let mut pending: Vec<u8> = Vec::with_capacity(4);
+        let mut offset = 0;
+
+        for piece in &self.pieces {
+            let mut chunk = &self.get_buffer(piece)[piece.start..piece.start + piece.length];
+            while !pending.is_empty() && !chunk.is_empty() {
+                pending.push(chunk[0]);
+                chunk = &chunk[1..];
+                match std::str::from_utf8(&pending) {
+                    Ok(_) => {
+                        offset += pending.len();
+                        pending.clear();
+                    }
+                    Err(err) if err.error_len().is_some() => return Some(offset),
+                    Err(_) => {}
+                }
+            }
+
+            match std::str::from_utf8(chunk) {
+                Ok(_) => offset += chunk.len(),
+                Err(err) if err.error_len().is_some() => return Some(offset + err.valid_up_to()),
+                Err(err) => {
+                    offset += err.valid_up_to();
+                    pending.extend_from_slice(&chunk[err.valid_up_to()..]);
+                }
+            }
+        }
+
+        // Truncated sequence at the end of the buffer
+        (!pending.is_empty()).then_some(offset)
+    }
+
+    /// Replace every invalid sequence with `U+FFFD`, returning how many were replaced.
+    pub fn repair_utf8(&mut self) -> usize {
+        let replacement = char::REPLACEMENT_CHARACTER.to_string();
+        let mut repaired = 0;
+
+        while let Some(at) = self.find_invalid_utf8() {
+            let tail: Vec<u8> = self.range(at..(at + 4).min(self.length)).copied().collect();
+            let len = match std::str::from_utf8(&tail) {
+                Err(err) => err.error_len().unwrap_or(tail.len()),
+                Ok(_) => unreachable!("invalid sequence at {at}"),
+            };
+
+            self.remove_range(at..at + len);
+            self.insert_slice(at, replacement.as_bytes());
+            repaired += 1;
+        }
+
+        repaired
+    }
 }
 
 impl<'a, T: 'a> PtBuffer<'a, T> {
     pub fn new(src: &'a [T]) -> Self {
-        let piece = Piece {
-            with_buffer: WithBuffer::Original,
-            start: 0,
-            length: src.len(),
+        let pieces = if src.is_empty() {
+            vec![]
+        } else {
+            vec![Piece {
+                with_buffer: WithBuffer::Original,
+                start: 0,
+                length: src.len(),
+            }]
         };
 
         Self {
             file_buffer: src,
             add_buffer: vec![],
-            pieces: vec![piece],
+            pieces,
             length: src.len(),
             last_edit_idx: 0,
+            revision: 0,
             reusable_edit: ReusableEdit::None,
+            pending_edits: None,
+            pinned_add_len: 0,
+            index_cache: Cell::default(),
         }
     }
 
+    /// A buffer over `src` with room for `cap` inserted elements before the add buffer
+    /// reallocates.
+    pub fn with_add_capacity(src: &'a [T], cap: usize) -> Self {
+        let mut buffer = Self::new(src);
+        buffer.reserve_add(cap);
+        buffer
+    }
+
+    /// Make room for at least `additional` more inserted elements.
+    pub fn reserve_add(&mut self, additional: usize) {
+        self.add_buffer.reserve(additional);
+    }
+
+    /// Make room for at least `additional` more pieces, about two per scattered edit.
+    pub fn reserve_pieces(&mut self, additional: usize) {
+        self.pieces.reserve(additional);
+    }
+
     pub fn push(&mut self, value: T) {
-        let reuse = self.pieces.last().map_or(false, |last| {
+        let reuse = self.pieces.last().is_some_and(|last| {
             last.with_buffer == WithBuffer::Add && last.start + last.length == self.add_buffer.len()
         });
 
@@ -92,199 +742,718 @@ impl<'a, T: 'a> PtBuffer<'a, T> {
 
         if reuse {
             self.pieces.last_mut().unwrap().length += 1;
-            self.reusable_edit = ReusableEdit::Insert(self.pieces.len() - 1, false);
         } else {
             self.pieces.push(Piece {
                 start: self.add_buffer.len() - 1,
                 length: 1,
                 with_buffer: WithBuffer::Add,
             });
-            self.reusable_edit = ReusableEdit::Insert(self.pieces.len() - 1, true);
         }
 
-        self.last_edit_idx = self.length;
+        self.reusable_edit = ReusableEdit::Insert(self.pieces.len() - 1);
+        self.record(Edit::Insert {
+            at: self.length,
+            len: 1,
+        });
         self.length += 1;
+        self.last_edit_idx = self.length;
+        self.revision += 1;
+        self.debug_validate();
     }
 
     pub fn insert(&mut self, at: usize, item: T) {
         debug_assert!(at <= self.length);
         match self.reusable_edit {
-            ReusableEdit::Insert(piece_idx, _) if at == self.last_edit_idx + 1 => {
-                let piece = &mut self.pieces[piece_idx];
+            ReusableEdit::Insert(piece_idx)
+                if at == self.last_edit_idx && self.ends_add_buffer(piece_idx) =>
+            {
                 self.add_buffer.push(item);
-                piece.length += 1;
+                self.pieces[piece_idx].length += 1;
             }
             _ => self.raw_insert(at, item),
         }
 
-        self.last_edit_idx = at;
+        self.record(Edit::Insert { at, len: 1 });
+        self.last_edit_idx = at + 1;
         self.length += 1;
+        self.revision += 1;
+        self.debug_validate();
     }
 
     pub fn remove(&mut self, at: usize) {
         debug_assert!(at < self.length);
 
-        let piece_to_remove = match self.reusable_edit {
-            ReusableEdit::Insert(piece_idx, head) if at + 1 == self.last_edit_idx && head => {
-                let piece = &mut self.pieces[piece_idx];
-                piece.length -= 1;
-                (piece.length == 0).then_some(piece_idx)
-            }
-            ReusableEdit::Remove(loc) if at == self.last_edit_idx => self.raw_remove(loc),
-            _ => {
-                let loc = self.index_to_piece_loc(at);
-                self.raw_remove(loc)
+        let loc = match self.reusable_edit {
+            // Backspace right after typing: drop the last element of the insert piece
+            ReusableEdit::Insert(piece_idx) if at + 1 == self.last_edit_idx => {
+                self.last_element_loc(piece_idx)
             }
+            ReusableEdit::Remove(loc) if at + 1 == self.last_edit_idx => loc,
+            _ => self.index_to_piece_loc(at),
         };
 
-        if let Some(piece_idx) = piece_to_remove {
-            self.pieces.remove(piece_idx);
+        self.raw_remove(loc);
+        self.record(Edit::Remove {
+            start: at,
+            end: at + 1,
+        });
+        self.last_edit_idx = at;
+        self.length -= 1;
+        self.revision += 1;
+        self.debug_validate();
+    }
 
-            if piece_idx > 0 {
-                let idx = piece_idx - 1;
-                let len = self.pieces[idx].length;
-                let loc = if len == 1 {
-                    Location::Head(idx)
-                } else {
-                    Location::Tail(idx, len - 1)
-                };
+    /// Insert `items` at once as a single add piece.
+    pub fn insert_slice(&mut self, at: usize, items: &[T])
+    where
+        T: Clone,
+    {
+        self.insert_iter(at, items.iter().cloned());
+    }
+
+    /// Insert every item yielded by `items` as a single add piece, without collecting
+    /// them first.
+    pub fn insert_iter(&mut self, at: usize, items: impl IntoIterator<Item = T>) {
+        debug_assert!(at <= self.length);
+        let start = self.add_buffer.len();
+        self.add_buffer.extend(items);
+        let len = self.add_buffer.len() - start;
+        if len == 0 {
+            return;
+        }
+
+        let piece_idx = self.split_piece_at(at);
+        self.pieces.insert(
+            piece_idx,
+            Piece {
+                with_buffer: WithBuffer::Add,
+                start,
+                length: len,
+            },
+        );
+        let piece_idx = self.merge_with_previous(piece_idx);
+
+        self.record(Edit::Insert { at, len });
+        self.length += len;
+        self.last_edit_idx = at + len;
+        self.reusable_edit = ReusableEdit::Insert(piece_idx);
+        self.revision += 1;
+        self.debug_validate();
+    }
+
+    /// Remove every element in `range` at once, splitting at most two pieces.
+    pub fn remove_range(&mut self, range: impl RangeBounds<usize>) {
+        let (start, end) = self.bounds(range);
+        debug_assert!(start <= end && end <= self.length);
+        if start == end {
+            return;
+        }
+
+        let first = self.split_piece_at(start);
+        let last = self.split_piece_at(end);
+        self.pieces.drain(first..last);
+        if first < self.pieces.len() {
+            self.merge_with_previous(first);
+        }
+
+        self.record(Edit::Remove { start, end });
+        self.length -= end - start;
+        self.last_edit_idx = start;
+        self.reusable_edit = ReusableEdit::None;
+        self.revision += 1;
+        self.debug_validate();
+    }
+
+    /// Remove every element in `range` like [`PtBuffer::remove_range`], returning them in
+    /// order. They are collected before any piece is split or dropped.
+    pub fn remove_range_collect(&mut self, range: impl RangeBounds<usize>) -> Vec<T>
+    where
+        T: Clone,
+    {
+        let (start, end) = self.bounds(range);
+        let removed = self.range(start..end).cloned().collect();
+        self.remove_range(start..end);
+        removed
+    }
 
-                self.reusable_edit = ReusableEdit::Remove(loc);
+    /// Check the piece invariants: pieces add up to the buffer length, stay within their
+    /// buffer and are never empty, and the cached edit state points to a valid piece.
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        for (piece_idx, piece) in self.pieces.iter().enumerate() {
+            if piece.length == 0 {
+                return Err(InvariantError::EmptyPiece { piece_idx });
+            }
+
+            let buffer_len = match piece.with_buffer {
+                WithBuffer::Original => self.file_buffer.len(),
+                WithBuffer::Add => self.add_buffer.len(),
+            };
+            let end = piece.start + piece.length;
+            if end > buffer_len {
+                return Err(InvariantError::PieceOutOfBounds {
+                    piece_idx,
+                    end,
+                    buffer_len,
+                });
             }
         }
 
-        self.last_edit_idx = at;
+        let pieces = self.pieces.iter().map(|piece| piece.length).sum();
+        if pieces != self.length {
+            return Err(InvariantError::LengthMismatch {
+                pieces,
+                length: self.length,
+            });
+        }
+
+        if self.last_edit_idx > self.length {
+            return Err(InvariantError::LastEditOutOfBounds {
+                last_edit_idx: self.last_edit_idx,
+                length: self.length,
+            });
+        }
+
+        let reused_piece = match self.reusable_edit {
+            ReusableEdit::Insert(piece_idx) => Some(piece_idx),
+            ReusableEdit::Remove(Location::Head(piece_idx))
+            | ReusableEdit::Remove(Location::Middle(piece_idx, _))
+            | ReusableEdit::Remove(Location::Tail(piece_idx, _)) => Some(piece_idx),
+            ReusableEdit::Remove(Location::Eof) | ReusableEdit::None => None,
+        };
+
+        match reused_piece {
+            Some(piece_idx) if piece_idx >= self.pieces.len() => {
+                Err(InvariantError::StaleReusableEdit { piece_idx })
+            }
+            Some(piece_idx)
+                if matches!(self.reusable_edit, ReusableEdit::Insert(_))
+                    && self.pieces[piece_idx].with_buffer != WithBuffer::Add =>
+            {
+                Err(InvariantError::StaleReusableEdit { piece_idx })
+            }
+            _ => Ok(()),
+        }
     }
 
     pub fn len(&self) -> usize {
         self.length
     }
 
+    /// Where the index `idx` lands once `edit` is applied, see [`Edit::adjust`].
+    pub fn adjust_index(&self, idx: usize, edit: &Edit) -> usize {
+        edit.adjust(idx)
+    }
+
+    /// Clamp `idx` to a valid cursor position, the end of the buffer included.
+    pub fn clamp_index(&self, idx: usize) -> usize {
+        idx.min(self.length)
+    }
+
+    /// Element at `idx`, or `None` past the end of the buffer.
+    pub fn get(&'a self, idx: usize) -> Option<&'a T> {
+        self.cursor_at(idx).get()
+    }
+
     pub fn is_empty(&self) -> bool {
         self.length == 0
     }
-}
 
-impl<'a, T: 'a> PtBuffer<'a, T> {
-    fn raw_insert(&mut self, at: usize, item: T) {
-        let piece_start = self.add_buffer.len();
-        self.add_buffer.push(item);
-        match self.index_to_piece_loc(at) {
-            Location::Head(piece_idx) => {
-                self.pieces.insert(
-                    piece_idx,
-                    Piece {
-                        start: piece_start,
-                        length: 1,
-                        with_buffer: WithBuffer::Add,
-                    },
-                );
+    /// Hash of the content alone, buffers holding the same elements hashing equally
+    /// whatever their pieces.
+    pub fn content_hash(&self) -> u64
+    where
+        T: Hash,
+    {
+        let mut hasher = DefaultHasher::new();
+        for piece in &self.pieces {
+            T::hash_slice(
+                &self.get_buffer(piece)[piece.start..piece.start + piece.length],
+                &mut hasher,
+            );
+        }
+        hasher.write_usize(self.length);
+        hasher.finish()
+    }
 
-                self.reusable_edit = ReusableEdit::Insert(piece_idx, true);
+    /// Fold every element into an accumulator, piece slice after piece slice, which is
+    /// faster than going through the element iterator.
+    pub fn fold<B>(&self, init: B, mut f: impl FnMut(B, &T) -> B) -> B {
+        self.range_slices(..)
+            .fold(init, |acc, slice| slice.iter().fold(acc, &mut f))
+    }
+
+    /// Index of the first occurrence of `needle` starting at or after `from`, an empty
+    /// needle matching nowhere.
+    pub fn find(&self, needle: &[T], from: usize) -> Option<usize>
+    where
+        T: PartialEq,
+    {
+        let first = needle.first()?;
+        let last_start = self.length.checked_sub(needle.len())?;
+        (from..=last_start)
+            .zip(self.range(from..))
+            .filter(|(_, item)| *item == first)
+            .find(|&(start, _)| self.range(start..start + needle.len()).eq(needle))
+            .map(|(start, _)| start)
+    }
+
+    /// Replace every non-overlapping occurrence of `needle` with `replacement`, scanning
+    /// from the start, and return how many were replaced.
+    pub fn replace_all(&mut self, needle: &[T], replacement: &[T]) -> usize
+    where
+        T: Clone + PartialEq,
+    {
+        let mut matches = vec![];
+        let mut from = 0;
+        while let Some(start) = self.find(needle, from) {
+            matches.push(start);
+            from = start + needle.len();
+        }
+
+        // From the end so the earlier matches stay where they were found
+        for &start in matches.iter().rev() {
+            self.remove_range(start..start + needle.len());
+            self.insert_slice(start, replacement);
+        }
+
+        matches.len()
+    }
+
+    /// Start recording edits for [`PtBuffer::take_pending_edits`], which is not done by
+    /// default so the record doesn't grow without anyone draining it.
+    pub fn track_edits(&mut self) {
+        self.pending_edits.get_or_insert_with(Vec::new);
+    }
+
+    /// Edits made since the last call, or since [`PtBuffer::track_edits`], each one in the
+    /// coordinates of the content it applied to so they can be replayed in order.
+    /// Consecutive typing or backspacing comes out as one edit.
+    pub fn take_pending_edits(&mut self) -> Vec<Edit> {
+        self.pending_edits
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    fn record(&mut self, edit: Edit) {
+        let Some(edits) = &mut self.pending_edits else {
+            return;
+        };
+
+        match (edits.last_mut(), edit) {
+            (
+                Some(Edit::Insert { at, len }),
+                Edit::Insert {
+                    at: next,
+                    len: added,
+                },
+            ) if *at + *len == next => {
+                *len += added;
             }
-            Location::Middle(piece_idx, delta) | Location::Tail(piece_idx, delta) => {
-                let origin = self.pieces[piece_idx];
-                self.pieces[piece_idx].length = delta;
+            // Backspacing
+            (Some(Edit::Remove { start, .. }), Edit::Remove { start: next, end })
+                if end == *start =>
+            {
+                *start = next;
+            }
+            // Deleting forward
+            (
+                Some(Edit::Remove { start, end }),
+                Edit::Remove {
+                    start: next,
+                    end: next_end,
+                },
+            ) if next == *start => {
+                *end += next_end - next;
+            }
+            _ => edits.push(edit),
+        }
+    }
 
-                let insert = Piece {
-                    start: piece_start,
-                    with_buffer: WithBuffer::Add,
-                    length: 1,
-                };
+    /// Record going back to `checkpoint` as replacing what differs between the current
+    /// content and its content, the elements both share at each end being left out.
+    fn record_restore(&mut self, checkpoint: &Checkpoint) {
+        let (current, restored) = (&self.pieces, &checkpoint.pieces);
+        let spans = |pieces: &[Piece]| {
+            let spans: Vec<_> = pieces
+                .iter()
+                .map(|piece| (piece.with_buffer, piece.start, piece.length))
+                .collect();
+            spans
+        };
+        // Walking from the end, as spans of mirrored indexes
+        let rev_spans = |pieces: &[Piece]| {
+            let spans: Vec<_> = pieces
+                .iter()
+                .rev()
+                .map(|piece| {
+                    let start = usize::MAX - (piece.start + piece.length);
+                    (piece.with_buffer, start, piece.length)
+                })
+                .collect();
+            spans
+        };
 
-                let split = Piece {
-                    with_buffer: origin.with_buffer,
-                    start: origin.start + delta,
-                    length: origin.length - delta,
-                };
+        let prefix = shared_len(&spans(current), &spans(restored));
+        let suffix = shared_len(&rev_spans(current), &rev_spans(restored));
+        // Shared elements in the middle would be counted at both ends
+        let suffix = suffix.min(self.length.min(checkpoint.length) - prefix);
+
+        let end = self.length - suffix;
+        if prefix < end {
+            self.record(Edit::Remove { start: prefix, end });
+        }
+        let len = checkpoint.length - suffix - prefix;
+        if len > 0 {
+            self.record(Edit::Insert { at: prefix, len });
+        }
+    }
 
-                self.pieces.insert(piece_idx + 1, insert);
-                self.pieces.insert(piece_idx + 2, split);
-                self.reusable_edit = ReusableEdit::Insert(piece_idx + 1, false);
+    /// Number of changes made to the content since the buffer was created.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Remember the current content, to come back to it with [`PtBuffer::restore`].
+    pub fn checkpoint(&mut self) -> Checkpoint {
+        self.pinned_add_len = self.add_buffer.len();
+        Checkpoint {
+            pieces: self.pieces.clone(),
+            length: self.length,
+            add_len: self.add_buffer.len(),
+            revision: self.revision,
+        }
+    }
+
+    /// Bring the content back to `checkpoint`, which must come from this buffer.
+    /// Coming back is a change like any other, the revision keeps increasing.
+    pub fn restore(&mut self, checkpoint: &Checkpoint) {
+        assert!(
+            checkpoint.add_len <= self.add_buffer.len(),
+            "checkpoint taken from another buffer"
+        );
+
+        if self.pending_edits.is_some() {
+            self.record_restore(checkpoint);
+        }
+        self.pieces.clone_from(&checkpoint.pieces);
+        self.length = checkpoint.length;
+        self.last_edit_idx = 0;
+        self.reusable_edit = ReusableEdit::None;
+        self.revision += 1;
+        self.debug_validate();
+    }
+
+    /// The whole content as one slice when it is held by a single piece, as it is
+    /// for a freshly loaded buffer, letting callers skip the piece iteration.
+    pub fn as_slice(&self) -> Option<&[T]> {
+        match self.pieces.as_slice() {
+            [] => Some(&[]),
+            [piece] => {
+                let buffer = match piece.with_buffer {
+                    WithBuffer::Original => self.file_buffer,
+                    WithBuffer::Add => &self.add_buffer,
+                };
+                Some(&buffer[piece.start..piece.start + piece.length])
             }
-            Location::Eof => {
-                let piece_idx = self.pieces.len();
+            _ => None,
+        }
+    }
 
-                self.pieces.push(Piece {
-                    with_buffer: WithBuffer::Add,
-                    start: piece_start,
-                    length: 1,
-                });
+    /// Build an owned buffer of `f` applied to every element, held by a single add piece.
+    pub fn map<U>(&self, f: impl FnMut(&T) -> U) -> PtBuffer<'static, U> {
+        let add_buffer: Vec<U> = self.iter().map(f).collect();
+        let pieces = if add_buffer.is_empty() {
+            vec![]
+        } else {
+            vec![Piece {
+                with_buffer: WithBuffer::Add,
+                start: 0,
+                length: add_buffer.len(),
+            }]
+        };
+
+        PtBuffer {
+            file_buffer: &[],
+            length: add_buffer.len(),
+            add_buffer,
+            pieces,
+            last_edit_idx: 0,
+            revision: 0,
+            reusable_edit: ReusableEdit::None,
+            pending_edits: None,
+            pinned_add_len: 0,
+            index_cache: Cell::default(),
+        }
+    }
+}
+
+/// Number of leading elements the same in two lists of `(buffer, start, length)` spans,
+/// elements being the same when they are at the same place of the same buffer.
+fn shared_len(a: &[(WithBuffer, usize, usize)], b: &[(WithBuffer, usize, usize)]) -> usize {
+    let (mut a, mut b) = (a.iter().copied(), b.iter().copied());
+    let (mut left, mut right) = (a.next(), b.next());
+    let mut shared = 0;
+    while let (
+        Some((left_buffer, left_start, left_len)),
+        Some((right_buffer, right_start, right_len)),
+    ) = (left, right)
+    {
+        if left_buffer != right_buffer || left_start != right_start {
+            break;
+        }
 
-                self.reusable_edit = ReusableEdit::Insert(piece_idx, true);
+        let len = left_len.min(right_len);
+        shared += len;
+        left = match left_len - len {
+            0 => a.next(),
+            rest => Some((left_buffer, left_start + len, rest)),
+        };
+        right = match right_len - len {
+            0 => b.next(),
+            rest => Some((right_buffer, right_start + len, rest)),
+        };
+    }
+
+    shared
+}
+
+impl<'a, T: 'a> PtBuffer<'a, T> {
+    fn raw_insert(&mut self, at: usize, item: T) {
+        // At the end, an add piece ending the add buffer grows in place like on `push`
+        if let Some(last) = self.pieces.len().checked_sub(1) {
+            if at == self.length && self.ends_add_buffer(last) {
+                self.add_buffer.push(item);
+                self.pieces[last].length += 1;
+                self.reusable_edit = ReusableEdit::Insert(last);
+                return;
             }
         }
+
+        let piece_start = self.add_buffer.len();
+        self.add_buffer.push(item);
+        let insert = Piece {
+            start: piece_start,
+            with_buffer: WithBuffer::Add,
+            length: 1,
+        };
+
+        let piece_idx = self.split_piece_at(at);
+        self.pieces.insert(piece_idx, insert);
+        let piece_idx = self.merge_with_previous(piece_idx);
+        self.reusable_edit = ReusableEdit::Insert(piece_idx);
     }
 
-    fn raw_remove(&mut self, location: Location) -> Option<usize> {
-        match location {
+    fn raw_remove(&mut self, location: Location) {
+        // Backspacing what was just typed, the element can go from the add buffer too
+        let reclaimable = match location {
+            Location::Head(piece_idx) => Some((piece_idx, 0)),
+            Location::Middle(piece_idx, delta) | Location::Tail(piece_idx, delta) => {
+                Some((piece_idx, delta))
+            }
+            Location::Eof => None,
+        }
+        .is_some_and(|(piece_idx, delta)| {
+            let piece = self.pieces[piece_idx];
+            let idx = piece.start + delta;
+            piece.with_buffer == WithBuffer::Add
+                && idx + 1 == self.add_buffer.len()
+                && idx >= self.pinned_add_len
+        });
+
+        // Piece and offset of the element preceding the removed one
+        let previous = match location {
             Location::Head(piece_idx) => {
                 let piece = &mut self.pieces[piece_idx];
                 piece.start += 1;
                 piece.length -= 1;
 
-                if piece.length == 0 {
-                    return Some(piece_idx);
-                };
+                let emptied = piece.length == 0;
+                if emptied {
+                    self.pieces.remove(piece_idx);
+                }
+
+                piece_idx.checked_sub(1).map(|previous| {
+                    let delta = self.pieces[previous].length - 1;
+                    // The pieces on both sides of the emptied one may be contiguous now
+                    if emptied && piece_idx < self.pieces.len() {
+                        self.merge_with_previous(piece_idx);
+                    }
+                    (previous, delta)
+                })
             }
             Location::Tail(piece_idx, delta) => {
                 self.pieces[piece_idx].length -= 1;
-
-                let loc = if delta - 1 == 0 {
-                    Location::Head(piece_idx)
-                } else {
-                    Location::Tail(piece_idx, delta - 1)
-                };
-
-                self.reusable_edit = ReusableEdit::Remove(loc);
+                Some((piece_idx, delta - 1))
             }
             Location::Middle(piece_idx, delta) => {
                 let orig = self.pieces[piece_idx];
                 self.pieces[piece_idx].length = delta;
+                self.pieces.insert(
+                    piece_idx + 1,
+                    Piece {
+                        start: orig.start + delta + 1,
+                        length: orig.length - delta - 1,
+                        with_buffer: orig.with_buffer,
+                    },
+                );
 
-                let start = delta + 1;
-                if orig.length - start > 0 {
-                    self.pieces.insert(
-                        piece_idx + 1,
-                        Piece {
-                            start: orig.start + start,
-                            length: orig.length - start,
-                            with_buffer: orig.with_buffer,
-                        },
-                    );
-                }
+                Some((piece_idx, delta - 1))
+            }
+            Location::Eof => None,
+        };
 
-                if piece_idx > 0 {
-                    let loc = if delta - 1 == 0 {
-                        Location::Head(piece_idx)
-                    } else {
-                        Location::Middle(piece_idx, delta - 1)
-                    };
+        self.reusable_edit = match previous {
+            Some((piece_idx, delta)) => ReusableEdit::Remove(self.element_loc(piece_idx, delta)),
+            None => ReusableEdit::None,
+        };
+        if reclaimable {
+            self.add_buffer.pop();
+        }
+    }
 
-                    self.reusable_edit = ReusableEdit::Remove(loc);
-                }
+    /// Fuse the piece at `piece_idx` into the previous one when it picks up right where
+    /// the previous one ends in the same buffer. Returns the index of the piece now
+    /// holding its elements.
+    fn merge_with_previous(&mut self, piece_idx: PieceIdx) -> PieceIdx {
+        let Some(previous) = piece_idx.checked_sub(1) else {
+            return piece_idx;
+        };
+
+        let (before, piece) = (self.pieces[previous], self.pieces[piece_idx]);
+        if before.with_buffer != piece.with_buffer || before.start + before.length != piece.start {
+            return piece_idx;
+        }
+
+        self.pieces[previous].length += piece.length;
+        self.pieces.remove(piece_idx);
+        previous
+    }
+
+    fn debug_validate(&self) {
+        #[cfg(debug_assertions)]
+        if let Err(err) = self.validate() {
+            panic!("piece table corrupted: {err}\n{:?}", self.pieces);
+        }
+    }
+
+    fn last_element_loc(&self, piece_idx: PieceIdx) -> Location {
+        self.element_loc(piece_idx, self.pieces[piece_idx].length - 1)
+    }
+
+    /// Location of the element `delta` into the piece, as `index_to_piece_loc` gives it.
+    fn element_loc(&self, piece_idx: PieceIdx, delta: Delta) -> Location {
+        match delta {
+            0 => Location::Head(piece_idx),
+            delta if delta == self.pieces[piece_idx].length - 1 => Location::Tail(piece_idx, delta),
+            delta => Location::Middle(piece_idx, delta),
+        }
+    }
+
+    fn ends_add_buffer(&self, piece_idx: PieceIdx) -> bool {
+        let piece = &self.pieces[piece_idx];
+        piece.with_buffer == WithBuffer::Add && piece.start + piece.length == self.add_buffer.len()
+    }
+
+    /// Make sure a piece boundary exists at `idx` and return the index of the piece starting there.
+    fn split_piece_at(&mut self, idx: usize) -> PieceIdx {
+        match self.index_to_piece_loc(idx) {
+            Location::Head(piece_idx) => piece_idx,
+            Location::Middle(piece_idx, delta) | Location::Tail(piece_idx, delta) => {
+                // Pieces change before the revision does
+                self.index_cache.take();
+                let origin = self.pieces[piece_idx];
+                self.pieces[piece_idx].length = delta;
+                self.pieces.insert(
+                    piece_idx + 1,
+                    Piece {
+                        with_buffer: origin.with_buffer,
+                        start: origin.start + delta,
+                        length: origin.length - delta,
+                    },
+                );
+
+                piece_idx + 1
             }
-            Location::Eof => {}
+            Location::Eof => self.pieces.len(),
         }
+    }
+
+    pub(crate) fn bounds(&self, range: impl RangeBounds<usize>) -> (usize, usize) {
+        let from = match range.start_bound() {
+            Bound::Included(x) => *x,
+            Bound::Excluded(x) => *x + 1,
+            Bound::Unbounded => 0,
+        };
 
-        None
+        let to = match range.end_bound() {
+            Bound::Included(x) => *x + 1,
+            Bound::Excluded(x) => *x,
+            Bound::Unbounded => self.length,
+        };
+
+        (from, to)
+    }
+
+    /// The parts of each piece within `range`, in document order.
+    fn range_slices(&self, range: impl RangeBounds<usize>) -> impl Iterator<Item = &[T]> {
+        let (from, to) = self.bounds(range);
+        let mut piece_start = 0;
+        self.pieces
+            .iter()
+            .map_while(move |piece| {
+                let start = piece_start;
+                piece_start += piece.length;
+                (start < to).then_some((start, piece))
+            })
+            .filter(move |(start, piece)| start + piece.length > from)
+            .map(move |(start, piece)| {
+                let skip = from.saturating_sub(start);
+                let take = to.min(start + piece.length) - start;
+                let buffer = match piece.with_buffer {
+                    WithBuffer::Original => self.file_buffer,
+                    WithBuffer::Add => &self.add_buffer,
+                };
+                &buffer[piece.start + skip..piece.start + take]
+            })
     }
 
-    fn index_to_piece_loc(&self, idx: usize) -> Location {
-        let mut acc = 0;
-        for (piece_idx, piece) in self.pieces.iter().enumerate() {
-            if idx >= acc && idx < acc + piece.length {
-                return match idx - acc {
-                    0 => Location::Head(piece_idx),
-                    delta if delta == piece.length - 1 => Location::Tail(piece_idx, delta),
-                    delta => Location::Middle(piece_idx, delta),
-                };
+    /// Walks the pieces from the ones found by the previous lookup, so looking up indices
+    /// close to each other only steps over the pieces in between.
+    fn index_to_piece_loc(&self, idx: usize) -> Location {
+        let cache = self.index_cache.get();
+        let (mut piece_idx, mut acc) = if cache.revision == self.revision {
+            (cache.piece_idx, cache.piece_start)
+        } else {
+            (0, 0)
+        };
+
+        while idx < acc {
+            piece_idx -= 1;
+            acc -= self.pieces[piece_idx].length;
+            count_scanned_piece();
+        }
+
+        loop {
+            let Some(piece) = self.pieces.get(piece_idx) else {
+                return Location::Eof;
+            };
+
+            if idx < acc + piece.length {
+                break;
             }
 
             acc += piece.length;
+            piece_idx += 1;
+            count_scanned_piece();
         }
 
-        Location::Eof
+        self.index_cache.set(IndexCache {
+            revision: self.revision,
+            piece_idx,
+            piece_start: acc,
+        });
+        self.element_loc(piece_idx, idx - acc)
     }
 
     pub(crate) fn get_buffer(&'a self, piece: &Piece) -> &'a [T] {
@@ -297,9 +1466,13 @@ impl<'a, T: 'a> PtBuffer<'a, T> {
 
 #[cfg(test)]
 mod test {
+    use proptest::prelude::*;
     use unicode_segmentation::UnicodeSegmentation;
 
-    use crate::{Piece, PtBuffer};
+    use crate::{
+        graphemes, line_endings, DocStats, Edit, InvariantError, Piece, PtBuffer, ReusableEdit,
+        WithBuffer, SCANNED_PIECES,
+    };
 
     #[test]
     fn should_create_a_pt_buffer() {
@@ -498,6 +1671,974 @@ mod test {
         assert_eq!("Hello w$rld", string);
     }
 
+    #[test]
+    fn should_trim_trailing_whitespace() {
+        let src = graphemes("fn main() {  \n    let a = 1;\t\n}");
+        let mut buf = PtBuffer::new(&src);
+        let removed = buf.trim_trailing_whitespace();
+
+        assert_eq!(removed, [11..13, 28..29]);
+        assert_eq!(buf.len(), 28);
+        assert_string_buf(&buf, "fn main() {\n    let a = 1;\n}");
+    }
+
+    #[test]
+    fn should_trim_whitespace_only_line() {
+        let src = graphemes("a\n   \nb");
+        let mut buf = PtBuffer::new(&src);
+        assert_eq!(buf.trailing_whitespace(), vec![2..5]);
+        buf.trim_trailing_whitespace();
+        assert_string_buf(&buf, "a\n\nb");
+    }
+
+    #[test]
+    fn should_trim_final_line() {
+        let src = graphemes("a b\nc d \t ");
+        let mut buf = PtBuffer::new(&src);
+        let removed = buf.trim_trailing_whitespace();
+
+        assert_eq!(removed, vec![7..10]);
+        assert_string_buf(&buf, "a b\nc d");
+    }
+
+    #[test]
+    fn should_ensure_single_final_newline() {
+        let src = graphemes("a");
+        let mut buf = PtBuffer::new(&src);
+        buf.ensure_final_newline();
+        assert_string_buf(&buf, "a\n");
+
+        let src = graphemes("a\n\n\n");
+        let mut buf = PtBuffer::new(&src);
+        assert_eq!(buf.trailing_line_endings(), 3);
+        buf.ensure_final_newline();
+        assert_string_buf(&buf, "a\n");
+    }
+
+    #[test]
+    fn should_remove_range_across_pieces() {
+        let mut buf = PtBuffer::new(b"Hello world");
+        insert_str_at(&mut buf, 5, ",");
+        buf.remove_range(3..9);
+        assert_eq!(buf.len(), 6);
+        assert_buf_str(&buf, "Helrld");
+    }
+
+    #[test]
+    fn should_insert_slice() {
+        let mut buf = PtBuffer::new(b"Hello world");
+        buf.insert_slice(5, b", big");
+        buf.insert_slice(0, b">> ");
+        buf.insert_slice(buf.len(), b"!");
+        assert_eq!(buf.len(), 20);
+        assert_buf_str(&buf, ">> Hello, big world!");
+    }
+
+    #[test]
+    fn should_insert_from_iterators() {
+        let mut buf = PtBuffer::new(b"Hello world");
+        buf.insert_iter(5, vec![b',', b' ', b'b', b'i', b'g']);
+        buf.insert_iter(buf.len(), "!!".bytes());
+        buf.insert_iter(0, std::iter::empty());
+
+        assert_buf_str(&buf, "Hello, big world!!");
+        assert_eq!(buf.pieces.len(), 4);
+        assert_eq!(buf.revision(), 2);
+        assert_eq!(buf.validate(), Ok(()));
+    }
+
+    #[test]
+    fn should_get_line_range() {
+        let src = graphemes("ab\n\ncde");
+        let buf = PtBuffer::new(&src);
+        assert_eq!(buf.line_range(0), Some(0..2));
+        assert_eq!(buf.line_range(1), Some(3..3));
+        assert_eq!(buf.line_range(2), Some(4..7));
+        assert_eq!(buf.line_range(3), None);
+        assert_eq!(buf.line_count(), 3);
+    }
+
+    #[test]
+    fn should_find_blank_lines() {
+        let src = graphemes("\nab\n \t\ncd\n\nef");
+        let mut buf = PtBuffer::new(&src);
+        let blank: Vec<bool> = (0..7).map(|line| buf.is_blank_line(line)).collect();
+        assert_eq!(blank, [true, false, true, false, true, false, false]);
+
+        assert_eq!(buf.next_blank_line(0), Some(2));
+        assert_eq!(buf.next_blank_line(2), Some(4));
+        assert_eq!(buf.next_blank_line(4), None);
+        assert_eq!(buf.next_blank_line(10), None);
+        assert_eq!(buf.prev_blank_line(4), Some(2));
+        assert_eq!(buf.prev_blank_line(2), Some(0));
+        assert_eq!(buf.prev_blank_line(0), None);
+        assert_eq!(buf.prev_blank_line(10), Some(4));
+
+        // A trailing line ending starts a last, empty line
+        buf.insert(buf.len(), "\n".to_string());
+        assert_eq!(buf.next_blank_line(4), Some(6));
+    }
+
+    #[test]
+    fn should_convert_line_column_to_idx() {
+        let src = graphemes("ab\ncd");
+        let buf = PtBuffer::new(&src);
+        assert_eq!(buf.line_column_to_idx(0, 0), 0);
+        assert_eq!(buf.line_column_to_idx(2, 0), 2);
+        assert_eq!(buf.line_column_to_idx(1, 1), 4);
+        assert_eq!(buf.line_column_to_idx(2, 1), 5);
+    }
+
+    #[test]
+    fn should_fold_like_the_iterator() {
+        let mut buf = PtBuffer::new(&[3u32, 9, 1, 4]);
+        buf.insert_slice(2, &[7, 12]);
+        buf.remove(0);
+        buf.push(5);
+        assert_eq!(buf.iter().copied().collect::<Vec<_>>(), [9, 7, 12, 1, 4, 5]);
+
+        let sum = buf.fold(0, |sum, n| sum + n);
+        assert_eq!(sum, buf.iter().sum::<u32>());
+        let max = buf.fold(None, |max: Option<u32>, n| max.max(Some(*n)));
+        assert_eq!(max, buf.iter().copied().max());
+        assert_eq!(PtBuffer::<u32>::new(&[]).fold(0, |count, _| count + 1), 0);
+    }
+
+    #[test]
+    fn should_replace_every_occurrence() {
+        let mut buf = PtBuffer::new(b"a cat, a cat and a dog");
+        buf.insert_slice(6, b" ca");
+        assert!(buf.eq_bytes(b"a cat, ca a cat and a dog"));
+        assert_eq!(buf.find(b"cat", 0), Some(2));
+        assert_eq!(buf.find(b"cat", 3), Some(12));
+        assert_eq!(buf.find(b"", 0), None);
+
+        assert_eq!(buf.replace_all(b"cat", b"tiger"), 2);
+        assert!(buf.eq_bytes(b"a tiger, ca a tiger and a dog"));
+        assert_eq!(buf.replace_all(b"a ", b""), 4);
+        assert!(buf.eq_bytes(b"tiger, ctiger and dog"));
+
+        // Overlapping candidates are matched from the left, without rescanning replacements
+        let mut buf = PtBuffer::new(b"aaaaa");
+        assert_eq!(buf.replace_all(b"aa", b"ba"), 2);
+        assert!(buf.eq_bytes(b"babaa"));
+
+        let revision = buf.revision();
+        assert_eq!(buf.replace_all(b"x", b"y"), 0);
+        assert_eq!(buf.replace_all(b"babaab", b"y"), 0);
+        assert_eq!(buf.revision(), revision);
+    }
+
+    #[test]
+    fn should_insert_newlines_with_their_indent() {
+        let mut buf = PtBuffer::new(b"    let a = 1;");
+        assert_eq!(buf.insert_newline(10, b"    "), 15);
+        assert!(buf.eq_bytes(b"    let a \n    = 1;"));
+        assert_eq!(buf.insert_newline(buf.len(), b""), 20);
+        assert!(buf.eq_bytes(b"    let a \n    = 1;\n"));
+
+        let src: Vec<String> = "fn f() {".chars().map(String::from).collect();
+        let mut buf = PtBuffer::new(&src);
+        let indent = vec![" ".to_string(); 4];
+        assert_eq!(buf.insert_newline(8, &indent), 13);
+        assert!(buf.eq_str("fn f() {\n    "));
+        assert_eq!(buf.insert_newline(3, &[]), 4);
+        assert!(buf.eq_str("fn \nf() {\n    "));
+    }
+
+    #[test]
+    fn should_list_line_starts_across_pieces() {
+        let src = graphemes("ab\ncd\n");
+        let mut buf = PtBuffer::new(&src);
+        assert_eq!(buf.line_starts(), [0, 3, 6]);
+
+        buf.insert_slice(4, &graphemes("\nx\n"));
+        buf.remove(0);
+        buf.insert(0, "\n".to_string());
+        let rescan: Vec<usize> = std::iter::once(0)
+            .chain(
+                buf.iter()
+                    .enumerate()
+                    .filter(|(_, c)| *c == "\n")
+                    .map(|(i, _)| i + 1),
+            )
+            .collect();
+        assert_eq!(buf.line_starts(), rescan);
+        assert_eq!(buf.line_starts_from(&line_endings(&src)), rescan);
+        assert_eq!(buf.line_starts().len(), buf.line_count());
+        assert_eq!(PtBuffer::<String>::new(&[]).line_starts(), [0]);
+        assert_eq!(PtBuffer::<String>::new(&[]).line_starts_from(&[]), [0]);
+    }
+
+    #[test]
+    fn should_map_bytes_and_elements() {
+        let src = graphemes("añ\r\nx");
+        let mut buf = PtBuffer::new(&src);
+        // "añ日🇫🇷\r\nx" with the wide ones in an add piece
+        buf.insert_slice(2, &graphemes("日🇫🇷"));
+        let text = "añ日🇫🇷\r\nx";
+
+        let starts = [0, 1, 3, 6, 14, 16];
+        for (elem, &byte) in starts.iter().enumerate() {
+            assert_eq!(buf.element_to_byte(elem), byte);
+            assert_eq!(buf.byte_to_element(byte), elem);
+        }
+        assert_eq!(buf.element_to_byte(buf.len()), text.len());
+        assert_eq!(buf.element_to_byte(100), text.len());
+        assert_eq!(buf.byte_to_element(text.len()), buf.len());
+
+        // Inside a grapheme, the element it belongs to
+        assert_eq!(buf.byte_to_element(2), 1);
+        assert_eq!(buf.byte_to_element(5), 2);
+        assert_eq!(buf.byte_to_element(10), 3);
+        assert_eq!(buf.byte_to_element(15), 4);
+    }
+
+    #[test]
+    fn should_count_lines_in_range() {
+        let mut buf = PtBuffer::new(b"ab\ncd\n\nef");
+        buf.insert_slice(4, b"x\ny");
+        buf.insert(0, b'\n');
+        assert!(buf.eq_bytes(b"\nab\ncx\nyd\n\nef"));
+
+        for range in [0..14, 0..1, 1..4, 2..7, 4..8, 5..6, 8..11, 11..14] {
+            let expected = buf.range(range.clone()).filter(|b| **b == b'\n').count();
+            assert_eq!(
+                buf.count_lines_in_range(range.clone()),
+                expected,
+                "{range:?}"
+            );
+        }
+        assert_eq!(buf.count_lines_in_range(..), 5);
+        assert_eq!(buf.count_lines_in_range(3..=7), 2);
+        assert_eq!(buf.count_lines_in_range(9..9), 0);
+
+        let src = graphemes("a\nb\r\nc");
+        let mut buf = PtBuffer::new(&src);
+        buf.insert(1, "\n".to_string());
+        // A CRLF element isn't a line ending, as for line_count
+        assert_eq!(buf.count_lines_in_range(..), 2);
+        assert_eq!(buf.count_lines_in_range(1..2), 1);
+        assert_eq!(buf.count_lines_in_range(2..), 1);
+    }
+
+    #[test]
+    fn should_restore_checkpoints() {
+        let mut buf = PtBuffer::new(b"Hello");
+        buf.insert(5, b'!');
+        let before = buf.checkpoint();
+        assert_eq!(before.revision(), 1);
+
+        buf.remove(0);
+        buf.insert_slice(0, b"J");
+        buf.insert(5, b'y');
+        let after = buf.checkpoint();
+        assert!(buf.eq_bytes(b"Jelloy!"));
+
+        buf.restore(&before);
+        assert!(buf.eq_bytes(b"Hello!"));
+        assert_eq!(buf.revision(), 5);
+
+        // Edits after restoring don't disturb the other checkpoint
+        buf.insert(6, b'!');
+        buf.remove(0);
+        assert!(buf.eq_bytes(b"ello!!"));
+        buf.restore(&after);
+        assert!(buf.eq_bytes(b"Jelloy!"));
+        buf.insert(7, b'?');
+        assert!(buf.eq_bytes(b"Jelloy!?"));
+        buf.restore(&before);
+        assert!(buf.eq_bytes(b"Hello!"));
+        assert_eq!(buf.revision(), 10);
+    }
+
+    #[test]
+    fn should_find_line_start_and_end() {
+        let src = graphemes("ab\n\ncd\n");
+        let mut buf = PtBuffer::new(&src);
+        buf.insert(6, "e".to_string());
+        let bounds = |idx| (buf.line_start(idx), buf.line_end(idx));
+
+        // Start, middle and line ending of the first line
+        assert_eq!(bounds(0), (0, 2));
+        assert_eq!(bounds(1), (0, 2));
+        assert_eq!(bounds(2), (0, 2));
+        // An empty line
+        assert_eq!(bounds(3), (3, 3));
+        // A line across pieces, then the empty last line
+        assert_eq!(bounds(4), (4, 7));
+        assert_eq!(bounds(6), (4, 7));
+        assert_eq!(bounds(7), (4, 7));
+        assert_eq!(bounds(8), (8, 8));
+        assert_eq!(bounds(42), (8, 8));
+    }
+
+    #[test]
+    fn should_get_at_line_column() {
+        let src = graphemes("ab\ncd");
+        let mut buf = PtBuffer::new(&src);
+        buf.insert(4, "x".to_string());
+
+        assert_eq!(buf.at_line_column(1, 0).map(String::as_str), Some("b"));
+        assert_eq!(buf.at_line_column(2, 0).map(String::as_str), Some("\n"));
+        assert_eq!(buf.at_line_column(1, 1).map(String::as_str), Some("x"));
+        assert_eq!(buf.try_line_column_to_idx(3, 1), Some(6));
+
+        // Past the end of a line, of the document and below the last line
+        assert_eq!(buf.try_line_column_to_idx(3, 0), None);
+        assert_eq!(buf.at_line_column(3, 1), None);
+        assert_eq!(buf.at_line_column(4, 1), None);
+        assert_eq!(buf.at_line_column(0, 2), None);
+        assert_eq!(buf.get(6), None);
+    }
+
+    #[test]
+    fn should_insert_at_line_column() {
+        let src = graphemes("fn main() {\n}\n");
+        let mut buf = PtBuffer::new(&src);
+
+        assert_eq!(buf.insert_at(0, 1, " ".to_string()), 13);
+        assert_eq!(
+            buf.insert_str_at_line_column(11, 0, "\n    let 🦀 = 1;"),
+            26
+        );
+        assert_string_buf(&buf, "fn main() {\n    let 🦀 = 1;\n }\n");
+
+        // End of the document
+        assert_eq!(buf.insert_str_at_line_column(0, 3, "//"), 32);
+        assert_string_buf(&buf, "fn main() {\n    let 🦀 = 1;\n }\n//");
+
+        let mut buf = PtBuffer::new(b"ab\ncd");
+        assert_eq!(buf.insert_at(1, 1, b'x'), 5);
+        assert_eq!(buf.insert_at(2, 0, b'!'), 3);
+        assert_eq!(buf.iter().copied().collect::<Vec<u8>>(), b"ab!\ncxd");
+    }
+
+    #[test]
+    fn should_extend_ranges_into_a_buffer() {
+        let mut buf = PtBuffer::new(b"Hello world");
+        buf.insert(5, b',');
+        let mut out = b"> ".to_vec();
+        buf.extend_range_into(..6, &mut out);
+        buf.extend_range_into(6.., &mut out);
+        assert_eq!(out, b"> Hello, world");
+
+        let src = graphemes("fn main() {}");
+        let buf = PtBuffer::new(&src);
+        let mut out = String::new();
+        buf.extend_str_into(3..7, &mut out);
+        buf.extend_str_into(9.., &mut out);
+        assert_eq!(out, "main {}");
+    }
+
+    #[test]
+    fn should_append_str_as_one_piece() {
+        let src = graphemes("ab");
+        let mut buf = PtBuffer::new(&src);
+
+        buf.append_str("héllo 👋🏽");
+        assert_eq!(buf.pieces.len(), 2);
+        assert_eq!(buf.len(), 9);
+
+        // Keeps growing the trailing add piece
+        buf.append_str("\r\n!");
+        buf.append_str("");
+        assert_eq!(buf.pieces.len(), 2);
+        assert_eq!(buf.len(), 11);
+        assert_eq!(buf.revision(), 2);
+        assert_eq!(buf.iter().cloned().collect::<String>(), "abhéllo 👋🏽\r\n!");
+        assert_eq!(buf.validate(), Ok(()));
+
+        // An edit elsewhere ends the run
+        buf.insert(0, ">".to_string());
+        buf.append_str("?");
+        assert_eq!(buf.pieces.len(), 4);
+    }
+
+    #[test]
+    fn should_grow_the_last_piece_when_inserting_at_the_end() {
+        let mut buf = PtBuffer::new(b"ab");
+        for &byte in b"cdef" {
+            buf.insert(buf.len(), byte);
+        }
+        assert!(buf.eq_bytes(b"abcdef"));
+        assert_eq!(buf.pieces.len(), 2);
+
+        // Without the cached edit to reuse
+        let checkpoint = buf.checkpoint();
+        buf.restore(&checkpoint);
+        buf.insert(buf.len(), b'g');
+        assert_eq!(
+            buf.pieces[1..],
+            [Piece {
+                with_buffer: WithBuffer::Add,
+                start: 0,
+                length: 5,
+            }]
+        );
+
+        // Once something else was added, the end gets a piece of its own
+        buf.insert(0, b'>');
+        buf.insert(buf.len(), b'h');
+        assert!(buf.eq_bytes(b">abcdefgh"));
+        assert_eq!(buf.pieces.len(), 4);
+    }
+
+    #[test]
+    fn should_return_the_removed_range() {
+        let mut buf = PtBuffer::new(b"hello world");
+        buf.insert_slice(5, b" big");
+        assert!(buf.eq_bytes(b"hello big world"));
+
+        // Across the original and add pieces
+        assert_eq!(buf.remove_range_collect(3..12), b"lo big wo");
+        assert!(buf.eq_bytes(b"helrld"));
+        assert_eq!(buf.remove_range_collect(4..), b"ld");
+        assert_eq!(buf.remove_range_collect(1..1), b"");
+        assert!(buf.eq_bytes(b"helr"));
+    }
+
+    #[test]
+    fn should_reclaim_the_add_buffer_on_backspace() {
+        let mut buf = PtBuffer::new(b"hello !");
+        buf.insert(6, b'x');
+        let added = buf.add_buffer.len();
+
+        for (idx, c) in b"world".iter().enumerate() {
+            buf.insert(6 + idx, *c);
+        }
+        assert_eq!(buf.add_buffer.len(), added + 5);
+        for idx in (6..11).rev() {
+            buf.remove(idx);
+        }
+        assert!(buf.eq_bytes(b"hello x!"));
+        assert_eq!(buf.add_buffer.len(), added);
+
+        // Typing again takes the freed slots
+        buf.insert(6, b'w');
+        assert!(buf.eq_bytes(b"hello wx!"));
+        assert_eq!(buf.add_buffer.len(), added + 1);
+
+        // What a checkpoint may point at stays
+        let checkpoint = buf.checkpoint();
+        buf.remove(6);
+        assert_eq!(buf.add_buffer.len(), added + 1);
+        buf.insert_slice(8, b"??");
+        buf.remove(9);
+        buf.remove(8);
+        assert_eq!(buf.add_buffer.len(), added + 1);
+        buf.restore(&checkpoint);
+        assert!(buf.eq_bytes(b"hello wx!"));
+
+        // Deleting something typed earlier leaves the add buffer as is
+        buf.remove(7);
+        assert!(buf.eq_bytes(b"hello w!"));
+        assert_eq!(buf.add_buffer.len(), added + 1);
+    }
+
+    #[test]
+    fn should_drain_pending_edits() {
+        let mut buf = PtBuffer::new(b"hello world");
+        buf.insert(0, b'!');
+        assert_eq!(buf.take_pending_edits(), []);
+
+        buf.track_edits();
+        // Typing, backspacing and deleting forward each make up one edit
+        buf.insert(6, b',');
+        buf.insert(7, b'?');
+        buf.remove(7);
+        buf.remove(6);
+        buf.remove(0);
+        buf.remove(0);
+        assert!(buf.eq_bytes(b"ello world"));
+        assert_eq!(
+            buf.take_pending_edits(),
+            [
+                Edit::Insert { at: 6, len: 2 },
+                Edit::Remove { start: 6, end: 8 },
+                Edit::Remove { start: 0, end: 2 },
+            ]
+        );
+        assert_eq!(buf.take_pending_edits(), []);
+
+        // Going back to a checkpoint only replaces what changed since
+        let checkpoint = buf.checkpoint();
+        buf.insert_slice(3, b"XY");
+        buf.remove_range(0..1);
+        assert!(buf.eq_bytes(b"llXYo world"));
+        assert_eq!(
+            buf.take_pending_edits(),
+            [
+                Edit::Insert { at: 3, len: 2 },
+                Edit::Remove { start: 0, end: 1 },
+            ]
+        );
+
+        buf.restore(&checkpoint);
+        assert!(buf.eq_bytes(b"ello world"));
+        assert_eq!(
+            buf.take_pending_edits(),
+            [
+                Edit::Remove { start: 0, end: 4 },
+                Edit::Insert { at: 0, len: 3 },
+            ]
+        );
+        buf.restore(&checkpoint);
+        assert_eq!(buf.take_pending_edits(), []);
+    }
+
+    #[test]
+    fn should_merge_contiguous_pieces() {
+        let mut buf = PtBuffer::new(b"hello world");
+
+        // Inserting then removing the same element leaves the original piece whole
+        for _ in 0..20 {
+            buf.insert(5, b',');
+            buf.remove(5);
+        }
+        assert_eq!(buf.pieces.len(), 1);
+        buf.insert_slice(5, b" big");
+        buf.remove_range(5..9);
+        assert_eq!(buf.pieces.len(), 1);
+
+        // Slices inserted one after the other grow the same piece
+        for (i, word) in [&b" a"[..], b" b", b" c"].into_iter().enumerate() {
+            buf.insert_slice(5 + 2 * i, word);
+        }
+        assert!(buf.eq_bytes(b"hello a b c world"));
+        assert_eq!(buf.pieces.len(), 3);
+
+        // As do single elements once the cached edit is gone
+        buf.insert(11, b'!');
+        buf.remove(0);
+        buf.insert(11, b'?');
+        buf.insert(0, b'h');
+        assert!(buf.eq_bytes(b"hello a b c!? world"));
+        assert_eq!(buf.pieces.len(), 4);
+
+        // Backspacing into a merged piece keeps going from the right element
+        buf.remove(12);
+        buf.remove(11);
+        buf.remove(10);
+        assert!(buf.eq_bytes(b"hello a b  world"));
+        assert_eq!(buf.validate(), Ok(()));
+    }
+
+    #[test]
+    fn should_find_invalid_utf8() {
+        let src = "aé€🦀".as_bytes();
+        let mut buf = PtBuffer::new(src);
+        assert!(buf.is_valid_utf8());
+
+        // Sequences split across pieces are still valid
+        buf.remove(2);
+        buf.insert(2, src[2]);
+        buf.remove(4);
+        buf.insert(4, src[4]);
+        assert!(buf.pieces.len() > 1);
+        assert!(buf.is_valid_utf8());
+
+        // Dangling continuation byte of "é"
+        buf.remove(1);
+        assert_eq!(buf.find_invalid_utf8(), Some(1));
+
+        // Truncated crab at the end
+        let mut buf = PtBuffer::new(src);
+        buf.remove(src.len() - 1);
+        assert_eq!(buf.find_invalid_utf8(), Some(6));
+    }
+
+    #[test]
+    fn should_repair_utf8() {
+        let src = "aé€🦀".as_bytes();
+        let mut buf = PtBuffer::new(src);
+        buf.remove(1);
+        buf.remove(src.len() - 2);
+
+        assert_eq!(buf.repair_utf8(), 2);
+        assert!(buf.is_valid_utf8());
+        let bytes: Vec<u8> = buf.iter().copied().collect();
+        assert_eq!(String::from_utf8(bytes).unwrap(), "a\u{FFFD}€\u{FFFD}");
+        assert_eq!(buf.validate(), Ok(()));
+    }
+
+    #[test]
+    fn should_map_into_a_new_buffer() {
+        let mut buf = PtBuffer::new(b"Hello world");
+        buf.insert(5, b',');
+        let mut upper = buf.map(u8::to_ascii_uppercase);
+
+        assert_buf_str(&upper, "HELLO, WORLD");
+        assert_eq!(upper.pieces.len(), 1);
+        assert_eq!(upper.validate(), Ok(()));
+
+        // The new buffer is independent and editable
+        upper.push(b'!');
+        assert_buf_str(&upper, "HELLO, WORLD!");
+        assert_buf_str(&buf, "Hello, world");
+
+        let chars = buf.map(|b| *b as char);
+        assert_eq!(chars.iter().collect::<String>(), "Hello, world");
+        assert!(PtBuffer::<u8>::new(b"").map(|b| *b).is_empty());
+    }
+
+    #[test]
+    fn should_borrow_a_single_piece_as_slice() {
+        let mut buf = PtBuffer::new(b"Hello world");
+        assert_eq!(buf.as_slice(), Some(&b"Hello world"[..]));
+
+        buf.insert(5, b',');
+        assert_eq!(buf.as_slice(), None);
+
+        let mapped = buf.map(|b| *b);
+        assert_eq!(mapped.as_slice(), Some(&b"Hello, world"[..]));
+
+        buf.remove_range(..6);
+        buf.remove_range(..);
+        assert_eq!(buf.as_slice(), Some(&[][..]));
+    }
+
+    #[test]
+    fn should_compare_with_bytes() {
+        let mut buf = PtBuffer::new(b"Hello world");
+        buf.insert(5, b',');
+
+        assert!(buf.eq_bytes(b"Hello, world"));
+        assert!(!buf.eq_bytes(b"Hello, worl"));
+        assert!(!buf.eq_bytes(b"Hello, world!"));
+        assert!(!buf.eq_bytes(b"Hello; world"));
+        assert!(PtBuffer::new(b"").eq_bytes(b""));
+    }
+
+    #[test]
+    fn should_compare_with_str() {
+        let src = graphemes("héllo\r\n");
+        let mut buf = PtBuffer::new(&src);
+        buf.insert(2, "y".to_string());
+
+        assert!(buf.eq_str("héyllo\r\n"));
+        assert!(!buf.eq_str("héyllo"));
+        assert!(!buf.eq_str("héyllo\r\n!"));
+        assert!(!buf.eq_str("hexllo\r\n"));
+        assert!(!buf.eq_str(""));
+        assert!(PtBuffer::<String>::new(&[]).eq_str(""));
+    }
+
+    #[test]
+    fn should_insert_without_reallocating_below_the_reserved_size() {
+        let mut buf = PtBuffer::with_add_capacity(b"Hello", 64);
+        buf.reserve_pieces(32);
+        assert!(buf.add_buffer.capacity() >= 64);
+        let add_buffer = buf.add_buffer.as_ptr();
+        let pieces = buf.pieces.as_ptr();
+
+        for i in 0..16 {
+            buf.insert(i * 2 % (buf.len() + 1), b'x');
+            buf.insert_slice(0, b"ab");
+        }
+        assert_eq!(buf.add_buffer.len(), 48);
+        assert_eq!(buf.add_buffer.as_ptr(), add_buffer);
+        assert_eq!(buf.pieces.as_ptr(), pieces);
+
+        buf.reserve_add(100);
+        assert!(buf.add_buffer.capacity() >= 148);
+    }
+
+    #[test]
+    fn should_count_lines_words_and_chars() {
+        let src = graphemes("fn main() {\n    println!(\"héllo\");\n}\n");
+        let mut buf = PtBuffer::new(&src);
+        buf.insert(11, " ".to_string());
+
+        assert_eq!(
+            buf.stats(),
+            DocStats {
+                lines: 4,
+                words: 5,
+                chars: 38,
+                bytes: 39,
+            }
+        );
+        assert_eq!(
+            buf.range_stats(17..),
+            DocStats {
+                lines: 3,
+                words: 2,
+                chars: 21,
+                bytes: 22,
+            }
+        );
+
+        let empty: PtBuffer<String> = PtBuffer::new(&[]);
+        assert_eq!(empty.stats().words, 0);
+        assert_eq!(empty.stats().lines, 1);
+    }
+
+    #[test]
+    fn should_hash_content_whatever_the_pieces() {
+        let mut edited = PtBuffer::new(b"Hello world");
+        edited.remove_range(5..6);
+        edited.insert_slice(5, b", ");
+        edited.push(b'!');
+        let mut built = PtBuffer::new(b"");
+        built.insert_slice(0, b"Hello, world!");
+
+        assert_ne!(edited.pieces.len(), built.pieces.len());
+        assert_eq!(edited.content_hash(), built.content_hash());
+        assert_eq!(
+            edited.content_hash(),
+            PtBuffer::new(b"Hello, world!").content_hash()
+        );
+
+        built.remove(12);
+        assert_ne!(edited.content_hash(), built.content_hash());
+
+        // Elements are hashed whole, not as their concatenation
+        let split = ["ab".to_string(), "c".to_string()];
+        let joined = ["a".to_string(), "bc".to_string()];
+        assert_ne!(
+            PtBuffer::new(&split).content_hash(),
+            PtBuffer::new(&joined).content_hash()
+        );
+    }
+
+    #[test]
+    fn should_bump_revision_on_changes() {
+        let mut buf = PtBuffer::new(b"Hello");
+        assert_eq!(buf.revision(), 0);
+
+        buf.insert(5, b'!');
+        buf.push(b'!');
+        buf.remove(0);
+        buf.insert_slice(0, b"Je");
+        buf.remove_range(0..2);
+        assert_eq!(buf.revision(), 5);
+
+        // Empty edits leave the content untouched
+        buf.insert_slice(0, b"");
+        buf.remove_range(2..2);
+        assert_eq!(buf.revision(), 5);
+    }
+
+    #[test]
+    fn should_adjust_index_to_edits() {
+        let buf = PtBuffer::new(b"Hello world");
+        let cases = [
+            // Insert before, at and after the tracked index
+            (Edit::Insert { at: 2, len: 3 }, 5, 8),
+            (Edit::Insert { at: 5, len: 3 }, 5, 8),
+            (Edit::Insert { at: 6, len: 3 }, 5, 5),
+            // Remove before, around, at the edges of and after the tracked index
+            (Edit::Remove { start: 1, end: 3 }, 5, 3),
+            (Edit::Remove { start: 3, end: 7 }, 5, 3),
+            (Edit::Remove { start: 5, end: 7 }, 5, 5),
+            (Edit::Remove { start: 3, end: 5 }, 5, 3),
+            (Edit::Remove { start: 6, end: 8 }, 5, 5),
+        ];
+
+        for (edit, idx, expected) in cases {
+            assert_eq!(buf.adjust_index(idx, &edit), expected, "{edit:?}");
+            assert_eq!(edit.adjust(idx), expected, "{edit:?}");
+        }
+    }
+
+    #[test]
+    fn should_clamp_index_after_removing_at_the_end() {
+        let mut buf = PtBuffer::new(b"Hello");
+        let cursor = buf.len();
+        assert_eq!(buf.clamp_index(cursor), 5);
+
+        buf.remove(4);
+        buf.remove_range(2..);
+        assert_eq!(buf.clamp_index(cursor), 2);
+        assert_eq!(buf.clamp_index(1), 1);
+
+        buf.remove_range(..);
+        assert_eq!(buf.clamp_index(cursor), 0);
+    }
+
+    #[test]
+    fn should_reuse_edits_across_backspace_and_delete() {
+        let mut buf = PtBuffer::new(b"Hello world");
+        // Type, backspace, type again at the logically next position
+        insert_str_at(&mut buf, 5, "!!");
+        buf.remove(6);
+        insert_str_at(&mut buf, 6, "?");
+        assert_buf_str(&buf, "Hello!? world");
+
+        // Backspace then delete forward must not reuse the backspace location
+        buf.remove(9);
+        buf.remove(8);
+        buf.remove(8);
+        assert_buf_str(&buf, "Hello!? ld");
+        assert_eq!(buf.len(), 10);
+
+        // Typing after a backspace must not resurrect the removed element
+        buf.insert(10, b'X');
+        buf.remove(10);
+        buf.insert(10, b'Y');
+        assert_buf_str(&buf, "Hello!? ldY");
+    }
+
+    #[test]
+    fn should_walk_pieces_from_the_last_index() {
+        let src = [b'a'; 100];
+        let mut buf = PtBuffer::new(&src);
+        for at in (10..100).step_by(10).rev() {
+            buf.insert(at, b'-');
+        }
+        let pieces = buf.pieces.len();
+        assert_eq!(pieces, 19);
+        SCANNED_PIECES.set(0);
+
+        // Every piece is stepped over once, where a lookup from the first piece would scan
+        // nine of them per element on average
+        let content: Vec<u8> = buf.iter().copied().collect();
+        let read: Vec<u8> = (0..buf.len()).map(|idx| buf[idx]).collect();
+        assert_eq!(read, content);
+        assert_eq!(SCANNED_PIECES.get(), pieces - 1);
+
+        let read: Vec<u8> = (0..buf.len()).rev().map(|idx| buf[idx]).collect();
+        assert!(read.iter().eq(content.iter().rev()));
+        assert_eq!(SCANNED_PIECES.get(), 2 * (pieces - 1));
+
+        // Edits leave the cached piece behind
+        buf.remove_range(5..25);
+        buf.insert(3, b'+');
+        let content: Vec<u8> = buf.iter().copied().collect();
+        let read: Vec<u8> = (0..buf.len()).map(|idx| buf[idx]).collect();
+        assert_eq!(read, content);
+        assert_eq!(buf.get(buf.len()), None);
+    }
+
+    #[test]
+    fn should_validate_after_edits() {
+        let src = graphemes("fn main() {  \n    let a = 1;\t\n\n}");
+        let mut buf = PtBuffer::new(&src);
+        assert_eq!(buf.validate(), Ok(()));
+
+        buf.insert(3, "x".to_string());
+        buf.insert(4, "y".to_string());
+        buf.remove(4);
+        buf.remove(0);
+        buf.push("!".to_string());
+        buf.insert_slice(10, &graphemes("abc"));
+        buf.remove_range(2..12);
+        buf.trim_trailing_whitespace();
+        buf.ensure_final_newline();
+        assert_eq!(buf.validate(), Ok(()));
+
+        buf.remove_range(..);
+        assert_eq!(buf.validate(), Ok(()));
+        buf.push("a".to_string());
+        assert_eq!(buf.validate(), Ok(()));
+    }
+
+    #[test]
+    fn should_report_broken_invariants() {
+        let mut buf = PtBuffer::new(b"Hello");
+        buf.length = 4;
+        assert_eq!(
+            buf.validate(),
+            Err(InvariantError::LengthMismatch {
+                pieces: 5,
+                length: 4
+            })
+        );
+
+        let mut buf = PtBuffer::new(b"Hello");
+        buf.pieces[0].start = 1;
+        assert_eq!(
+            buf.validate(),
+            Err(InvariantError::PieceOutOfBounds {
+                piece_idx: 0,
+                end: 6,
+                buffer_len: 5
+            })
+        );
+
+        let mut buf = PtBuffer::new(b"Hello");
+        buf.insert(2, b'!');
+        buf.pieces[1].length = 0;
+        assert_eq!(
+            buf.validate(),
+            Err(InvariantError::EmptyPiece { piece_idx: 1 })
+        );
+
+        let mut buf = PtBuffer::new(b"Hello");
+        buf.reusable_edit = ReusableEdit::Insert(0);
+        assert_eq!(
+            buf.validate(),
+            Err(InvariantError::StaleReusableEdit { piece_idx: 0 })
+        );
+    }
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Type(u8),
+        Backspace,
+        Delete,
+        Jump(usize),
+        Push(u8),
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            4 => (b'a'..=b'z').prop_map(Op::Type),
+            3 => Just(Op::Backspace),
+            1 => Just(Op::Delete),
+            1 => any::<usize>().prop_map(Op::Jump),
+            1 => (b'a'..=b'z').prop_map(Op::Push),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn edits_match_a_vec_model(
+            src in "[a-z\n]{0,20}",
+            ops in prop::collection::vec(op(), 0..100),
+        ) {
+            let mut buf = PtBuffer::new(src.as_bytes());
+            let mut model = src.as_bytes().to_vec();
+            let mut cursor = 0;
+
+            for op in ops {
+                match op {
+                    Op::Type(c) => {
+                        buf.insert(cursor, c);
+                        model.insert(cursor, c);
+                        cursor += 1;
+                    }
+                    Op::Backspace if cursor > 0 => {
+                        cursor -= 1;
+                        buf.remove(cursor);
+                        model.remove(cursor);
+                    }
+                    Op::Delete if cursor < model.len() => {
+                        buf.remove(cursor);
+                        model.remove(cursor);
+                    }
+                    Op::Jump(idx) => cursor = idx % (model.len() + 1),
+                    Op::Push(c) => {
+                        buf.push(c);
+                        model.push(c);
+                    }
+                    _ => {}
+                }
+
+                let content: Vec<u8> = buf.iter().copied().collect();
+                prop_assert_eq!(buf.validate(), Ok(()));
+                prop_assert_eq!(buf.len(), model.len());
+                prop_assert_eq!(content, model.clone());
+            }
+        }
+    }
+
+    fn assert_string_buf(buf: &PtBuffer<String>, s: &str) {
+        let x: Vec<&str> = buf.iter().map(String::as_str).collect();
+        assert_eq!(s, x.join(""));
+    }
+
     fn insert_str_at(buf: &mut PtBuffer<u8>, idx: usize, s: &str) {
         for (i, char) in s.bytes().enumerate() {
             buf.insert(idx + i, char)
@@ -0,0 +1,87 @@
+use std::ops::Range;
+
+use regex::Regex;
+
+use crate::PtBuffer;
+
+impl<'a> PtBuffer<'a, String> {
+    /// Element range of the first match of `re` starting at or after `from`.
+    ///
+    /// The text from `from` to the end is collected into a string for the regex to run
+    /// on, which costs a copy of it on every call. That is cheap enough for a file being
+    /// edited, [`PtBuffer::find_regex_in`] bounds the copy for large ones.
+    pub fn find_regex(&self, re: &Regex, from: usize) -> Option<(usize, usize)> {
+        self.find_regex_in(re, from..self.length)
+    }
+
+    /// Element range of the first match of `re` within `range`, only that region being
+    /// collected and searched.
+    ///
+    /// Nothing past the region is seen, anchors taking its bounds as the text bounds. `.`
+    /// stops at line endings unless the pattern sets `(?s)`.
+    pub fn find_regex_in(&self, re: &Regex, range: Range<usize>) -> Option<(usize, usize)> {
+        let range = range.start.min(self.length)..range.end.min(self.length);
+
+        // Byte offset of each element, to bring the match back to elements
+        let mut text = String::new();
+        let mut offsets = Vec::with_capacity(range.len() + 1);
+        for item in self.range(range.clone()) {
+            offsets.push(text.len());
+            text.push_str(item);
+        }
+        offsets.push(text.len());
+
+        let found = re.find(&text)?;
+        // A match starting or ending inside a grapheme takes the whole grapheme
+        let start = offsets.partition_point(|&offset| offset <= found.start()) - 1;
+        let end = offsets.partition_point(|&offset| offset < found.end());
+        let end = end.max(start + usize::from(!found.is_empty()));
+        Some((range.start + start, range.start + end))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use regex::Regex;
+
+    use crate::{graphemes, PtBuffer};
+
+    #[test]
+    fn should_find_regex_across_pieces() {
+        let src = graphemes("let café = 1;\nfn main() {}");
+        let mut buf = PtBuffer::new(&src);
+        let added = graphemes("fé");
+        // "caféfé" over an original, an add and an original piece
+        buf.insert_slice(6, &added);
+
+        let word = Regex::new(r"ca[fé]+").unwrap();
+        assert_eq!(buf.find_regex(&word, 0), Some((4, 10)));
+        assert_eq!(buf.find_regex(&word, 5), None);
+
+        let digits = Regex::new(r"\d+").unwrap();
+        assert_eq!(buf.find_regex(&digits, 0), Some((13, 14)));
+        assert_eq!(buf.find_regex_in(&digits, 0..13), None);
+
+        // Across the line ending only with (?s)
+        let across = Regex::new(r"1;.fn").unwrap();
+        assert_eq!(buf.find_regex(&across, 0), None);
+        let across = Regex::new(r"(?s)1;.fn").unwrap();
+        assert_eq!(buf.find_regex(&across, 0), Some((13, 18)));
+
+        // Anchors see the searched region as the whole text
+        let line_start = Regex::new(r"(?m)^\w+").unwrap();
+        assert_eq!(buf.find_regex(&line_start, 1), Some((1, 3)));
+        assert_eq!(buf.find_regex(&line_start, 4), Some((4, 10)));
+        assert_eq!(
+            buf.find_regex_in(&line_start, 11..buf.len()),
+            Some((16, 18))
+        );
+
+        let empty = Regex::new(r"x*").unwrap();
+        assert_eq!(buf.find_regex(&empty, 3), Some((3, 3)));
+        assert_eq!(
+            buf.find_regex(&empty, buf.len()),
+            Some((buf.len(), buf.len()))
+        );
+    }
+}
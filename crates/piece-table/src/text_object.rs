@@ -0,0 +1,306 @@
+use std::ops::Range;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::PtBuffer;
+
+/// What to select around a position, as resolved by [`PtBuffer::text_object`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextObjectKind {
+    /// A segment between Unicode word boundaries, the outer range taking the blanks around it
+    Word,
+    /// Text between two of the given quote on the line, the outer range including them
+    Quote(char),
+    /// Text between a bracket pair, given by either its opening or its closing bracket
+    Bracket(char),
+    /// A run of non blank lines, the outer range taking the blank lines around it
+    Paragraph,
+}
+
+/// Element ranges of a text object, `inner` being always within `outer`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextObject {
+    pub inner: Range<usize>,
+    pub outer: Range<usize>,
+}
+
+const BRACKETS: [(&str, &str); 4] = [("(", ")"), ("[", "]"), ("{", "}"), ("<", ">")];
+
+impl<'a> PtBuffer<'a, String> {
+    /// The `kind` of object around `at`, `None` when there is none or `at` is past the end.
+    pub fn text_object(&self, kind: TextObjectKind, at: usize) -> Option<TextObject> {
+        if at >= self.length {
+            return None;
+        }
+
+        match kind {
+            TextObjectKind::Word => self.word_object(at),
+            TextObjectKind::Quote(quote) => self.quote_object(&quote.to_string(), at),
+            TextObjectKind::Bracket(bracket) => {
+                let bracket = bracket.to_string();
+                let &(open, close) = BRACKETS
+                    .iter()
+                    .find(|(open, close)| *open == bracket || *close == bracket)?;
+                self.bracket_object(open, close, at)
+            }
+            TextObjectKind::Paragraph => Some(self.paragraph_object(at)),
+        }
+    }
+
+    /// Index of the bracket matching the one at `at`, nested pairs being skipped.
+    pub fn match_bracket(&self, at: usize) -> Option<usize> {
+        let item = self.get(at)?;
+        BRACKETS.iter().find_map(|&(open, close)| {
+            if item == open {
+                self.find_unmatched(at + 1, open, close, true)
+            } else if item == close {
+                self.find_unmatched(at, open, close, false)
+            } else {
+                None
+            }
+        })
+    }
+
+    /// First `close` after `from` (or `open` before it going backward) not balanced by
+    /// a bracket in between.
+    fn find_unmatched(&self, from: usize, open: &str, close: &str, forward: bool) -> Option<usize> {
+        let (target, nested) = if forward {
+            (close, open)
+        } else {
+            (open, close)
+        };
+        let mut depth = 0;
+        let mut scan = |(offset, item): (usize, &String)| {
+            if item == target {
+                if depth == 0 {
+                    return Some(offset);
+                }
+                depth -= 1;
+            } else if item == nested {
+                depth += 1;
+            }
+            None
+        };
+
+        if forward {
+            self.range(from..)
+                .enumerate()
+                .find_map(&mut scan)
+                .map(|offset| from + offset)
+        } else {
+            self.rev_range(self.length - from..self.length)
+                .enumerate()
+                .find_map(&mut scan)
+                .map(|offset| from - offset - 1)
+        }
+    }
+
+    /// Element range of the line holding `at`, excluding its line ending.
+    fn line_around(&self, at: usize) -> Range<usize> {
+        self.line_start(at)..self.line_end(at)
+    }
+
+    fn is_blank(&self, range: Range<usize>) -> bool {
+        self.range(range)
+            .all(|item| item.chars().all(char::is_whitespace))
+    }
+
+    fn word_object(&self, at: usize) -> Option<TextObject> {
+        let line = self.line_around(at);
+        if at == line.end {
+            return None;
+        }
+
+        // Byte offset of each element, to bring the word bounds back to elements
+        let mut text = String::new();
+        let mut offsets = vec![];
+        for item in self.range(line.clone()) {
+            offsets.push(text.len());
+            text.push_str(item);
+        }
+
+        let to_idx = |byte: usize| line.start + offsets.partition_point(|&offset| offset < byte);
+        let segments: Vec<Range<usize>> = text
+            .split_word_bound_indices()
+            .map(|(byte, word)| to_idx(byte)..to_idx(byte + word.len()))
+            .collect();
+        let current = segments.iter().position(|segment| segment.contains(&at))?;
+
+        let inner = segments[current].clone();
+        let next = segments.get(current + 1);
+        let prev = current.checked_sub(1).map(|prev| &segments[prev]);
+        let outer = if self.is_blank(inner.clone()) {
+            match next {
+                Some(next) => inner.start..next.end,
+                None => inner.clone(),
+            }
+        } else {
+            match (next, prev) {
+                (Some(next), _) if self.is_blank(next.clone()) => inner.start..next.end,
+                (_, Some(prev)) if self.is_blank(prev.clone()) => prev.start..inner.end,
+                _ => inner.clone(),
+            }
+        };
+
+        Some(TextObject { inner, outer })
+    }
+
+    fn quote_object(&self, quote: &str, at: usize) -> Option<TextObject> {
+        let line = self.line_around(at);
+        let mut escaped = false;
+        let mut quotes = vec![];
+        for (offset, item) in self.range(line.clone()).enumerate() {
+            if item == quote && !escaped {
+                quotes.push(line.start + offset);
+            }
+            escaped = item == "\\" && !escaped;
+        }
+
+        quotes
+            .chunks_exact(2)
+            .find(|pair| (pair[0]..=pair[1]).contains(&at))
+            .map(|pair| TextObject {
+                inner: pair[0] + 1..pair[1],
+                outer: pair[0]..pair[1] + 1,
+            })
+    }
+
+    fn bracket_object(&self, open: &str, close: &str, at: usize) -> Option<TextObject> {
+        let start = if self[at] == open {
+            at
+        } else {
+            self.find_unmatched(at, open, close, false)?
+        };
+        let end = self.match_bracket(start)?;
+
+        Some(TextObject {
+            inner: start + 1..end,
+            outer: start..end + 1,
+        })
+    }
+
+    /// Lines around `line` with the same blankness, from the first start to the last end.
+    fn line_run(&self, line: Range<usize>) -> Range<usize> {
+        let blank = self.is_blank(line.clone());
+        let mut run = line;
+        while run.start > 0 {
+            let prev = self.line_around(run.start - 1);
+            if self.is_blank(prev.clone()) != blank {
+                break;
+            }
+            run.start = prev.start;
+        }
+        while run.end < self.length {
+            let next = self.line_around(run.end + 1);
+            if self.is_blank(next.clone()) != blank {
+                break;
+            }
+            run.end = next.end;
+        }
+
+        run
+    }
+
+    fn paragraph_object(&self, at: usize) -> TextObject {
+        let run = self.line_run(self.line_around(at));
+        // Whole lines, with their line ending
+        let inner = run.start..(run.end + 1).min(self.length);
+        let outer = if run.end + 1 < self.length {
+            let after = self.line_run(self.line_around(run.end + 1));
+            inner.start..(after.end + 1).min(self.length)
+        } else if run.start > 0 {
+            let before = self.line_run(self.line_around(run.start - 1));
+            before.start..inner.end
+        } else {
+            inner.clone()
+        };
+
+        TextObject { inner, outer }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::text_object::{TextObject, TextObjectKind};
+    use crate::{graphemes, PtBuffer};
+
+    fn object(inner: std::ops::Range<usize>, outer: std::ops::Range<usize>) -> TextObject {
+        TextObject { inner, outer }
+    }
+
+    #[test]
+    fn should_resolve_words() {
+        let src = graphemes("let café = 1;\nnext");
+        let buf = PtBuffer::new(&src);
+        let word = |at| buf.text_object(TextObjectKind::Word, at);
+
+        // The blanks after the word, or before it at the end of the line
+        assert_eq!(word(4), Some(object(4..8, 4..9)));
+        assert_eq!(word(7), Some(object(4..8, 4..9)));
+        assert_eq!(word(0), Some(object(0..3, 0..4)));
+        assert_eq!(word(12), Some(object(12..13, 12..13)));
+        assert_eq!(word(11), Some(object(11..12, 10..12)));
+        // On blanks, the word after them
+        assert_eq!(word(8), Some(object(8..9, 8..10)));
+
+        assert_eq!(word(13), None);
+        assert_eq!(word(16), Some(object(14..18, 14..18)));
+        assert_eq!(word(18), None);
+    }
+
+    #[test]
+    fn should_resolve_quotes() {
+        let src = graphemes("say(\"hi \\\"you\\\"\", \"x\")\n\"open");
+        let buf = PtBuffer::new(&src);
+        let quote = |at| buf.text_object(TextObjectKind::Quote('"'), at);
+
+        let string = object(5..15, 4..16);
+        assert_eq!(quote(4), Some(string.clone()));
+        assert_eq!(quote(9), Some(string.clone()));
+        assert_eq!(quote(15), Some(string));
+        assert_eq!(quote(19), Some(object(19..20, 18..21)));
+
+        assert_eq!(quote(2), None);
+        assert_eq!(quote(16), None);
+        // No quote to pair with on that line
+        assert_eq!(quote(24), None);
+    }
+
+    #[test]
+    fn should_resolve_brackets() {
+        let src = graphemes("f(a, (b), [c])\n{\n  x\n}");
+        let buf = PtBuffer::new(&src);
+        let bracket = |c, at| buf.text_object(TextObjectKind::Bracket(c), at);
+
+        let call = object(2..13, 1..14);
+        assert_eq!(bracket('(', 1), Some(call.clone()));
+        assert_eq!(bracket('(', 3), Some(call.clone()));
+        assert_eq!(bracket(')', 13), Some(call.clone()));
+        // Nested pairs are skipped, unless inside them
+        assert_eq!(bracket('(', 11), Some(call));
+        assert_eq!(bracket('(', 6), Some(object(6..7, 5..8)));
+        assert_eq!(bracket('[', 12), Some(object(11..12, 10..13)));
+
+        // Across lines
+        assert_eq!(bracket('}', 18), Some(object(16..21, 15..22)));
+        assert_eq!(bracket('(', 0), None);
+        assert_eq!(bracket('"', 3), None);
+
+        assert_eq!(buf.match_bracket(5), Some(7));
+        assert_eq!(buf.match_bracket(13), Some(1));
+        assert_eq!(buf.match_bracket(0), None);
+    }
+
+    #[test]
+    fn should_resolve_paragraphs() {
+        let src = graphemes("a\nb\n\n  \nc\n");
+        let buf = PtBuffer::new(&src);
+        let paragraph = |at| buf.text_object(TextObjectKind::Paragraph, at);
+
+        assert_eq!(paragraph(0), Some(object(0..4, 0..8)));
+        assert_eq!(paragraph(2), Some(object(0..4, 0..8)));
+        assert_eq!(paragraph(5), Some(object(4..8, 4..10)));
+        // The last paragraph takes the blank lines before it
+        assert_eq!(paragraph(8), Some(object(8..10, 4..10)));
+    }
+}
@@ -0,0 +1,120 @@
+use piece_table::graphemes;
+
+use crate::selection::Selection;
+use crate::Editor;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Case {
+    Upper,
+    Lower,
+    /// Uppercase letters become lowercase and the other way around
+    Swap,
+}
+
+/// `text` in `case`, characters with no case being left alone. A character can map to
+/// several ones (`ß` uppercases to `SS`), so the result can be longer than `text`.
+pub fn change_case(text: &str, case: Case) -> String {
+    match case {
+        Case::Upper => text.to_uppercase(),
+        Case::Lower => text.to_lowercase(),
+        Case::Swap => text
+            .chars()
+            .flat_map(|c| {
+                let swapped: Vec<char> = if c.is_uppercase() {
+                    c.to_lowercase().collect()
+                } else {
+                    c.to_uppercase().collect()
+                };
+                swapped
+            })
+            .collect(),
+    }
+}
+
+impl Editor<'_> {
+    /// Change the case of the selection, which then covers the changed text, or of the
+    /// grapheme under the cursor. Returns whether anything changed.
+    pub(crate) fn change_case(&mut self, case: Case) -> bool {
+        let range = match self.selection {
+            Some(selection) => selection.range(),
+            None => {
+                let pos = self.get_cursor_absolute_position();
+                pos..self.doc.clamp_index(pos + 1)
+            }
+        };
+
+        let text: String = self.doc.range(range.clone()).map(String::as_str).collect();
+        let changed = change_case(&text, case);
+        if changed == text {
+            return false;
+        }
+
+        let items = graphemes(&changed);
+        let cursor = self.get_cursor_absolute_position();
+        let changed_range = range.start..range.start + items.len();
+        self.replace_range(range, &items);
+
+        let cursor = match self.selection {
+            Some(selection) => {
+                let selection = if selection.head < selection.anchor {
+                    Selection::new(changed_range.end, changed_range.start)
+                } else {
+                    Selection::new(changed_range.start, changed_range.end)
+                };
+                self.selection = Some(selection);
+                selection.head
+            }
+            None => cursor,
+        };
+        self.move_cursor_to_idx(cursor);
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use piece_table::graphemes;
+
+    use crate::case::{change_case, Case};
+    use crate::selection::Selection;
+    use crate::{Command, Editor};
+
+    #[test]
+    fn change_case_of_text() {
+        assert_eq!(change_case("Hello, World", Case::Upper), "HELLO, WORLD");
+        assert_eq!(change_case("Hello, World", Case::Lower), "hello, world");
+        assert_eq!(change_case("Hello, World", Case::Swap), "hELLO, wORLD");
+        assert_eq!(change_case("ÉTÉ été", Case::Swap), "été ÉTÉ");
+
+        // Longer once uppercased
+        assert_eq!(change_case("straße", Case::Upper), "STRASSE");
+        assert_eq!(change_case("Maß", Case::Swap), "mASS");
+        assert_eq!(change_case("İ", Case::Lower), "i\u{307}");
+    }
+
+    #[test]
+    fn change_case_of_the_selection_or_cursor() {
+        let src = graphemes("die straße\nx");
+        let mut editor = Editor::headless(&src);
+
+        // The grapheme under the cursor, the cursor staying on it
+        let output = editor.run_script([Command::ToUpper]);
+        assert_eq!(output, "Die straße\nx");
+        assert_eq!(editor.get_cursor_absolute_position(), 0);
+        assert!(!editor.apply(Command::ToUpper));
+
+        // The selection grows with the text
+        editor.selection = Some(Selection::new(10, 4));
+        let output = editor.run_script([Command::ToUpper]);
+        assert_eq!(output, "Die STRASSE\nx");
+        assert_eq!(editor.selection, Some(Selection::new(11, 4)));
+        assert_eq!(editor.get_cursor_absolute_position(), 4);
+
+        let output = editor.run_script([Command::SwapCase]);
+        assert_eq!(output, "Die strasse\nx");
+        let output = editor.run_script([Command::Cancel, Command::MoveRight, Command::SwapCase]);
+        assert_eq!(output, "Die sTrasse\nx");
+        let output = editor.run_script([Command::ToLower]);
+        assert_eq!(output, "Die strasse\nx");
+    }
+}
@@ -0,0 +1,19 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+/// OSC 52 escape sequence asking the terminal to put `text` in the system clipboard.
+/// This goes through the terminal itself, so it also works over SSH.
+pub fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", STANDARD.encode(text))
+}
+
+#[cfg(test)]
+mod test {
+    use crate::clipboard::osc52;
+
+    #[test]
+    fn encode_osc52_sequence() {
+        assert_eq!(osc52("hello"), "\x1b]52;c;aGVsbG8=\x07");
+        assert_eq!(osc52("fn 🦀()\n"), "\x1b]52;c;Zm4g8J+mgCgpCg==\x07");
+    }
+}
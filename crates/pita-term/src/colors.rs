@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::{env, fs, io};
+
+use crossterm::style::Color;
+use serde::Deserialize;
+
+use crate::log::Level;
+
+/// Colors of every part of the UI. Colors are written as names (`dark_grey`), `#rrggbb`,
+/// `rgb_(r,g,b)` or `ansi_(n)`, missing entries keeping their default.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ColorScheme {
+    pub fg: Color,
+    pub bg: Color,
+    pub selection: Color,
+    pub whitespace: Color,
+    pub trailing_whitespace: Color,
+    pub scrollbar_thumb: Color,
+    /// Background of the ruler columns
+    pub ruler: Color,
+    pub status_fg: Color,
+    pub status_bg: Color,
+    /// Foreground of info log lines
+    pub log_fg: Color,
+    pub log_bg: Color,
+    pub log_debug: Color,
+    pub log_warn: Color,
+    pub log_error: Color,
+    pub popup_fg: Color,
+    pub popup_bg: Color,
+    /// Foreground of highlighted text by capture name, `function.method` falling back
+    /// to `function` when it has no color of its own
+    pub syntax: HashMap<String, Color>,
+}
+
+impl Default for ColorScheme {
+    fn default() -> Self {
+        let syntax = [
+            ("type", Color::from((129, 200, 190))),
+            // Builtin types are left in the default foreground rather than falling back
+            ("type.builtin", Color::White),
+            ("constructor", Color::from((229, 200, 144))),
+            ("punctuation.bracket", Color::from((239, 159, 118))),
+            ("punctuation.delimiter", Color::from((231, 130, 132))),
+            ("keyword", Color::from((234, 153, 156))),
+            ("label", Color::from((244, 184, 228))),
+            ("operator", Color::from((202, 158, 230))),
+        ]
+        .into_iter()
+        .map(|(name, color)| (name.to_string(), color))
+        .collect();
+
+        Self {
+            fg: Color::White,
+            bg: Color::from((59, 56, 73)),
+            selection: Color::from((88, 91, 112)),
+            whitespace: Color::from((110, 108, 126)),
+            trailing_whitespace: Color::from((120, 60, 72)),
+            scrollbar_thumb: Color::from((128, 132, 156)),
+            ruler: Color::from((66, 63, 82)),
+            status_fg: Color::White,
+            status_bg: Color::DarkGrey,
+            log_fg: Color::Grey,
+            log_bg: Color::Black,
+            log_debug: Color::DarkGrey,
+            log_warn: Color::Yellow,
+            log_error: Color::Red,
+            popup_fg: Color::White,
+            popup_bg: Color::Black,
+            syntax,
+        }
+    }
+}
+
+impl ColorScheme {
+    pub fn from_toml(src: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(src)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let src = fs::read_to_string(path)?;
+        Self::from_toml(&src).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+    }
+
+    /// Foreground of log lines at `level`.
+    pub fn log(&self, level: Level) -> Color {
+        match level {
+            Level::Debug => self.log_debug,
+            Level::Info => self.log_fg,
+            Level::Warn => self.log_warn,
+            Level::Error => self.log_error,
+        }
+    }
+
+    /// Foreground of text captured as `name`, the default one outside of any capture.
+    pub fn highlight(&self, name: Option<&str>) -> Color {
+        let mut name = name;
+        while let Some(capture) = name {
+            if let Some(color) = self.syntax.get(capture) {
+                return *color;
+            }
+
+            name = capture.rsplit_once('.').map(|(parent, _)| parent);
+        }
+
+        self.fg
+    }
+}
+
+/// How many colors the terminal can show, colors beyond it being brought down to the
+/// closest one it has when presented.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ColorSupport {
+    Ansi16,
+    Ansi256,
+    TrueColor,
+}
+
+/// The 16 named colors as xterm shows them by default.
+const ANSI16: [(Color, (u8, u8, u8)); 16] = [
+    (Color::Black, (0, 0, 0)),
+    (Color::DarkRed, (128, 0, 0)),
+    (Color::DarkGreen, (0, 128, 0)),
+    (Color::DarkYellow, (128, 128, 0)),
+    (Color::DarkBlue, (0, 0, 128)),
+    (Color::DarkMagenta, (128, 0, 128)),
+    (Color::DarkCyan, (0, 128, 128)),
+    (Color::Grey, (192, 192, 192)),
+    (Color::DarkGrey, (128, 128, 128)),
+    (Color::Red, (255, 0, 0)),
+    (Color::Green, (0, 255, 0)),
+    (Color::Yellow, (255, 255, 0)),
+    (Color::Blue, (0, 0, 255)),
+    (Color::Magenta, (255, 0, 255)),
+    (Color::Cyan, (0, 255, 255)),
+    (Color::White, (255, 255, 255)),
+];
+
+/// Channel levels of the 6x6x6 color cube of the 256 color palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+impl ColorSupport {
+    /// Read from `$COLORTERM`, then from `$TERM` naming a 256 color terminal.
+    pub fn detect() -> Self {
+        let colorterm = env::var("COLORTERM").unwrap_or_default();
+        let term = env::var("TERM").unwrap_or_default();
+        Self::from_env(&colorterm, &term)
+    }
+
+    fn from_env(colorterm: &str, term: &str) -> Self {
+        if matches!(colorterm, "truecolor" | "24bit") || term.ends_with("-direct") {
+            ColorSupport::TrueColor
+        } else if term.contains("256color") {
+            ColorSupport::Ansi256
+        } else {
+            ColorSupport::Ansi16
+        }
+    }
+
+    /// `color`, or the closest one the terminal can show.
+    pub fn fit(self, color: Color) -> Color {
+        match (self, color) {
+            (ColorSupport::TrueColor, _) => color,
+            (ColorSupport::Ansi256, Color::Rgb { r, g, b }) => {
+                Color::AnsiValue(to_ansi256(r, g, b))
+            }
+            (ColorSupport::Ansi16, Color::Rgb { r, g, b }) => to_ansi16(r, g, b),
+            (ColorSupport::Ansi16, Color::AnsiValue(n)) if n >= 16 => {
+                let (r, g, b) = ansi256_rgb(n);
+                to_ansi16(r, g, b)
+            }
+            _ => color,
+        }
+    }
+}
+
+fn distance((r1, g1, b1): (u8, u8, u8), (r2, g2, b2): (u8, u8, u8)) -> u32 {
+    let d = |a: u8, b: u8| (i32::from(a) - i32::from(b)).unsigned_abs().pow(2);
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+/// Index of the closest entry of the 256 color palette to `(r, g, b)`, among the color
+/// cube and the grey ramp, leaving out the first 16 whose actual colors vary by terminal.
+pub fn to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    let level = |c: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|&i| CUBE_LEVELS[i].abs_diff(c))
+            .unwrap() as u8
+    };
+    let (ri, gi, bi) = (level(r), level(g), level(b));
+    let cube = 16 + 36 * ri + 6 * gi + bi;
+
+    let average = (u16::from(r) + u16::from(g) + u16::from(b)) / 3;
+    let grey = 232 + (average.saturating_sub(3) / 10).min(23) as u8;
+
+    if distance((r, g, b), ansi256_rgb(grey)) < distance((r, g, b), ansi256_rgb(cube)) {
+        grey
+    } else {
+        cube
+    }
+}
+
+/// Closest of the 16 named colors to `(r, g, b)`.
+pub fn to_ansi16(r: u8, g: u8, b: u8) -> Color {
+    ANSI16
+        .iter()
+        .min_by_key(|(_, rgb)| distance((r, g, b), *rgb))
+        .map(|(color, _)| *color)
+        .unwrap()
+}
+
+/// The color xterm shows for entry `n` of the 256 color palette.
+fn ansi256_rgb(n: u8) -> (u8, u8, u8) {
+    match n {
+        0..=15 => ANSI16[usize::from(n)].1,
+        16..=231 => {
+            let n = n - 16;
+            let level = |i: u8| CUBE_LEVELS[usize::from(i)];
+            (level(n / 36), level(n / 6 % 6), level(n % 6))
+        }
+        _ => {
+            let grey = 8 + 10 * (n - 232);
+            (grey, grey, grey)
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME/pita/colors.toml`, or under `~/.config` when it is unset.
+pub fn scheme_path() -> Option<PathBuf> {
+    let config_dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_dir.join("pita").join("colors.toml"))
+}
+
+#[cfg(test)]
+mod test {
+    use crossterm::style::Color;
+
+    use crate::colors::{to_ansi16, to_ansi256, ColorScheme, ColorSupport};
+
+    #[test]
+    fn parse_scheme() {
+        let scheme = ColorScheme::from_toml(
+            r##"
+            bg = "#1e1e2e"
+            status_bg = "dark_blue"
+
+            [syntax]
+            function = "rgb_(137,180,250)"
+            keyword = "ansi_(5)"
+            "##,
+        )
+        .unwrap();
+
+        assert_eq!(scheme.bg, Color::from((30, 30, 46)));
+        assert_eq!(scheme.status_bg, Color::DarkBlue);
+        assert_eq!(scheme.selection, ColorScheme::default().selection);
+
+        assert_eq!(
+            scheme.highlight(Some("function.method")),
+            Color::from((137, 180, 250))
+        );
+        assert_eq!(scheme.highlight(Some("keyword")), Color::AnsiValue(5));
+        // The syntax table replaces the default one
+        assert_eq!(scheme.highlight(Some("type")), scheme.fg);
+        assert_eq!(scheme.highlight(None), Color::White);
+
+        assert!(ColorScheme::from_toml("bg = \"not a color\"").is_err());
+    }
+
+    #[test]
+    fn default_syntax_colors() {
+        let scheme = ColorScheme::default();
+        assert_eq!(scheme.highlight(Some("type")), Color::from((129, 200, 190)));
+        assert_eq!(scheme.highlight(Some("type.builtin")), Color::White);
+        assert_eq!(
+            scheme.highlight(Some("constructor")),
+            Color::from((229, 200, 144))
+        );
+        assert_eq!(scheme.highlight(Some("function.method")), Color::White);
+    }
+
+    #[test]
+    fn downsample_to_the_closest_palette_entry() {
+        // Cube corners and levels
+        assert_eq!(to_ansi256(0, 0, 0), 16);
+        assert_eq!(to_ansi256(255, 255, 255), 231);
+        assert_eq!(to_ansi256(255, 0, 0), 196);
+        assert_eq!(to_ansi256(95, 135, 175), 67);
+        assert_eq!(to_ansi256(100, 130, 180), 67);
+        // Greys go to the ramp unless a cube entry is closer
+        assert_eq!(to_ansi256(128, 128, 128), 244);
+        assert_eq!(to_ansi256(8, 8, 8), 232);
+        assert_eq!(to_ansi256(59, 56, 73), 237);
+
+        assert_eq!(to_ansi16(250, 10, 20), Color::Red);
+        assert_eq!(to_ansi16(120, 120, 120), Color::DarkGrey);
+        assert_eq!(to_ansi16(30, 20, 40), Color::Black);
+
+        let rgb = Color::from((255, 0, 0));
+        assert_eq!(ColorSupport::TrueColor.fit(rgb), rgb);
+        assert_eq!(ColorSupport::Ansi256.fit(rgb), Color::AnsiValue(196));
+        assert_eq!(ColorSupport::Ansi16.fit(rgb), Color::Red);
+        assert_eq!(
+            ColorSupport::Ansi16.fit(Color::AnsiValue(231)),
+            Color::White
+        );
+        assert_eq!(
+            ColorSupport::Ansi16.fit(Color::AnsiValue(4)),
+            Color::AnsiValue(4)
+        );
+        assert_eq!(ColorSupport::Ansi16.fit(Color::Cyan), Color::Cyan);
+    }
+
+    #[test]
+    fn detect_color_support() {
+        assert_eq!(
+            ColorSupport::from_env("truecolor", "xterm"),
+            ColorSupport::TrueColor
+        );
+        assert_eq!(
+            ColorSupport::from_env("", "xterm-direct"),
+            ColorSupport::TrueColor
+        );
+        assert_eq!(
+            ColorSupport::from_env("", "tmux-256color"),
+            ColorSupport::Ansi256
+        );
+        assert_eq!(ColorSupport::from_env("", "xterm"), ColorSupport::Ansi16);
+    }
+}
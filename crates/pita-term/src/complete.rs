@@ -0,0 +1,118 @@
+use std::collections::HashMap;
+
+use piece_table::PtBuffer;
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Words of the document starting with the word typed right before `pos`, the most
+/// frequent first and ties going to the closest occurrence. The word being typed is
+/// not a candidate on its own.
+pub fn complete_word_at(doc: &PtBuffer<String>, pos: usize) -> Vec<String> {
+    let content = doc.range(..).collect_string();
+    let cursor = doc.range(0..pos.min(doc.len())).map(String::len).sum();
+    let prefix = word_before(&content, cursor);
+
+    // Occurrence count and distance to the closest one of each candidate
+    let mut candidates: HashMap<&str, (usize, usize)> = HashMap::new();
+    for (start, word) in words(&content) {
+        let end = start + word.len();
+        if start < cursor && cursor <= end {
+            continue;
+        }
+
+        if word == prefix || !word.starts_with(prefix) {
+            continue;
+        }
+
+        let distance = if end <= cursor {
+            cursor - end
+        } else {
+            start - cursor
+        };
+        let (count, closest) = candidates.entry(word).or_insert((0, usize::MAX));
+        *count += 1;
+        *closest = (*closest).min(distance);
+    }
+
+    let mut candidates: Vec<_> = candidates.into_iter().collect();
+    candidates.sort_by(|(a, (a_count, a_closest)), (b, (b_count, b_closest))| {
+        b_count
+            .cmp(a_count)
+            .then(a_closest.cmp(b_closest))
+            .then(a.cmp(b))
+    });
+    candidates
+        .into_iter()
+        .map(|(word, _)| word.to_string())
+        .collect()
+}
+
+/// Number of graphemes of the word typed right before `pos`, replaced by a completion.
+pub fn prefix_len(doc: &PtBuffer<String>, pos: usize) -> usize {
+    let content = doc.range(..).collect_string();
+    let cursor = doc.range(0..pos.min(doc.len())).map(String::len).sum();
+    word_before(&content, cursor).graphemes(true).count()
+}
+
+/// Start of the word ending or being cut at the byte offset `cursor`.
+fn word_before(content: &str, cursor: usize) -> &str {
+    words(content)
+        .find(|(start, word)| *start < cursor && cursor <= start + word.len())
+        .map_or("", |(start, _)| &content[start..cursor])
+}
+
+/// Unicode words with their byte offset, split again at inner punctuation to keep
+/// `items.iter` or `it's` apart the way code reads them.
+fn words(content: &str) -> impl Iterator<Item = (usize, &str)> {
+    content.unicode_word_indices().flat_map(|(offset, word)| {
+        word.split(|c: char| !c.is_alphanumeric() && c != '_')
+            .filter(|part| !part.is_empty())
+            .map(move |part| {
+                (
+                    offset + part.as_ptr() as usize - word.as_ptr() as usize,
+                    part,
+                )
+            })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use piece_table::{graphemes, PtBuffer};
+
+    use crate::complete::{complete_word_at, prefix_len};
+
+    #[test]
+    fn complete_the_word_before_the_cursor() {
+        let src =
+            graphemes("let item = items.iter();\nfor it in items {\n    ite\n}\nfn idle() {}\n");
+        let doc = PtBuffer::new(&src);
+        // Right after "ite"
+        let pos = 50;
+        assert_eq!(doc.range(pos - 3..pos).cloned().collect::<String>(), "ite");
+
+        // "items" is seen twice, then "iter" being closer than "item"
+        assert_eq!(complete_word_at(&doc, pos), vec!["items", "iter", "item"]);
+        assert_eq!(prefix_len(&doc, pos), 3);
+
+        // Cutting a word in the middle completes its start
+        assert_eq!(complete_word_at(&doc, 6), vec!["items", "iter", "ite"]);
+        assert_eq!(prefix_len(&doc, 6), 2);
+
+        // Nothing typed yet, every word is a candidate
+        let doc = PtBuffer::new(&src[..8]);
+        assert_eq!(complete_word_at(&doc, 4), vec!["item", "let"]);
+        assert_eq!(prefix_len(&doc, 4), 0);
+    }
+
+    #[test]
+    fn complete_non_ascii_words() {
+        let src = graphemes("café caféine ca");
+        let doc = PtBuffer::new(&src);
+
+        assert_eq!(complete_word_at(&doc, src.len()), vec!["caféine", "café"]);
+        assert_eq!(prefix_len(&doc, src.len()), 2);
+        assert_eq!(complete_word_at(&doc, 4), vec!["caféine"]);
+        // A word equal to the prefix completes nothing
+        assert_eq!(complete_word_at(&doc, 2), vec!["caféine"]);
+    }
+}
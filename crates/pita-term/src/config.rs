@@ -0,0 +1,66 @@
+use std::time::Duration;
+
+use crate::colors::ColorScheme;
+use crate::indent::IndentStyle;
+use crate::log::Level;
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Strip trailing spaces and tabs from every line before writing the file
+    pub trim_trailing_whitespace: bool,
+    /// Make sure the file ends with exactly one line ending when writing it
+    pub ensure_final_newline: bool,
+    /// Pad lines shorter than the block column with spaces on block insert instead of skipping them
+    pub block_pad_short_lines: bool,
+    /// Show the viewport position on the right edge of the editor
+    pub scrollbar: bool,
+    /// Lines of context kept visible above and below the cursor
+    pub scrolloff: usize,
+    /// Keep the cursor on the middle row, scrolling as it moves
+    pub center_cursor: bool,
+    /// Columns, counted from 1, tinted past the end of every line as a line length guide
+    pub rulers: Vec<usize>,
+    /// Show spaces as `·` and tabs as `→`, highlighting trailing whitespace
+    pub render_whitespace: bool,
+    /// Copy yanked text to the system clipboard with the OSC 52 escape sequence
+    pub osc52_clipboard: bool,
+    /// Files larger than this many bytes are opened without syntax highlighting
+    pub highlight_max_bytes: usize,
+    /// Only keep the highlights of the lines on screen rather than the whole document
+    pub highlight_visible_only: bool,
+    /// Indentation inserted by tab, detected from the file when unset
+    pub indent: Option<IndentStyle>,
+    /// Periodically write unsaved changes to a swap file next to the edited one
+    pub autosave: bool,
+    pub autosave_interval: Duration,
+    /// Write the file after this long without input while it has unsaved changes, zero
+    /// turning it off
+    pub idle_save_delay: Duration,
+    pub colors: ColorScheme,
+    /// Log lines below this level are kept but not shown
+    pub log_level: Level,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            trim_trailing_whitespace: false,
+            ensure_final_newline: false,
+            block_pad_short_lines: false,
+            scrollbar: true,
+            scrolloff: 0,
+            center_cursor: false,
+            rulers: vec![],
+            render_whitespace: false,
+            osc52_clipboard: false,
+            highlight_max_bytes: 1024 * 1024,
+            highlight_visible_only: true,
+            indent: None,
+            autosave: true,
+            autosave_interval: Duration::from_secs(5),
+            idle_save_delay: Duration::ZERO,
+            colors: ColorScheme::default(),
+            log_level: Level::Info,
+        }
+    }
+}
@@ -1,75 +1,451 @@
-use std::cmp::min;
+use piece_table::PtBuffer;
 
+use crate::lines::LineIndex;
+use crate::log::Level;
+use crate::screen::cell_width;
 use crate::Editor;
 
 impl Editor<'_> {
-    pub(crate) fn cursor_left(&self) -> bool {
-        let (mut x, y) = self.editor_screen.cursor();
-        if x == 0 {
-            x = self.line_endings[y].saturating_sub(1);
-            let y = y.saturating_sub(1);
-            if y == 0 {
-                self.editor_screen.dec_offset();
-            }
-
-            self.editor_screen.set_cursor(x, y);
-            true
+    /// Place the cursor on `column` of the visible row `y`, clamped to the line length.
+    pub(crate) fn set_cursor_column(&self, column: usize, y: usize) {
+        let line = y + self.editor_screen.line_offset();
+        let (idx, x) = match self.lines.line_range(&self.doc, line) {
+            Some(range) => (
+                range.start + column.min(range.len()),
+                display_x(self.doc.range(range), column),
+            ),
+            None => (self.doc.len(), 0),
+        };
+        self.cursor.set(idx);
+        self.editor_screen.set_cursor(x, y);
+    }
+
+    fn line_len(&self, line: usize) -> usize {
+        self.lines
+            .line_range(&self.doc, line)
+            .map_or(0, |range| range.len())
+    }
+
+    fn has_line(&self, line: usize) -> bool {
+        self.lines.line_range(&self.doc, line).is_some()
+    }
+
+    /// Move the cursor to `column` of the document `line`, scrolling to keep it in view.
+    /// Returns whether the viewport moved.
+    pub(crate) fn move_cursor_to(&self, line: usize, column: usize) -> bool {
+        let redraw = self.scroll_to_cursor(line);
+        self.set_cursor_column(column, line - self.editor_screen.line_offset());
+        redraw
+    }
+
+    /// Move the cursor to the document index `idx`. Returns whether the viewport moved.
+    pub(crate) fn move_cursor_to_idx(&self, idx: usize) -> bool {
+        let line = self.lines.line_of(&self.doc, idx);
+        let column = idx - self.lines.line_start(&self.doc, line).unwrap_or_default();
+        self.move_cursor_to(line, column)
+    }
+
+    /// Adjust the line offset so the cursor `line` is visible, honoring `scrolloff`
+    /// and `center_cursor`. Returns whether the viewport moved.
+    pub(crate) fn scroll_to_cursor(&self, line: usize) -> bool {
+        let offset = self.editor_screen.line_offset();
+        let new_offset = scroll_offset(
+            line,
+            offset,
+            self.editor_screen.height(),
+            self.lines.line_count(&self.doc),
+            self.config.scrolloff,
+            self.config.center_cursor,
+        );
+
+        self.editor_screen.set_line_offset(new_offset);
+        new_offset != offset
+    }
+
+    /// Scroll so the cursor line sits on the top, middle or bottom row, the cursor
+    /// staying on the same document position. Returns whether the viewport moved.
+    pub(crate) fn align_cursor(&self, align: ViewAlign) -> bool {
+        let (line, column) = self.cursor_line_column();
+        let offset = self.editor_screen.line_offset();
+        let new_offset = aligned_offset(
+            line,
+            self.editor_screen.height(),
+            self.lines.line_count(&self.doc),
+            align,
+        );
+
+        self.editor_screen.set_line_offset(new_offset);
+        self.set_cursor_column(column, line - new_offset);
+        new_offset != offset
+    }
+
+    /// Move the cursor to the start of the document with the first screenful shown, or to
+    /// its end with the last one shown. Returns whether the viewport moved.
+    pub(crate) fn goto_edge(&self, bottom: bool) -> bool {
+        let (line, align) = if bottom {
+            (self.lines.line_count(&self.doc) - 1, ViewAlign::Bottom)
+        } else {
+            (0, ViewAlign::Top)
+        };
+        let offset = self.editor_screen.line_offset();
+        let height = self.editor_screen.height();
+        let new_offset = aligned_offset(line, height, self.lines.line_count(&self.doc), align);
+
+        self.editor_screen.set_line_offset(new_offset);
+        let column = if bottom { self.line_len(line) } else { 0 };
+        self.set_cursor_column(column, line - new_offset);
+        new_offset != offset
+    }
+
+    /// Scroll the view by one line, the cursor keeping its line unless it would leave the
+    /// screen. Returns whether the viewport moved.
+    pub(crate) fn scroll_line(&self, down: bool) -> bool {
+        let (line, column) = self.cursor_line_column();
+        let offset = self.editor_screen.line_offset();
+        let (new_offset, new_line) = scroll_by_line(
+            line,
+            offset,
+            self.editor_screen.height(),
+            self.lines.line_count(&self.doc),
+            down,
+        );
+
+        self.editor_screen.set_line_offset(new_offset);
+        if new_line == line {
+            self.set_cursor_column(column, line - new_offset);
         } else {
-            self.editor_screen.set_cursor(x.saturating_sub(1), y);
+            let column = self.column_under_cursor_x(new_line);
+            self.set_cursor_column(column, new_line - new_offset);
+        }
+        new_offset != offset
+    }
+
+    pub(crate) fn cursor_left(&self) -> bool {
+        let (line, column) = self.cursor_line_column();
+
+        if column > 0 {
+            self.move_cursor_to(line, column - 1)
+        } else if line == 0 {
             false
+        } else {
+            self.move_cursor_to(line - 1, self.line_len(line - 1))
         }
     }
 
     pub(crate) fn cursor_right(&self) -> bool {
-        let (x, y) = self.editor_screen.cursor();
-        let ending = self.line_endings[y + 1];
-
-        if x >= ending - 1 {
-            let y = y + 1;
-            let redraw = if y > self.editor_screen.height() - 1 {
-                self.editor_screen.inc_offset();
-                true
-            } else {
-                false
-            };
-            self.editor_screen.set_cursor(0, y);
-            redraw
-        } else {
-            self.editor_screen.set_cursor(x + 1, y);
+        let (line, column) = self.cursor_line_column();
+
+        if column < self.line_len(line) {
+            self.move_cursor_to(line, column + 1)
+        } else if !self.has_line(line + 1) {
             false
+        } else {
+            self.move_cursor_to(line + 1, 0)
         }
     }
 
     pub(crate) fn cursor_down(&self) -> bool {
-        let (mut x, mut y) = self.editor_screen.cursor();
-        y = min(y + 1, self.line_endings.len() - 1);
-        let ending = self.line_endings[y + 1];
+        let (line, _) = self.cursor_line_column();
+        if !self.has_line(line + 1) {
+            return false;
+        }
+
+        self.move_cursor_to(line + 1, self.column_under_cursor_x(line + 1))
+    }
 
-        if x >= ending {
-            x = ending - 1;
+    pub(crate) fn cursor_up(&self) -> bool {
+        let (x, y) = self.editor_screen.cursor();
+        self.log(Level::Debug, format!("moving to {x}:{y}"));
+        let (line, _) = self.cursor_line_column();
+        if line == 0 {
+            return false;
         }
 
-        if y > self.editor_screen.height() - 1 {
-            self.editor_screen.inc_offset();
-            true
-        } else {
-            self.editor_screen.set_cursor(x, y);
-            false
+        self.move_cursor_to(line - 1, self.column_under_cursor_x(line - 1))
+    }
+
+    /// Move to the visible row `y`, as close as possible to the display column `x`.
+    pub(crate) fn move_to_row(&self, x: usize, y: usize) {
+        let line = y + self.editor_screen.line_offset();
+        let column = self
+            .lines
+            .line_range(&self.doc, line)
+            .map_or(0, |range| column_at(self.doc.range(range), x));
+        self.set_cursor_column(column, y);
+    }
+
+    /// Move the cursor to the start of the next or previous blank line, or to the document
+    /// edge when there is none. Returns whether the viewport moved.
+    pub(crate) fn jump_paragraph(&self, forward: bool) -> bool {
+        let (line, _) = self.cursor_line_column();
+        match paragraph_boundary(&self.doc, &self.lines, line, forward) {
+            Some(line) => self.move_cursor_to(line, 0),
+            None if forward => self.move_cursor_to_idx(self.doc.len()),
+            None => self.move_cursor_to(0, 0),
         }
     }
 
-    pub(crate) fn cursor_up(&self) -> bool {
-        let (mut x, mut y) = self.editor_screen.cursor();
-        self.log(format!("moving to {x}:{y}"));
-        if y == 0 {
-            self.editor_screen.dec_offset();
-            true
-        } else {
-            let ending = self.line_endings[y];
-            y = y - 1;
-            x = ending - 1;
-            self.editor_screen.set_cursor(x, y);
-            false
+    /// Column of `line` closest to the current cursor display column.
+    fn column_under_cursor_x(&self, line: usize) -> usize {
+        let (x, _) = self.editor_screen.cursor();
+        self.lines
+            .line_range(&self.doc, line)
+            .map_or(0, |range| column_at(self.doc.range(range), x))
+    }
+}
+
+/// Line offset showing `cursor_line` with `scrolloff` lines of context above and below,
+/// or with the cursor on the middle row when `center` is set. Near the document edges the
+/// margin can't be honored and the offset is clamped instead.
+pub(crate) fn scroll_offset(
+    cursor_line: usize,
+    offset: usize,
+    height: usize,
+    line_count: usize,
+    scrolloff: usize,
+    center: bool,
+) -> usize {
+    if center {
+        return aligned_offset(cursor_line, height, line_count, ViewAlign::Center);
+    }
+
+    let margin = scrolloff.min(height.saturating_sub(1) / 2);
+    let offset = if cursor_line < offset + margin {
+        cursor_line.saturating_sub(margin)
+    } else if cursor_line + margin >= offset + height {
+        cursor_line + margin + 1 - height
+    } else {
+        offset
+    };
+
+    offset.min(line_count.saturating_sub(height))
+}
+
+/// Line offset after scrolling one line up or `down`, along with the cursor line pulled
+/// back onto the screen when it fell off an edge.
+pub(crate) fn scroll_by_line(
+    cursor_line: usize,
+    offset: usize,
+    height: usize,
+    line_count: usize,
+    down: bool,
+) -> (usize, usize) {
+    let offset = if down {
+        (offset + 1).min(line_count.saturating_sub(height))
+    } else {
+        offset.saturating_sub(1)
+    };
+    let cursor_line = cursor_line.clamp(offset, offset + height.max(1) - 1);
+
+    (offset, cursor_line)
+}
+
+/// Row of the editor screen the cursor line is scrolled to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ViewAlign {
+    Top,
+    Center,
+    Bottom,
+}
+
+/// Line offset putting `cursor_line` on the `align` row of a `height` rows screen,
+/// without scrolling past the end of a `line_count` lines document.
+pub(crate) fn aligned_offset(
+    cursor_line: usize,
+    height: usize,
+    line_count: usize,
+    align: ViewAlign,
+) -> usize {
+    let offset = match align {
+        ViewAlign::Top => cursor_line,
+        ViewAlign::Center => cursor_line.saturating_sub(height / 2),
+        ViewAlign::Bottom => (cursor_line + 1).saturating_sub(height),
+    };
+
+    offset.min(line_count.saturating_sub(height))
+}
+
+/// Blank line ending the paragraph after `line`, or starting the one before it. Starting
+/// on blank lines skips them first, so repeated jumps move paragraph by paragraph.
+pub(crate) fn paragraph_boundary(
+    doc: &PtBuffer<String>,
+    lines: &LineIndex,
+    line: usize,
+    forward: bool,
+) -> Option<usize> {
+    let is_blank = |line: &usize| {
+        lines
+            .line_range(doc, *line)
+            .is_some_and(|range| doc.range(range).all(|c| c == " " || c == "\t"))
+    };
+
+    let count = lines.line_count(doc);
+    if forward {
+        (line..count).skip_while(is_blank).find(is_blank)
+    } else {
+        (0..=line.min(count - 1))
+            .rev()
+            .skip_while(is_blank)
+            .find(is_blank)
+    }
+}
+
+/// Display column where the element at `column` of a line starts.
+pub(crate) fn display_x<'a>(line: impl IntoIterator<Item = &'a String>, column: usize) -> usize {
+    line.into_iter()
+        .take(column)
+        .fold(0, |x, g| x + cell_width(g, x))
+}
+
+/// Element column of the grapheme drawn over the display column `x`,
+/// clamped to the line length.
+pub(crate) fn column_at<'a>(line: impl IntoIterator<Item = &'a String>, x: usize) -> usize {
+    let mut width = 0;
+    let mut len = 0;
+    for (column, g) in line.into_iter().enumerate() {
+        width += cell_width(g, width);
+        if width > x {
+            return column;
+        }
+        len = column + 1;
+    }
+
+    len
+}
+
+#[cfg(test)]
+mod test {
+    use piece_table::PtBuffer;
+    use unicode_segmentation::UnicodeSegmentation;
+
+    use crate::cursor::{
+        aligned_offset, column_at, display_x, paragraph_boundary, scroll_by_line, scroll_offset,
+        ViewAlign,
+    };
+    use crate::lines::LineIndex;
+
+    fn line(s: &[&str]) -> Vec<String> {
+        s.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn move_across_wide_character() {
+        let line = line(&["a", "中", "b", "🦀", "c"]);
+
+        let xs: Vec<usize> = (0..=line.len()).map(|c| display_x(&line, c)).collect();
+        assert_eq!(xs, vec![0, 1, 3, 4, 6, 7]);
+
+        // Moving right then left lands back on the same cells
+        for (column, x) in xs.iter().enumerate().skip(1) {
+            assert_eq!(column_at(&line, *x), column);
+            assert_eq!(display_x(&line, column - 1), xs[column - 1]);
+        }
+    }
+
+    #[test]
+    fn tab_advances_to_the_next_stop() {
+        let line = line(&["a", "\t", "b", "\t", "\t"]);
+        let xs: Vec<usize> = (0..=line.len()).map(|c| display_x(&line, c)).collect();
+        assert_eq!(xs, vec![0, 1, 4, 5, 8, 12]);
+        assert_eq!(column_at(&line, 2), 1);
+        assert_eq!(column_at(&line, 4), 2);
+    }
+
+    #[test]
+    fn map_cell_inside_wide_character() {
+        let line = line(&["a", "中", "b"]);
+        assert_eq!(column_at(&line, 1), 1);
+        assert_eq!(column_at(&line, 2), 1);
+        assert_eq!(column_at(&line, 3), 2);
+        assert_eq!(column_at(&line, 10), 3);
+    }
+
+    #[test]
+    fn scroll_by_line_keeps_the_cursor_on_screen() {
+        // The cursor stays on its line while it is visible
+        assert_eq!(scroll_by_line(5, 0, 10, 30, true), (1, 5));
+        assert_eq!(scroll_by_line(5, 3, 10, 30, false), (2, 5));
+        // It is pushed down from the top row, up from the bottom one
+        assert_eq!(scroll_by_line(3, 3, 10, 30, true), (4, 4));
+        assert_eq!(scroll_by_line(12, 3, 10, 30, false), (2, 11));
+        // No scrolling past either end of the document
+        assert_eq!(scroll_by_line(0, 0, 10, 30, false), (0, 0));
+        assert_eq!(scroll_by_line(25, 20, 10, 30, true), (20, 25));
+        assert_eq!(scroll_by_line(2, 0, 10, 4, true), (0, 2));
+    }
+
+    #[test]
+    fn scroll_only_past_the_screen_edges() {
+        // cursor_line, offset, height, line_count, scrolloff, center
+        assert_eq!(scroll_offset(5, 0, 10, 100, 0, false), 0);
+        assert_eq!(scroll_offset(10, 0, 10, 100, 0, false), 1);
+        assert_eq!(scroll_offset(3, 5, 10, 100, 0, false), 3);
+    }
+
+    #[test]
+    fn scroll_with_scrolloff() {
+        assert_eq!(scroll_offset(7, 0, 10, 100, 3, false), 1);
+        assert_eq!(scroll_offset(6, 0, 10, 100, 3, false), 0);
+        assert_eq!(scroll_offset(22, 20, 10, 100, 3, false), 19);
+        // Margin larger than half the screen is capped
+        assert_eq!(scroll_offset(51, 45, 10, 100, 20, false), 46);
+    }
+
+    #[test]
+    fn scrolloff_is_clamped_at_document_edges() {
+        assert_eq!(scroll_offset(0, 0, 10, 100, 3, false), 0);
+        assert_eq!(scroll_offset(1, 5, 10, 100, 3, false), 0);
+        assert_eq!(scroll_offset(99, 89, 10, 100, 3, false), 90);
+        assert_eq!(scroll_offset(4, 0, 10, 5, 3, false), 0);
+    }
+
+    #[test]
+    fn centered_cursor() {
+        assert_eq!(scroll_offset(50, 0, 10, 100, 0, true), 45);
+        assert_eq!(scroll_offset(2, 0, 10, 100, 0, true), 0);
+        assert_eq!(scroll_offset(98, 0, 10, 100, 0, true), 90);
+        assert_eq!(scroll_offset(3, 0, 10, 5, 0, true), 0);
+    }
+
+    #[test]
+    fn align_cursor_line() {
+        // cursor_line, height, line_count
+        assert_eq!(aligned_offset(50, 10, 100, ViewAlign::Center), 45);
+        assert_eq!(aligned_offset(50, 10, 100, ViewAlign::Top), 50);
+        assert_eq!(aligned_offset(50, 10, 100, ViewAlign::Bottom), 41);
+
+        // Clamped at the document edges
+        assert_eq!(aligned_offset(2, 10, 100, ViewAlign::Center), 0);
+        assert_eq!(aligned_offset(2, 10, 100, ViewAlign::Bottom), 0);
+        assert_eq!(aligned_offset(95, 10, 100, ViewAlign::Top), 90);
+        assert_eq!(aligned_offset(98, 10, 100, ViewAlign::Center), 90);
+        assert_eq!(aligned_offset(3, 10, 5, ViewAlign::Top), 0);
+    }
+
+    #[test]
+    fn find_paragraph_boundaries() {
+        let src: Vec<String> = "one\ntwo\n\n  \nthree\n\t\nfour\nfive"
+            .graphemes(true)
+            .map(str::to_string)
+            .collect();
+        let doc = PtBuffer::new(&src);
+        let lines = LineIndex::new(&src);
+
+        let cases = [
+            (0, Some(2), None),
+            (1, Some(2), None),
+            // Blank lines at the cursor are skipped
+            (2, Some(5), None),
+            (3, Some(5), None),
+            (4, Some(5), Some(3)),
+            (5, None, Some(3)),
+            (6, None, Some(5)),
+            (7, None, Some(5)),
+        ];
+        for (line, next, prev) in cases {
+            let boundary = |forward| paragraph_boundary(&doc, &lines, line, forward);
+            assert_eq!(boundary(true), next, "line {line}");
+            assert_eq!(boundary(false), prev, "line {line}");
         }
     }
 }
@@ -0,0 +1,106 @@
+use std::ops::Range;
+
+use piece_table::{Edit, PtBuffer};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// A change to the document carrying the inserted text, to be applied with others in a batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Insert { at: usize, text: Vec<String> },
+    Remove(Range<usize>),
+}
+
+/// `prev` extended with `c` when they form a single grapheme, a combining mark typed after
+/// its base joining it rather than becoming a cursor stop of its own.
+pub fn combine(prev: &str, c: char) -> Option<String> {
+    let mut combined = String::from(prev);
+    combined.push(c);
+    (combined.graphemes(true).count() == 1).then_some(combined)
+}
+
+/// Span removed to delete the line holding `idx`: the line with its line ending, or
+/// with the preceding one for the last line, so that no empty line is left behind.
+pub fn line_span(doc: &PtBuffer<String>, idx: usize) -> Range<usize> {
+    let start = doc.line_start(idx);
+    let end = doc.line_end(idx);
+    if end < doc.len() {
+        start..end + 1
+    } else {
+        start.saturating_sub(1)..end
+    }
+}
+
+/// Elements ending at `pos` that join into a single grapheme, removed together by a
+/// backward delete. A loaded document holds whole graphemes so this is usually the
+/// element before `pos`, but a cluster built from separate inserts spans several.
+pub fn cluster_before(doc: &PtBuffer<String>, pos: usize) -> Range<usize> {
+    let mut start = pos.saturating_sub(1);
+    let mut cluster = doc.range(start..pos).collect_string();
+    while start > 0 {
+        let joined = format!("{}{cluster}", doc[start - 1]);
+        if joined.graphemes(true).count() != 1 {
+            break;
+        }
+        cluster = joined;
+        start -= 1;
+    }
+
+    start..pos
+}
+
+/// Elements starting at `pos` that join into a single grapheme, see [`cluster_before`].
+pub fn cluster_after(doc: &PtBuffer<String>, pos: usize) -> Range<usize> {
+    let mut end = (pos + 1).min(doc.len());
+    let mut cluster = doc.range(pos..end).collect_string();
+    while end < doc.len() {
+        let joined = format!("{cluster}{}", doc[end]);
+        if joined.graphemes(true).count() != 1 {
+            break;
+        }
+        cluster = joined;
+        end += 1;
+    }
+
+    pos..end
+}
+
+impl Change {
+    /// Index shift caused by this change.
+    pub fn edit(&self) -> Edit {
+        match self {
+            Change::Insert { at, text } => Edit::Insert {
+                at: *at,
+                len: text.len(),
+            },
+            Change::Remove(range) => Edit::Remove {
+                start: range.start,
+                end: range.end,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use piece_table::{graphemes, PtBuffer};
+
+    use crate::edit::line_span;
+
+    #[test]
+    fn line_span_with_a_line_ending() {
+        let src = graphemes("ab\ncd\nef");
+        let doc = PtBuffer::new(&src);
+
+        // Its own line ending, from anywhere on the line
+        assert_eq!(line_span(&doc, 0), 0..3);
+        assert_eq!(line_span(&doc, 4), 3..6);
+        assert_eq!(line_span(&doc, 5), 3..6);
+        // The last line takes the one before it
+        assert_eq!(line_span(&doc, 7), 5..8);
+        assert_eq!(line_span(&doc, 8), 5..8);
+
+        let src: Vec<String> = vec!["a".to_string()];
+        assert_eq!(line_span(&PtBuffer::new(&src), 1), 0..1);
+        assert_eq!(line_span(&PtBuffer::new(&[]), 0), 0..0);
+    }
+}
@@ -0,0 +1,106 @@
+use piece_table::PtBuffer;
+
+use crate::log::Level;
+use crate::Editor;
+
+/// A character search on the cursor line, remembered to be repeated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CharFind {
+    pub target: String,
+    pub forward: bool,
+    /// Stop right before the match instead of on it
+    pub till: bool,
+}
+
+/// Index the cursor jumps to when looking for `ch` in `line_start..line_end` from the
+/// cursor, `None` when the line has no match. `till` stops next to the match, a match
+/// right next to the cursor being skipped so the search can be repeated.
+pub fn find_char_in_line(
+    buf: &PtBuffer<String>,
+    line_start: usize,
+    line_end: usize,
+    cursor: usize,
+    ch: &str,
+    forward: bool,
+    till: bool,
+) -> Option<usize> {
+    let skip = if till { 1 } else { 0 };
+    if forward {
+        let from = (cursor + 1 + skip).min(line_end);
+        buf.range(from..line_end)
+            .position(|g| g == ch)
+            .map(|offset| from + offset - skip)
+    } else {
+        let to = cursor.saturating_sub(skip).max(line_start);
+        buf.range(line_start..to)
+            .enumerate()
+            .filter(|(_, g)| *g == ch)
+            .last()
+            .map(|(offset, _)| line_start + offset + skip)
+    }
+}
+
+impl Editor<'_> {
+    /// Move to the next match of `find` on the cursor line, returning whether to redraw.
+    pub(crate) fn find_char(&mut self, find: &CharFind) -> bool {
+        let (line, _) = self.cursor_line_column();
+        let Some(range) = self.lines.line_range(&self.doc, line) else {
+            return false;
+        };
+
+        let cursor = self.get_cursor_absolute_position();
+        let target = find_char_in_line(
+            &self.doc,
+            range.start,
+            range.end,
+            cursor,
+            &find.target,
+            find.forward,
+            find.till,
+        );
+
+        match target {
+            Some(idx) => self.move_cursor_to_idx(idx) | self.selection.take().is_some(),
+            None => {
+                self.log(Level::Debug, format!("{:?} not found", find.target));
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use piece_table::PtBuffer;
+    use unicode_segmentation::UnicodeSegmentation;
+
+    use crate::find::find_char_in_line;
+
+    #[test]
+    fn find_in_the_cursor_line() {
+        let src: Vec<String> = "x\nab,cd,ef\nx"
+            .graphemes(true)
+            .map(str::to_string)
+            .collect();
+        let doc = PtBuffer::new(&src);
+        let find =
+            |cursor, forward, till| find_char_in_line(&doc, 2, 10, cursor, ",", forward, till);
+
+        assert_eq!(find(2, true, false), Some(4));
+        assert_eq!(find(4, true, false), Some(7));
+        assert_eq!(find(2, true, true), Some(3));
+        // The match right after the cursor is skipped when stopping before it
+        assert_eq!(find(3, true, true), Some(6));
+
+        assert_eq!(find(9, false, false), Some(7));
+        assert_eq!(find(7, false, false), Some(4));
+        assert_eq!(find(9, false, true), Some(8));
+        assert_eq!(find(8, false, true), Some(5));
+
+        // Nothing past the matches, and nothing from the other lines
+        assert_eq!(find(7, true, false), None);
+        assert_eq!(find(4, false, false), None);
+        assert_eq!(find_char_in_line(&doc, 2, 10, 2, "x", true, false), None);
+        assert_eq!(find_char_in_line(&doc, 2, 10, 9, "x", false, false), None);
+    }
+}
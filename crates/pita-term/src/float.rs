@@ -0,0 +1,144 @@
+use crossterm::style::Color;
+use unicode_width::UnicodeWidthStr;
+
+use crate::colors::ColorScheme;
+use crate::screen::{Screen, Style};
+
+/// Rows shown at once, longer lists scroll with the selection.
+const MAX_HEIGHT: usize = 8;
+
+/// A list of items drawn over the editor near the cursor, one of them being selected.
+pub struct FloatingWindow {
+    screen: Screen,
+    items: Vec<String>,
+    selected: usize,
+}
+
+impl FloatingWindow {
+    /// A window listing `items` right below the cell `x`, `y` of a `width` by `height`
+    /// area, or above it when there is no room left below.
+    pub fn new(items: Vec<String>, x: usize, y: usize, width: usize, height: usize) -> Self {
+        let window_width = items
+            .iter()
+            .map(|item| item.width() + 2)
+            .max()
+            .unwrap_or(2)
+            .min(width);
+        let window_height = items.len().clamp(1, MAX_HEIGHT).min(height);
+
+        let x = x.min(width - window_width);
+        let y = if y + 1 + window_height <= height {
+            y + 1
+        } else {
+            y.saturating_sub(window_height)
+        };
+
+        Self {
+            screen: Screen::headless(window_width, window_height, x, y, Color::Black),
+            items,
+            selected: 0,
+        }
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    pub fn selected(&self) -> Option<&str> {
+        self.items.get(self.selected).map(String::as_str)
+    }
+
+    pub fn select_next(&mut self) {
+        if !self.items.is_empty() {
+            self.selected = (self.selected + 1) % self.items.len();
+        }
+    }
+
+    pub fn select_prev(&mut self) {
+        if !self.items.is_empty() {
+            self.selected = (self.selected + self.items.len() - 1) % self.items.len();
+        }
+    }
+
+    pub fn draw(&self, colors: &ColorScheme) {
+        self.screen.clear(colors.popup_bg);
+        let height = self.screen.height();
+        // Scroll just enough to keep the selection visible
+        let first = (self.selected + 1).saturating_sub(height);
+
+        for (y, item) in self.items.iter().skip(first).take(height).enumerate() {
+            let bg = if first + y == self.selected {
+                colors.selection
+            } else {
+                colors.popup_bg
+            };
+
+            let style = Style(colors.popup_fg, bg);
+            for x in 0..self.screen.width() {
+                self.screen.draw(x, y, " ", style);
+            }
+            self.screen.draw(1, y, item, style);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crossterm::style::Color;
+
+    use crate::colors::ColorScheme;
+    use crate::float::FloatingWindow;
+    use crate::screen::Screen;
+
+    fn row(window: &FloatingWindow, y: usize) -> (String, Color) {
+        let screen = window.screen();
+        let text = (0..screen.width())
+            .map(|x| screen.cell(x, y).unwrap().1)
+            .collect();
+        (text, screen.cell(0, y).unwrap().0 .1)
+    }
+
+    #[test]
+    fn render_items_and_move_selection() {
+        let items = ["foo", "bar", "bazinga"].map(String::from).to_vec();
+        let colors = ColorScheme::default();
+        let mut window = FloatingWindow::new(items, 4, 2, 80, 20);
+        window.draw(&colors);
+
+        assert_eq!(row(&window, 0), (" foo     ".to_string(), colors.selection));
+        assert_eq!(row(&window, 1), (" bar     ".to_string(), colors.popup_bg));
+        assert_eq!(row(&window, 2), (" bazinga ".to_string(), colors.popup_bg));
+
+        window.select_next();
+        window.select_next();
+        window.draw(&colors);
+        assert_eq!(window.selected(), Some("bazinga"));
+        assert_eq!(row(&window, 0).1, colors.popup_bg);
+        assert_eq!(row(&window, 2).1, colors.selection);
+
+        window.select_next();
+        assert_eq!(window.selected(), Some("foo"));
+        window.select_prev();
+        assert_eq!(window.selected(), Some("bazinga"));
+    }
+
+    #[test]
+    fn compose_below_the_cursor() {
+        let items = vec!["ab".to_string()];
+        let colors = ColorScheme::default();
+        let window = FloatingWindow::new(items, 78, 2, 80, 20);
+        window.draw(&colors);
+
+        let editor = Screen::headless(80, 20, 0, 0, colors.bg);
+        editor.compose(window.screen());
+        // Pushed left to fit, one row below the cursor
+        assert_eq!(editor.cell(77, 3).unwrap().1, "a");
+        assert_eq!(editor.cell(78, 3).unwrap().1, "b");
+
+        // No room below the last rows
+        let window = FloatingWindow::new(vec!["ab".to_string()], 0, 19, 80, 20);
+        window.draw(&colors);
+        editor.compose(window.screen());
+        assert_eq!(editor.cell(1, 18).unwrap().1, "a");
+    }
+}
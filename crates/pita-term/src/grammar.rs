@@ -0,0 +1,190 @@
+use std::path::Path;
+
+use tree_sitter::{Language, QueryError};
+use tree_sitter_highlight::HighlightConfiguration;
+
+use crate::language::LanguageConfig;
+
+/// A tree-sitter grammar the editor can highlight with.
+pub trait Grammar: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn language(&self) -> Language;
+
+    fn highlights_query(&self) -> &'static str;
+
+    fn injections_query(&self) -> &'static str {
+        ""
+    }
+
+    fn locals_query(&self) -> &'static str {
+        ""
+    }
+
+    /// File extensions (without the leading dot) this grammar handles.
+    fn extensions(&self) -> &'static [&'static str];
+
+    fn language_config(&self) -> LanguageConfig {
+        LanguageConfig::plain_text()
+    }
+
+    /// Build the highlight configuration, recognizing every capture name of the query.
+    fn highlight_config(&self) -> Result<HighlightConfiguration, QueryError> {
+        let mut config = HighlightConfiguration::new(
+            self.language(),
+            self.name(),
+            self.highlights_query(),
+            self.injections_query(),
+            self.locals_query(),
+        )?;
+
+        let hl_names: Vec<String> = config
+            .query
+            .capture_names()
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        config.configure(&hl_names);
+
+        Ok(config)
+    }
+}
+
+pub struct Rust;
+
+impl Grammar for Rust {
+    fn name(&self) -> &'static str {
+        "rust"
+    }
+
+    fn language(&self) -> Language {
+        tree_sitter_rust::language()
+    }
+
+    fn highlights_query(&self) -> &'static str {
+        tree_sitter_rust::HIGHLIGHTS_QUERY
+    }
+
+    fn injections_query(&self) -> &'static str {
+        tree_sitter_rust::INJECTIONS_QUERY
+    }
+
+    fn extensions(&self) -> &'static [&'static str] {
+        &["rs"]
+    }
+
+    fn language_config(&self) -> LanguageConfig {
+        LanguageConfig::rust()
+    }
+}
+
+/// Grammars known to the editor, resolved by file extension.
+pub struct GrammarRegistry {
+    grammars: Vec<Box<dyn Grammar>>,
+}
+
+impl Default for GrammarRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register(Rust);
+        registry
+    }
+}
+
+impl GrammarRegistry {
+    pub fn empty() -> Self {
+        Self { grammars: vec![] }
+    }
+
+    /// Register a grammar, taking precedence over previously registered ones
+    /// sharing an extension.
+    pub fn register(&mut self, grammar: impl Grammar + 'static) {
+        self.grammars.insert(0, Box::new(grammar));
+    }
+
+    pub fn for_extension(&self, extension: &str) -> Option<&dyn Grammar> {
+        self.grammars
+            .iter()
+            .find(|grammar| grammar.extensions().contains(&extension))
+            .map(Box::as_ref)
+    }
+
+    pub fn for_path(&self, path: impl AsRef<Path>) -> Option<&dyn Grammar> {
+        path.as_ref()
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| self.for_extension(ext))
+    }
+
+    /// Conventions of the grammar handling `path`, plain text when there is none.
+    pub fn language_for_path(&self, path: impl AsRef<Path>) -> LanguageConfig {
+        self.for_path(path)
+            .map_or_else(LanguageConfig::plain_text, |grammar| {
+                grammar.language_config()
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tree_sitter::Language;
+
+    use crate::grammar::{Grammar, GrammarRegistry};
+    use crate::indent::IndentStyle;
+    use crate::language::LanguageConfig;
+
+    struct Stub;
+
+    impl Grammar for Stub {
+        fn name(&self) -> &'static str {
+            "stub"
+        }
+
+        fn language(&self) -> Language {
+            tree_sitter_rust::language()
+        }
+
+        fn highlights_query(&self) -> &'static str {
+            "(identifier) @variable"
+        }
+
+        fn extensions(&self) -> &'static [&'static str] {
+            &["stub", "stb"]
+        }
+    }
+
+    #[test]
+    fn resolve_grammar_by_extension() {
+        let mut registry = GrammarRegistry::default();
+        registry.register(Stub);
+
+        let grammar = registry.for_path("src/main.stb").unwrap();
+        assert_eq!(grammar.name(), "stub");
+        assert!(grammar.highlight_config().is_ok());
+
+        assert_eq!(registry.for_path("main.rs").unwrap().name(), "rust");
+        assert!(registry.for_path("README.md").is_none());
+        assert!(registry.for_path("Makefile").is_none());
+    }
+
+    #[test]
+    fn resolve_language_config_by_extension() {
+        let registry = GrammarRegistry::default();
+
+        let rust = registry.language_for_path("src/main.rs");
+        assert_eq!(rust, LanguageConfig::rust());
+        assert_eq!(rust.line_comment, Some("//"));
+        assert_eq!(rust.block_comment, Some(("/*", "*/")));
+        assert_eq!(rust.indent_unit, IndentStyle::Spaces(4));
+
+        // No grammar, or one not overriding its conventions
+        let plain = registry.language_for_path("notes.txt");
+        assert_eq!(plain, LanguageConfig::plain_text());
+        assert_eq!(plain.line_comment, None);
+        assert_eq!(plain.closing("["), Some("]"));
+        assert_eq!(plain.closing(")"), None);
+        let mut registry = GrammarRegistry::empty();
+        registry.register(Stub);
+        assert_eq!(registry.language_for_path("a.stub"), plain);
+    }
+}
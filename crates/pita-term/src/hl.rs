@@ -1,6 +1,34 @@
-use std::ops::{Add, Index};
-use std::slice::Iter;
+use std::ops::Range;
 
+use piece_table::PtBuffer;
+
+use crate::lines::LineIndex;
+
+/// Lines highlighted above and below the screen, so that scrolling by a few lines shows
+/// them highlighted right away.
+pub const VISIBLE_MARGIN: usize = 16;
+
+/// Byte range of the lines shown from `line_offset` on `height` rows, `margin` lines
+/// around them included, as tree-sitter reports highlight events in bytes.
+pub fn visible_byte_range(
+    doc: &PtBuffer<String>,
+    lines: &LineIndex,
+    line_offset: usize,
+    height: usize,
+    margin: usize,
+) -> Range<usize> {
+    let first = line_offset.saturating_sub(margin);
+    let start = lines.line_start(doc, first).unwrap_or(doc.len());
+    // Up to the start of the line after the last one, its line ending included
+    let end = lines
+        .line_start(doc, line_offset + height + margin)
+        .unwrap_or(doc.len());
+
+    doc.element_to_byte(start)..doc.element_to_byte(end)
+}
+
+/// Highlight spans `(start, end, highlight)` in document elements, kept sorted by start.
+/// Spans emitted by tree-sitter never overlap, so ends are sorted as well.
 #[derive(Debug)]
 pub struct HlQueue {
     inner: Vec<(usize, usize, usize)>,
@@ -18,24 +46,63 @@ impl HlQueue {
     }
 
     pub fn push(&mut self, item: (usize, usize, usize)) {
-        self.inner.push(item);
+        let idx = self.inner.partition_point(|(start, _, _)| *start <= item.0);
+        self.inner.insert(idx, item);
+    }
+
+    /// Keep spans roughly aligned with an edit at `at` changing the length by `delta`,
+    /// until the next parse replaces them. Spans before `at` are kept, spans after it
+    /// are moved and spans the edit lands in are dropped.
+    pub fn shift(&mut self, at: usize, delta: isize) {
+        let removed_end = at + delta.min(0).unsigned_abs();
+        self.inner.retain_mut(|(start, end, _)| {
+            if *end <= at {
+                true
+            } else if *start >= removed_end {
+                *start = start.saturating_add_signed(delta);
+                *end = end.saturating_add_signed(delta);
+                true
+            } else {
+                false
+            }
+        });
     }
 }
 
 impl HlQueue {
     pub fn get(&self, index: usize) -> Option<usize> {
-        let idx = index + 1;
-        self.inner.iter().find(|(start, end, hl)| {
-            idx >= *start && idx < *end
-        }).map(|h|h.2)
+        let idx = self.inner.partition_point(|(_, end, _)| *end <= index);
+        self.inner
+            .get(idx)
+            .filter(|(start, _, _)| *start <= index)
+            .map(|h| h.2)
     }
 
-
+    /// All spans overlapping `start..end`, clipped to that region.
+    pub fn range(
+        &self,
+        start: usize,
+        end: usize,
+    ) -> impl Iterator<Item = (usize, usize, usize)> + '_ {
+        let idx = self
+            .inner
+            .partition_point(|(_, span_end, _)| *span_end <= start);
+        self.inner[idx..]
+            .iter()
+            .take_while(move |(span_start, _, _)| *span_start < end)
+            .map(move |(span_start, span_end, hl)| {
+                (*span_start.max(&start), *span_end.min(&end), *hl)
+            })
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::hl::HlQueue;
+    use piece_table::PtBuffer;
+    use unicode_segmentation::UnicodeSegmentation;
+
+    use crate::hl::{visible_byte_range, HlQueue};
+    use crate::lines::LineIndex;
 
     #[test]
     fn index_hls() {
@@ -44,7 +111,74 @@ mod test {
         };
 
         assert_eq!(hls.get(2), Some(11));
-        assert_eq!(hls.get(6), Some(12));
-        assert_eq!(hls.get(7), None);
+        assert_eq!(hls.get(5), Some(12));
+        assert_eq!(hls.get(6), None);
+    }
+
+    #[test]
+    fn range_hls() {
+        let mut hls = HlQueue::with_capacity(5);
+        hls.push((10, 14, 3));
+        hls.push((0, 3, 11));
+        hls.push((4, 6, 12));
+        hls.push((7, 9, 1));
+        hls.push((16, 20, 2));
+
+        let spans: Vec<_> = hls.range(5, 12).collect();
+        assert_eq!(spans, vec![(5, 6, 12), (7, 9, 1), (10, 12, 3)]);
+
+        let spans: Vec<_> = hls.range(14, 16).collect();
+        assert_eq!(spans, vec![]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn shift_hls_after_insert() {
+        let mut hls = HlQueue {
+            inner: vec![(0, 3, 11), (4, 6, 12), (8, 10, 13)],
+        };
+
+        // At the end of a span, inside one, and at the start of another
+        hls.shift(3, 2);
+        assert_eq!(hls.inner, vec![(0, 3, 11), (6, 8, 12), (10, 12, 13)]);
+        hls.shift(7, 1);
+        assert_eq!(hls.inner, vec![(0, 3, 11), (11, 13, 13)]);
+        hls.shift(11, 3);
+        assert_eq!(hls.inner, vec![(0, 3, 11), (14, 16, 13)]);
+    }
+
+    #[test]
+    fn shift_hls_after_remove() {
+        let mut hls = HlQueue {
+            inner: vec![(0, 3, 11), (4, 6, 12), (8, 10, 13)],
+        };
+
+        // Removing the gap between two spans
+        hls.shift(6, -2);
+        assert_eq!(hls.inner, vec![(0, 3, 11), (4, 6, 12), (6, 8, 13)]);
+
+        // Removing part of a span drops it
+        hls.shift(2, -3);
+        assert_eq!(hls.inner, vec![(3, 5, 13)]);
+    }
+
+    #[test]
+    fn visible_bytes_with_a_margin() {
+        let src: Vec<String> = "é\nab\ncd\nef\ngh"
+            .graphemes(true)
+            .map(str::to_string)
+            .collect();
+        let doc = PtBuffer::new(&src);
+        let lines = LineIndex::new(&src);
+        let visible =
+            |offset, height, margin| visible_byte_range(&doc, &lines, offset, height, margin);
+
+        // "é" takes two bytes
+        assert_eq!(visible(0, 1, 0), 0..3);
+        assert_eq!(visible(2, 1, 0), 6..9);
+        assert_eq!(visible(2, 1, 1), 3..12);
+        // Clamped to the document at both ends
+        assert_eq!(visible(1, 2, 5), 0..14);
+        assert_eq!(visible(4, 3, 0), 12..14);
+        assert_eq!(visible(9, 3, 0), 14..14);
+    }
+}
@@ -0,0 +1,417 @@
+use std::ops::Range;
+
+use piece_table::PtBuffer;
+
+use crate::edit::Change;
+use crate::lines::LineIndex;
+use crate::screen::{cell_width, TAB_WIDTH};
+
+/// Indented lines looked at before settling on a style.
+const MAX_SAMPLES: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndentStyle {
+    Tabs,
+    Spaces(usize),
+}
+
+impl Default for IndentStyle {
+    fn default() -> Self {
+        IndentStyle::Spaces(4)
+    }
+}
+
+impl IndentStyle {
+    /// Text inserted for one indentation level.
+    pub fn unit(&self) -> Vec<String> {
+        match self {
+            IndentStyle::Tabs => vec!["\t".to_string()],
+            IndentStyle::Spaces(width) => vec![" ".to_string(); *width],
+        }
+    }
+
+    /// Display columns of one indentation level.
+    pub fn width(&self) -> usize {
+        match self {
+            IndentStyle::Tabs => TAB_WIDTH,
+            IndentStyle::Spaces(width) => *width,
+        }
+    }
+}
+
+/// Insert one indentation level at the start of every non blank line in `lines`.
+pub fn indent_lines(
+    doc: &PtBuffer<String>,
+    index: &LineIndex,
+    lines: Range<usize>,
+    style: IndentStyle,
+) -> Vec<Change> {
+    lines
+        .filter_map(|line| index.line_range(doc, line))
+        .filter(|range| doc.range(range.clone()).any(|g| g != " " && g != "\t"))
+        .map(|range| Change::Insert {
+            at: range.start,
+            text: style.unit(),
+        })
+        .collect()
+}
+
+/// Remove up to one indentation level of leading whitespace from every line in `lines`.
+pub fn dedent_lines(
+    doc: &PtBuffer<String>,
+    index: &LineIndex,
+    lines: Range<usize>,
+    style: IndentStyle,
+) -> Vec<Change> {
+    lines
+        .filter_map(|line| index.line_range(doc, line))
+        .filter_map(|range| {
+            let mut column = 0;
+            let mut len = 0;
+            for g in doc.range(range.clone()) {
+                if column >= style.width() || (g != " " && g != "\t") {
+                    break;
+                }
+
+                column += cell_width(g, column);
+                len += 1;
+            }
+
+            (len > 0).then(|| Change::Remove(range.start..range.start + len))
+        })
+        .collect()
+}
+
+/// Rewrite the leading whitespace of every line in `lines` with tabs or with spaces only,
+/// keeping its display width, a tab spanning `width` columns. Columns short of a whole tab
+/// stay spaces when converting to tabs.
+pub fn convert_indent(
+    doc: &PtBuffer<String>,
+    index: &LineIndex,
+    lines: Range<usize>,
+    to_tabs: bool,
+    width: usize,
+) -> Vec<Change> {
+    lines
+        .filter_map(|line| index.line_range(doc, line))
+        .flat_map(|range| {
+            let start = range.start;
+            let indent: Vec<&String> = doc
+                .range(range)
+                .take_while(|g| *g == " " || *g == "\t")
+                .collect();
+            let columns = indent.iter().fold(0, |column, g| match g.as_str() {
+                "\t" => column + width - column % width,
+                _ => column + 1,
+            });
+
+            let converted = if to_tabs {
+                let mut converted = vec!["\t".to_string(); columns / width];
+                converted.extend(vec![" ".to_string(); columns % width]);
+                converted
+            } else {
+                vec![" ".to_string(); columns]
+            };
+
+            if indent.iter().copied().eq(&converted) {
+                return vec![];
+            }
+
+            // Inserting after the old indentation keeps a selection starting the line on it
+            let end = start + indent.len();
+            vec![
+                Change::Remove(start..end),
+                Change::Insert {
+                    at: end,
+                    text: converted,
+                },
+            ]
+        })
+        .collect()
+}
+
+/// Leading whitespace of `line`.
+pub fn line_indent(doc: &PtBuffer<String>, index: &LineIndex, line: usize) -> Vec<String> {
+    index.line_range(doc, line).map_or_else(Vec::new, |range| {
+        doc.range(range)
+            .take_while(|g| *g == " " || *g == "\t")
+            .cloned()
+            .collect()
+    })
+}
+
+/// Open an empty line below or above `line` without splitting it, indented like `line`
+/// or one level deeper below a line opening a block. Returns the change and the index
+/// where the cursor lands.
+pub fn open_line(
+    doc: &PtBuffer<String>,
+    index: &LineIndex,
+    line: usize,
+    style: IndentStyle,
+    below: bool,
+) -> (Change, usize) {
+    let range = index.line_range(doc, line).expect("line is within bounds");
+    let mut indent = line_indent(doc, index, line);
+
+    if below {
+        let opens_block = doc
+            .range(range.clone())
+            .filter(|g| *g != " " && *g != "\t")
+            .last()
+            .is_some_and(|g| matches!(g.as_str(), "{" | "(" | "["));
+        if opens_block {
+            indent.extend(style.unit());
+        }
+
+        let cursor = range.end + 1 + indent.len();
+        let text = std::iter::once("\n".to_string()).chain(indent).collect();
+        (
+            Change::Insert {
+                at: range.end,
+                text,
+            },
+            cursor,
+        )
+    } else {
+        let cursor = range.start + indent.len();
+        indent.push("\n".to_string());
+        (
+            Change::Insert {
+                at: range.start,
+                text: indent,
+            },
+            cursor,
+        )
+    }
+}
+
+/// Guess the indentation style from the first indented lines: tabs if most of them start
+/// with one, otherwise the most common indentation change between consecutive lines.
+pub fn detect_indent(doc: &PtBuffer<String>, fallback: IndentStyle) -> IndentStyle {
+    let mut tab_lines = 0;
+    let mut space_lines = 0;
+    // How often each indentation width change was seen
+    let mut deltas = [0usize; 9];
+    let mut previous: usize = 0;
+
+    let mut at_line_start = true;
+    let mut leading: usize = 0;
+    let mut tab = false;
+    for g in doc.iter() {
+        if tab_lines + space_lines >= MAX_SAMPLES {
+            break;
+        }
+
+        if g == "\n" {
+            // Blank lines say nothing about indentation
+            at_line_start = true;
+            leading = 0;
+            tab = false;
+            continue;
+        }
+
+        if !at_line_start {
+            continue;
+        }
+
+        match g.as_str() {
+            "\t" if leading == 0 => tab = true,
+            " " if !tab => leading += 1,
+            " " | "\t" => {}
+            _ => {
+                at_line_start = false;
+                if tab {
+                    tab_lines += 1;
+                } else if leading > 0 {
+                    space_lines += 1;
+                }
+
+                if !tab {
+                    let delta = leading.abs_diff(previous);
+                    if (2..deltas.len()).contains(&delta) {
+                        deltas[delta] += 1;
+                    }
+                    previous = leading;
+                }
+            }
+        }
+    }
+
+    if tab_lines > space_lines {
+        return IndentStyle::Tabs;
+    }
+
+    deltas
+        .iter()
+        .enumerate()
+        .filter(|(_, count)| **count > 0)
+        .max_by_key(|(width, count)| (**count, usize::MAX - width))
+        .map_or(fallback, |(width, _)| IndentStyle::Spaces(width))
+}
+
+#[cfg(test)]
+mod test {
+    use piece_table::{graphemes, PtBuffer};
+
+    use crate::edit::Change;
+    use crate::indent::{
+        convert_indent, dedent_lines, detect_indent, indent_lines, open_line, IndentStyle,
+    };
+    use crate::lines::LineIndex;
+
+    fn detect(s: &str) -> IndentStyle {
+        detect_indent(&PtBuffer::new(&graphemes(s)), IndentStyle::default())
+    }
+
+    #[test]
+    fn indent_non_blank_lines() {
+        let src = graphemes("a\n\n  b\nc");
+        let doc = PtBuffer::new(&src);
+        let index = LineIndex::new(&src);
+
+        let unit = |style: IndentStyle| style.unit();
+        assert_eq!(
+            indent_lines(&doc, &index, 0..3, IndentStyle::Spaces(2)),
+            vec![
+                Change::Insert {
+                    at: 0,
+                    text: unit(IndentStyle::Spaces(2))
+                },
+                Change::Insert {
+                    at: 3,
+                    text: unit(IndentStyle::Spaces(2))
+                },
+            ]
+        );
+        assert_eq!(
+            indent_lines(&doc, &index, 3..10, IndentStyle::Tabs),
+            vec![Change::Insert {
+                at: 7,
+                text: unit(IndentStyle::Tabs)
+            }]
+        );
+    }
+
+    #[test]
+    fn dedent_at_most_one_level() {
+        let src = graphemes("      a\n  b\nc\n\t\td\n \te");
+        let doc = PtBuffer::new(&src);
+        let index = LineIndex::new(&src);
+
+        assert_eq!(
+            dedent_lines(&doc, &index, 0..5, IndentStyle::Spaces(4)),
+            vec![
+                Change::Remove(0..4),
+                // Not enough leading whitespace, all of it goes
+                Change::Remove(8..10),
+                Change::Remove(14..15),
+                // A tab reaching the next stop counts as a whole level
+                Change::Remove(18..20),
+            ]
+        );
+    }
+
+    #[test]
+    fn convert_between_tabs_and_spaces() {
+        let src = graphemes("\ta\n      b\n  \tc\nd\n\t");
+        let doc = PtBuffer::new(&src);
+        let index = LineIndex::new(&src);
+        let replace = |start, end, s: &str| {
+            [
+                Change::Remove(start..end),
+                Change::Insert {
+                    at: end,
+                    text: graphemes(s),
+                },
+            ]
+        };
+
+        // A tab after spaces only reaches the next tab stop
+        assert_eq!(
+            convert_indent(&doc, &index, 0..5, false, 4),
+            [
+                replace(0, 1, "    "),
+                replace(11, 14, "    "),
+                replace(18, 19, "    "),
+            ]
+            .concat()
+        );
+        // Columns left over a whole tab stay spaces
+        assert_eq!(
+            convert_indent(&doc, &index, 0..5, true, 4),
+            [replace(3, 9, "\t  "), replace(11, 14, "\t")].concat()
+        );
+        assert_eq!(convert_indent(&doc, &index, 3..4, true, 4), vec![]);
+    }
+
+    #[test]
+    fn detect_tabs() {
+        assert_eq!(
+            detect("fn main() {\n\tif a {\n\t\tb();\n\t}\n}\n"),
+            IndentStyle::Tabs
+        );
+    }
+
+    #[test]
+    fn detect_spaces() {
+        let two = "fn main() {\n  if a {\n    b();\n\n    c();\n  }\n}\n";
+        assert_eq!(detect(two), IndentStyle::Spaces(2));
+
+        let four =
+            "impl A {\n    fn a() {\n        if b {\n            c();\n        }\n    }\n}\n";
+        assert_eq!(detect(four), IndentStyle::Spaces(4));
+
+        // Aligned continuation lines don't win over the dominant width
+        let aligned =
+            "fn a() {\n    b(1,\n      2);\n    c();\n    if d {\n        e();\n    }\n}\n";
+        assert_eq!(detect(aligned), IndentStyle::Spaces(4));
+    }
+
+    #[test]
+    fn fall_back_to_default_without_indentation() {
+        assert_eq!(detect("a\nb\n"), IndentStyle::default());
+        assert_eq!(detect(""), IndentStyle::default());
+    }
+
+    #[test]
+    fn open_line_with_indent() {
+        let src = graphemes("fn a() {\n    b();\n}");
+        let doc = PtBuffer::new(&src);
+        let index = LineIndex::new(&src);
+        let style = IndentStyle::Spaces(4);
+        let insert = |at, s: &str| Change::Insert {
+            at,
+            text: graphemes(s),
+        };
+
+        // First line opens a block
+        assert_eq!(
+            open_line(&doc, &index, 0, style, true),
+            (insert(8, "\n    "), 13)
+        );
+        assert_eq!(
+            open_line(&doc, &index, 0, style, false),
+            (insert(0, "\n"), 0)
+        );
+
+        // Middle line keeps its indentation
+        assert_eq!(
+            open_line(&doc, &index, 1, style, true),
+            (insert(17, "\n    "), 22)
+        );
+        assert_eq!(
+            open_line(&doc, &index, 1, style, false),
+            (insert(9, "    \n"), 13)
+        );
+
+        // Last line without a line ending
+        assert_eq!(
+            open_line(&doc, &index, 2, style, true),
+            (insert(19, "\n"), 20)
+        );
+        assert_eq!(
+            open_line(&doc, &index, 2, style, false),
+            (insert(18, "\n"), 18)
+        );
+    }
+}
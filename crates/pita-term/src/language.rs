@@ -0,0 +1,55 @@
+use crate::indent::IndentStyle;
+
+/// Editing conventions of a language, resolved with its grammar by file extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LanguageConfig {
+    /// Starts a comment running to the end of the line
+    pub line_comment: Option<&'static str>,
+    /// Opening and closing delimiters of a comment that can span lines
+    pub block_comment: Option<(&'static str, &'static str)>,
+    /// Indentation used when the file doesn't show one
+    pub indent_unit: IndentStyle,
+    /// Typing the opening one also inserts the closing one after the cursor
+    pub auto_pairs: &'static [(&'static str, &'static str)],
+    /// Pairs the selection expands through
+    pub brackets: &'static [(&'static str, &'static str)],
+}
+
+const BRACKETS: &[(&str, &str)] = &[("(", ")"), ("[", "]"), ("{", "}")];
+
+impl LanguageConfig {
+    /// Fallback for files no grammar handles.
+    pub fn plain_text() -> Self {
+        Self {
+            line_comment: None,
+            block_comment: None,
+            indent_unit: IndentStyle::default(),
+            auto_pairs: BRACKETS,
+            brackets: BRACKETS,
+        }
+    }
+
+    pub fn rust() -> Self {
+        Self {
+            line_comment: Some("//"),
+            block_comment: Some(("/*", "*/")),
+            indent_unit: IndentStyle::Spaces(4),
+            auto_pairs: &[("(", ")"), ("[", "]"), ("{", "}"), ("\"", "\"")],
+            brackets: BRACKETS,
+        }
+    }
+
+    /// Closing bracket of the pair opened by `open`.
+    pub fn closing(&self, open: &str) -> Option<&'static str> {
+        self.brackets
+            .iter()
+            .find(|(o, _)| *o == open)
+            .map(|(_, close)| *close)
+    }
+}
+
+impl Default for LanguageConfig {
+    fn default() -> Self {
+        Self::plain_text()
+    }
+}
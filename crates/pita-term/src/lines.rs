@@ -0,0 +1,249 @@
+use std::ops::Range;
+
+use piece_table::PtBuffer;
+
+/// Start index of every line of the document, updated on each edit instead of rescanning.
+///
+/// A pending index doesn't know them yet, the document is scanned on every lookup until
+/// they are found off the editing task and [`LineIndex::from_line_endings`] replaces it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineIndex {
+    /// `None` while pending
+    starts: Option<Vec<usize>>,
+    len: usize,
+}
+
+impl LineIndex {
+    /// Index of `doc` built element by element, the reference [`LineIndex::from_doc`] is
+    /// checked against.
+    #[cfg(test)]
+    pub fn new<'a>(doc: impl IntoIterator<Item = &'a String>) -> Self {
+        let mut index = Self {
+            starts: Some(vec![0]),
+            len: 0,
+        };
+        index.insert(0, doc);
+        index
+    }
+
+    /// Index of the whole of `doc`, built from its piece slices rather than element by element.
+    pub fn from_doc(doc: &PtBuffer<String>) -> Self {
+        Self {
+            starts: Some(doc.line_starts()),
+            len: doc.len(),
+        }
+    }
+
+    /// Index of `doc` from `original_endings`, the line endings of its original buffer,
+    /// only the text added since being scanned.
+    pub fn from_line_endings(doc: &PtBuffer<String>, original_endings: &[usize]) -> Self {
+        Self {
+            starts: Some(doc.line_starts_from(original_endings)),
+            len: doc.len(),
+        }
+    }
+
+    /// Index scanning the document until the line starts are known.
+    pub fn pending() -> Self {
+        Self {
+            starts: None,
+            len: 0,
+        }
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.starts.is_none()
+    }
+
+    /// Number of lines, a trailing line ending starting a last empty line.
+    pub fn line_count(&self, doc: &PtBuffer<String>) -> usize {
+        match &self.starts {
+            Some(starts) => starts.len(),
+            None => doc.line_count(),
+        }
+    }
+
+    pub fn line_start(&self, doc: &PtBuffer<String>, line: usize) -> Option<usize> {
+        match &self.starts {
+            Some(starts) => starts.get(line).copied(),
+            None => doc.line_range(line).map(|range| range.start),
+        }
+    }
+
+    /// Index range of `line`, excluding its line ending.
+    pub fn line_range(&self, doc: &PtBuffer<String>, line: usize) -> Option<Range<usize>> {
+        let Some(starts) = &self.starts else {
+            return doc.line_range(line);
+        };
+
+        let start = *starts.get(line)?;
+        let end = starts.get(line + 1).map_or(self.len, |next| next - 1);
+        Some(start..end)
+    }
+
+    /// Line holding the element at `idx`.
+    pub fn line_of(&self, doc: &PtBuffer<String>, idx: usize) -> usize {
+        match &self.starts {
+            Some(starts) => starts.partition_point(|start| *start <= idx) - 1,
+            None => doc.count_lines_in_range(..idx.min(doc.len())),
+        }
+    }
+
+    /// Record `items` being inserted at `at`.
+    pub fn insert<'a>(&mut self, at: usize, items: impl IntoIterator<Item = &'a String>) {
+        let Some(starts) = &mut self.starts else {
+            return;
+        };
+
+        let line = starts.partition_point(|start| *start <= at) - 1;
+        let mut len = 0;
+        let mut new_starts = vec![];
+        for item in items {
+            len += 1;
+            if item == "\n" {
+                new_starts.push(at + len);
+            }
+        }
+
+        for start in &mut starts[line + 1..] {
+            *start += len;
+        }
+        starts.splice(line + 1..line + 1, new_starts);
+        self.len += len;
+    }
+
+    /// Record the elements in `range` being removed.
+    pub fn remove(&mut self, range: Range<usize>) {
+        let Some(starts) = &mut self.starts else {
+            return;
+        };
+
+        let removed = range.len();
+        // Lines whose line ending is removed are joined with the next one
+        starts.retain(|start| *start <= range.start || *start > range.end);
+        for start in starts {
+            if *start > range.end {
+                *start -= removed;
+            }
+        }
+        self.len -= removed;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use piece_table::{graphemes, line_endings, PtBuffer};
+    use proptest::prelude::*;
+
+    use crate::lines::LineIndex;
+
+    #[test]
+    fn index_lines() {
+        let src = graphemes("ab\n\ncd\n");
+        let doc = PtBuffer::new(&src);
+        let index = LineIndex::new(&src);
+
+        assert_eq!(index.line_count(&doc), 4);
+        assert_eq!(index.line_range(&doc, 0), Some(0..2));
+        assert_eq!(index.line_range(&doc, 1), Some(3..3));
+        assert_eq!(index.line_range(&doc, 2), Some(4..6));
+        assert_eq!(index.line_range(&doc, 3), Some(7..7));
+        assert_eq!(index.line_range(&doc, 4), None);
+        assert_eq!(index.line_of(&doc, 2), 0);
+        assert_eq!(index.line_of(&doc, 5), 2);
+    }
+
+    #[test]
+    fn trailing_line_ending_starts_an_empty_line() {
+        let src = graphemes("ab\ncd");
+        let doc = PtBuffer::new(&src);
+        let index = LineIndex::new(&src);
+        assert_eq!(index.line_count(&doc), 2);
+        assert_eq!(index.line_range(&doc, 1), Some(3..5));
+
+        let src = graphemes("ab\ncd\n");
+        let doc = PtBuffer::new(&src);
+        let index = LineIndex::new(&src);
+        assert_eq!(index.line_count(&doc), 3);
+        assert_eq!(index.line_range(&doc, 2), Some(6..6));
+        assert_eq!(index.line_of(&doc, 6), 2);
+
+        let src = graphemes("");
+        let doc = PtBuffer::new(&src);
+        let index = LineIndex::new(&src);
+        assert_eq!(index.line_count(&doc), 1);
+        assert_eq!(index.line_range(&doc, 0), Some(0..0));
+    }
+
+    #[test]
+    fn pending_index_scans_the_document() {
+        let src = graphemes("ab\n\ncd\n");
+        let doc = PtBuffer::new(&src);
+        let index = LineIndex::pending();
+
+        assert!(index.is_pending());
+        assert_eq!(index.line_count(&doc), 4);
+        assert_eq!(index.line_start(&doc, 2), Some(4));
+        assert_eq!(index.line_range(&doc, 1), Some(3..3));
+        assert_eq!(index.line_range(&doc, 4), None);
+        assert_eq!(index.line_of(&doc, 5), 2);
+        assert_eq!(index.line_of(&doc, 7), 3);
+    }
+
+    #[derive(Debug, Clone)]
+    enum Op {
+        Insert(usize, String),
+        Remove(usize, usize),
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            (any::<usize>(), "[ab\n]{1,4}").prop_map(|(at, s)| Op::Insert(at, s)),
+            (any::<usize>(), 1..5usize).prop_map(|(at, len)| Op::Remove(at, len)),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn incremental_index_matches_rescan(
+            src in "[ab\n]{0,20}",
+            ops in prop::collection::vec(op(), 0..50),
+        ) {
+            let src = graphemes(&src);
+            let mut doc = PtBuffer::new(&src);
+            let mut index = LineIndex::new(&src);
+            let mut pending = LineIndex::pending();
+
+            for op in ops {
+                match op {
+                    Op::Insert(at, s) => {
+                        let at = at % (doc.len() + 1);
+                        let items = graphemes(&s);
+                        doc.insert_slice(at, &items);
+                        index.insert(at, &items);
+                        pending.insert(at, &items);
+                    }
+                    Op::Remove(at, len) => {
+                        let start = at % (doc.len() + 1);
+                        let end = (start + len).min(doc.len());
+                        doc.remove_range(start..end);
+                        index.remove(start..end);
+                        pending.remove(start..end);
+                    }
+                }
+
+                prop_assert_eq!(&index, &LineIndex::new(doc.iter()));
+                prop_assert_eq!(&index, &LineIndex::from_doc(&doc));
+                prop_assert_eq!(&index, &LineIndex::from_line_endings(&doc, &line_endings(&src)));
+                prop_assert_eq!(pending.line_count(&doc), index.line_count(&doc));
+                for line in 0..index.line_count(&doc) {
+                    prop_assert_eq!(index.line_range(&doc, line), doc.line_range(line));
+                    prop_assert_eq!(pending.line_range(&doc, line), doc.line_range(line));
+                }
+                for idx in 0..=doc.len() {
+                    prop_assert_eq!(pending.line_of(&doc, idx), index.line_of(&doc, idx));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,60 @@
+/// How much a log line matters, lines below the configured level being hidden.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogLine {
+    pub level: Level,
+    pub message: String,
+}
+
+impl LogLine {
+    pub fn new(level: Level, message: impl ToString) -> Self {
+        Self {
+            level,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// The `count` last lines at `min` level or above, newest first.
+pub fn visible(lines: &[LogLine], min: Level, count: usize) -> impl Iterator<Item = &LogLine> {
+    lines
+        .iter()
+        .rev()
+        .filter(move |line| line.level >= min)
+        .take(count)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::log::{visible, Level, LogLine};
+
+    #[test]
+    fn filter_by_level_newest_first() {
+        let lines = [
+            LogLine::new(Level::Error, "failed"),
+            LogLine::new(Level::Debug, "moving"),
+            LogLine::new(Level::Info, "written"),
+            LogLine::new(Level::Warn, "read-only"),
+        ];
+        let messages = |min, count| -> Vec<&str> {
+            visible(&lines, min, count)
+                .map(|line| line.message.as_str())
+                .collect()
+        };
+
+        assert_eq!(
+            messages(Level::Info, 10),
+            ["read-only", "written", "failed"]
+        );
+        assert_eq!(messages(Level::Warn, 10), ["read-only", "failed"]);
+        assert_eq!(messages(Level::Debug, 2), ["read-only", "written"]);
+        assert!(messages(Level::Error, 0).is_empty());
+    }
+}
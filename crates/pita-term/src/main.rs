@@ -13,54 +13,157 @@
 //          - runnables
 // TODO: Plugins wasm runtime + API
 
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 use std::io::stdout;
+use std::ops::Range;
 use std::panic::{set_hook, take_hook};
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use std::{fs, io};
 
-use crossterm::event::{Event, EventStream, KeyCode, KeyModifiers, MouseEvent};
+use crossterm::event::{
+    DisableMouseCapture, Event, EventStream, KeyCode, KeyModifiers, MouseEvent, MouseEventKind,
+};
 use crossterm::style::Color;
 use crossterm::terminal::{disable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen};
 use crossterm::{execute, terminal};
-use futures::{join, FutureExt, StreamExt};
+use futures::{join, FutureExt, Stream, StreamExt};
 use futures_timer::Delay;
 use tokio::select;
 use tree_sitter_highlight::{HighlightConfiguration, HighlightEvent, Highlighter};
 use unicode_segmentation::UnicodeSegmentation;
 
-use piece_table::PtBuffer;
+use piece_table::{graphemes, line_endings, Edit, PtBuffer};
 
-use crate::hl::HlQueue;
-use crate::screen::{Screen, Style};
+use crate::case::Case;
+use crate::colors::ColorScheme;
+use crate::complete::{complete_word_at, prefix_len};
+use crate::config::Config;
+use crate::cursor::ViewAlign;
+use crate::edit::{cluster_after, cluster_before, combine, line_span, Change};
+use crate::find::CharFind;
+use crate::float::FloatingWindow;
+use crate::grammar::{Grammar, GrammarRegistry};
+use crate::hl::{visible_byte_range, HlQueue, VISIBLE_MARGIN};
+use crate::indent::{
+    convert_indent, dedent_lines, detect_indent, indent_lines, line_indent, open_line, IndentStyle,
+};
+use crate::language::LanguageConfig;
+use crate::lines::LineIndex;
+use crate::log::{Level, LogLine};
+use crate::pane::Pane;
+use crate::screen::{vertical_split, Screen, Style};
+use crate::selection::{expand_range, BlockSelection, Selection};
+use crate::swap::Swap;
 
+mod case;
+mod clipboard;
+mod colors;
+mod complete;
+mod config;
 mod cursor;
+mod edit;
+mod find;
+mod float;
+mod grammar;
 mod hl;
+mod indent;
+mod language;
+mod lines;
+mod log;
+mod mouse;
+mod number;
+mod pane;
 mod screen;
+mod script;
+mod selection;
+mod sort;
+mod surround;
+mod swap;
+#[cfg(feature = "trace")]
+mod timing;
 
 struct Editor<'a> {
+    path: String,
+    config: Config,
     doc: PtBuffer<'a, String>,
     highlighter: Highlighter,
-    rust_config: HighlightConfiguration,
+    hl_config: Option<HighlightConfiguration>,
+    /// Comment, indentation and bracket conventions of the file type
+    language: LanguageConfig,
     highlight: HlQueue,
+    /// Document revision and first screen line the highlights were computed for, edits
+    /// shifting them until the command loop parses the document again
+    highlighted_at: Option<(u64, usize)>,
     editor_screen: Screen,
+    /// Second view of the document when split, the fields above it being the focused one
+    other_pane: Option<Pane>,
+    status_screen: Screen,
     log_screen: Screen,
-    log_buffer: RefCell<Vec<String>>,
-    line_endings: Vec<usize>,
+    log_buffer: RefCell<Vec<LogLine>>,
+    lines: LineIndex,
+    /// Document index of the cursor, the screen cursor being placed from it
+    cursor: Cell<usize>,
+    block_selection: Option<BlockSelection>,
+    selection: Option<Selection>,
+    /// Selections left by expanding, restored one at a time when shrinking
+    selection_history: Vec<Selection>,
+    /// Where the left button went down, the selection following the drag from there
+    drag_anchor: Option<usize>,
+    /// Character search waiting for the next typed character as its target
+    pending_find: Option<(bool, bool)>,
+    /// Surround waiting for the next typed character to pick its delimiters, adding them
+    /// when `true` and removing them when `false`
+    pending_surround: Option<bool>,
+    /// Last character search, repeated forward or backward
+    last_find: Option<CharFind>,
+    /// Commands of the last edit, replayed by [`Command::RepeatEdit`]
+    last_edit: Vec<Command>,
+    /// Whether the next typing or deleting command still extends `last_edit`
+    edit_run_open: bool,
+    /// Completion list drawn over the document, navigation keys driving it while open
+    completion: Option<FloatingWindow>,
+    /// The file can't be written, edits are rejected
+    read_only: bool,
+    /// Typed characters replace the one under the cursor instead of being inserted
+    overtype: bool,
+    indent: IndentStyle,
+    /// Document revision last written to the swap file
+    autosaved_revision: u64,
+    /// Content hash of the file as last read or written
+    saved_hash: u64,
+    /// Whether the content differs from the saved one, computed once per revision
+    modified: Cell<Option<(u64, bool)>>,
+    /// Syntax highlighting is on, it can be toggled off for very large files
+    highlighting: bool,
+    /// Last yanked text, OSC 52 being write-only
+    register: Vec<String>,
+    /// Reused across frames to draw highlighted runs without allocating
+    text_scratch: String,
 }
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
-    execute!(stdout(), EnterAlternateScreen)?;
-    // execute!(stdout(), event::EnableMouseCapture)?;
+    let path = match parse_args(std::env::args()) {
+        Startup::Usage => {
+            eprintln!("{USAGE}");
+            return Ok(());
+        }
+        Startup::Unnamed => None,
+        Startup::File(path) => Some(path),
+        Startup::Script { script, path } => return run_script_file(&script, &path),
+    };
 
-    let args: Vec<String> = std::env::args().collect();
-    let path = args[1].clone();
+    execute!(stdout(), EnterAlternateScreen)?;
 
-    let (shutdown_tx, shutdown_rx) = tokio::sync::broadcast::channel::<()>(32);
+    let (shutdown_tx, _) = tokio::sync::broadcast::channel::<()>(32);
     let (command_tx, command_rx) = tokio::sync::mpsc::channel(32);
     let (hl_tx, hl_rx) = tokio::sync::mpsc::channel(32);
-    let event_handler = tokio::spawn(handle_events(command_tx, shutdown_tx.clone()));
+    let event_handler = tokio::spawn(handle_events(
+        EventStream::new(),
+        command_tx,
+        shutdown_tx.subscribe(),
+    ));
     let command_handler = tokio::spawn(handle_command(path, command_rx, hl_tx, shutdown_tx));
     let hl_handler = tokio::spawn(handle_highlight(hl_rx));
 
@@ -69,34 +172,203 @@ async fn main() -> io::Result<()> {
     Ok(())
 }
 
-async fn handle_highlight(hl_rx: tokio::sync::mpsc::Receiver<()>) {}
+const USAGE: &str = "Usage: pita <file>
+       pita --new                      edit an empty unnamed buffer
+       pita --script <script> <file>   apply the commands of <script> to <file> and print it";
+
+/// What to edit, from the command line arguments.
+#[derive(Debug, PartialEq, Eq)]
+enum Startup {
+    /// Print the usage and exit
+    Usage,
+    /// An empty buffer not backed by any file yet
+    Unnamed,
+    /// A file, created on save when it doesn't exist
+    File(String),
+    /// Apply the commands of a script to a file without a terminal, printing the result
+    Script { script: String, path: String },
+}
+
+fn parse_args(args: impl IntoIterator<Item = String>) -> Startup {
+    let mut args = args.into_iter().skip(1);
+    match args.next().as_deref() {
+        None | Some("-h" | "--help") => Startup::Usage,
+        Some("--new") => Startup::Unnamed,
+        Some("--script") => match (args.next(), args.next()) {
+            (Some(script), Some(path)) => Startup::Script { script, path },
+            _ => Startup::Usage,
+        },
+        Some(path) => Startup::File(path.to_string()),
+    }
+}
+
+/// Run the commands of the `script` file over the file at `path` with a headless editor
+/// and print the resulting document. The file is only written if the script saves it.
+fn run_script_file(script: &str, path: &str) -> io::Result<()> {
+    let commands = script::parse_script(&fs::read_to_string(script)?)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, format!("{script}: {err}")))?;
+    let file = match fs::read_to_string(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(err) => return Err(err),
+    };
+
+    let src = graphemes(&file);
+    let mut editor = Editor::headless_at(path.to_string(), &src);
+    print!("{}", editor.run_script(commands));
+    for line in editor.log_buffer.borrow().iter() {
+        eprintln!("{}", line.message);
+    }
+    Ok(())
+}
+
+async fn handle_highlight(_hl_rx: tokio::sync::mpsc::Receiver<()>) {}
 
 pub fn init_panic_hook() {
     let original_hook = take_hook();
     set_hook(Box::new(move |panic_info| {
         disable_raw_mode().unwrap();
-        execute!(stdout(), LeaveAlternateScreen).unwrap();
+        execute!(stdout(), DisableMouseCapture, LeaveAlternateScreen).unwrap();
         original_hook(panic_info);
     }));
 }
 
+impl<'a> Editor<'a> {
+    fn new(
+        path: String,
+        src: &'a [String],
+        editor_screen: Screen,
+        status_screen: Screen,
+        log_screen: Screen,
+        config: Config,
+    ) -> Self {
+        let doc = PtBuffer::new(src);
+        let saved_hash = doc.content_hash();
+        let highlight = HlQueue::with_capacity(doc.len());
+        // The command loop indexes lines in the background, headless editors right away
+        let lines = LineIndex::pending();
+        let grammars = GrammarRegistry::default();
+        let language = grammars.language_for_path(&path);
+        let indent = config
+            .indent
+            .unwrap_or_else(|| detect_indent(&doc, language.indent_unit));
+        let grammar = grammars.for_path(&path);
+
+        let mut editor = Self {
+            path,
+            config,
+            doc,
+            highlighter: Highlighter::new(),
+            hl_config: None,
+            language,
+            highlight,
+            highlighted_at: None,
+            editor_screen,
+            other_pane: None,
+            status_screen,
+            log_screen,
+            log_buffer: RefCell::new(vec![]),
+            lines,
+            cursor: Cell::new(0),
+            block_selection: None,
+            selection: None,
+            selection_history: vec![],
+            drag_anchor: None,
+            pending_find: None,
+            pending_surround: None,
+            last_find: None,
+            last_edit: vec![],
+            edit_run_open: false,
+            completion: None,
+            read_only: false,
+            overtype: false,
+            indent,
+            autosaved_revision: 0,
+            saved_hash,
+            modified: Cell::new(None),
+            highlighting: true,
+            register: vec![],
+            text_scratch: String::new(),
+        };
+        if let Some(grammar) = grammar {
+            editor.load_highlighting(grammar);
+        }
+
+        editor
+    }
+
+    /// Highlight with `grammar`, editing without highlighting when its queries don't load
+    /// rather than taking the editor down.
+    fn load_highlighting(&mut self, grammar: &dyn Grammar) {
+        match grammar.highlight_config() {
+            Ok(config) => self.hl_config = Some(config),
+            Err(err) => {
+                self.hl_config = None;
+                self.highlighting = false;
+                self.log(
+                    Level::Error,
+                    format!(
+                        "Syntax highlighting off, the {} queries failed to load: {err}",
+                        grammar.name()
+                    ),
+                );
+            }
+        }
+    }
+}
+
 impl Editor<'_> {
-    fn log(&self, args: impl ToString) {
-        self.log_buffer.borrow_mut().push(args.to_string())
+    fn log(&self, level: Level, args: impl ToString) {
+        self.log_buffer.borrow_mut().push(LogLine::new(level, args))
     }
 
+    #[cfg_attr(feature = "trace", tracing::instrument(skip_all))]
     fn update_highlights(&mut self) {
-        let doc: Vec<&str> = self.doc.iter().map(|c| c.as_str()).collect();
-        let string = doc.join("");
+        self.highlight.clear();
+        self.highlighted_at = Some((self.doc.revision(), self.editor_screen.line_offset()));
+        if !self.highlighting {
+            return;
+        }
+
+        let Some(hl_config) = &self.hl_config else {
+            return;
+        };
+
+        let visible = if self.config.highlight_visible_only {
+            visible_byte_range(
+                &self.doc,
+                &self.lines,
+                self.editor_screen.line_offset(),
+                self.editor_screen.height(),
+                VISIBLE_MARGIN,
+            )
+        } else {
+            0..usize::MAX
+        };
+
+        let string = self.doc.range(..).collect_string();
         let highlights = self
             .highlighter
-            .highlight(&self.rust_config, string.as_bytes(), None, |_| None)
+            .highlight(hl_config, string.as_bytes(), None, |_| None)
             .unwrap();
         let mut next_hl = vec![];
         let mut next_range = vec![];
-        self.highlight.clear();
+        // Spans are kept in elements like the rest of the editor, and tree-sitter reports
+        // them in increasing byte offsets, so one walk over the document converts them
+        let mut elements = self.doc.iter();
+        let (mut byte, mut element) = (0, 0);
+        let mut to_element = |target: usize| {
+            while byte < target {
+                let Some(g) = elements.next() else { break };
+                byte += g.len();
+                element += 1;
+            }
+            element
+        };
         for event in highlights {
             match event.unwrap() {
+                // Events come in document order, nothing left to show past the screen
+                HighlightEvent::Source { start, .. } if start >= visible.end => break,
                 HighlightEvent::Source { start, end } => {
                     next_range.push((start, end));
                 }
@@ -105,7 +377,10 @@ impl Editor<'_> {
                 }
                 HighlightEvent::HighlightEnd => {
                     if let Some((hl, (start, end))) = next_hl.pop().zip(next_range.pop()) {
-                        self.highlight.push((start, end, hl.0));
+                        if end > visible.start {
+                            let span = (to_element(start), to_element(end), hl.0);
+                            self.highlight.push(span);
+                        }
                     }
                 }
             }
@@ -113,381 +388,3039 @@ impl Editor<'_> {
     }
 
     fn get_cursor_absolute_position(&self) -> usize {
-        let (x, y) = self.editor_screen.cursor();
-        let y = y + self.editor_screen.line_offset();
-        self.doc.line_column_to_idx(x, y)
+        self.doc.clamp_index(self.cursor.get())
     }
 
-    fn draw_logs(&mut self) {
-        for (idx, log_line) in self.log_buffer.borrow().iter().rev().enumerate() {
-            if idx > self.log_screen.height() {
-                break;
+    /// Keep the cursor on the same text across `edit`, text inserted right at the cursor
+    /// going after it for the command to place the cursor. Returns whether it moved.
+    fn shift_cursor(&self, edit: &Edit) -> bool {
+        let cursor = self.cursor.get();
+        let shifted = match *edit {
+            Edit::Insert { at, .. } if at == cursor => cursor,
+            _ => edit.adjust(cursor),
+        };
+        self.cursor.set(shifted);
+        shifted != cursor
+    }
+
+    /// Shift the cursors of both panes along `edit`, once applied to the document and the
+    /// line index.
+    fn follow_edit(&mut self, edit: Edit) {
+        if let Some(pane) = &mut self.other_pane {
+            pane.follow_edit(&edit);
+        }
+        if self.shift_cursor(&edit) {
+            self.move_cursor_to_idx(self.cursor.get());
+        }
+    }
+
+    /// Insert `item` at `idx`, keeping the line index in sync.
+    fn insert(&mut self, idx: usize, item: String) {
+        self.lines.insert(idx, [&item]);
+        self.highlight.shift(idx, 1);
+        self.doc.insert(idx, item);
+        self.follow_edit(Edit::Insert { at: idx, len: 1 });
+    }
+
+    /// Insert `items` at `idx` as a single piece, keeping the line index in sync.
+    fn insert_slice(&mut self, idx: usize, items: &[String]) {
+        self.lines.insert(idx, items);
+        self.highlight.shift(idx, items.len() as isize);
+        self.doc.insert_slice(idx, items);
+        let len = items.len();
+        self.follow_edit(Edit::Insert { at: idx, len });
+    }
+
+    /// Insert a line ending and `indent` at `idx`, returning the index right after them.
+    fn insert_newline(&mut self, idx: usize, indent: &[String]) -> usize {
+        let newline = "\n".to_string();
+        self.lines
+            .insert(idx, std::iter::once(&newline).chain(indent));
+        self.highlight.shift(idx, 1 + indent.len() as isize);
+        let cursor = self.doc.insert_newline(idx, indent);
+        let len = cursor - idx;
+        self.follow_edit(Edit::Insert { at: idx, len });
+        cursor
+    }
+
+    /// Insert a copy of `line` below it, returning the inserted range.
+    fn duplicate_line(&mut self, line: usize) -> std::ops::Range<usize> {
+        let range = self
+            .lines
+            .line_range(&self.doc, line)
+            .expect("line is within bounds");
+        // Inserting the line ending first also covers a last line without one
+        let copy: Vec<String> = std::iter::once("\n".to_string())
+            .chain(self.doc.range(range.clone()).cloned())
+            .collect();
+        self.insert_slice(range.end, &copy);
+        range.end..range.end + copy.len()
+    }
+
+    /// The selection, or the cursor line with its line ending.
+    fn yank_range(&self) -> std::ops::Range<usize> {
+        match self.selection {
+            Some(selection) => selection.range(),
+            None => {
+                let (line, _) = self.cursor_line_column();
+                let range = self
+                    .lines
+                    .line_range(&self.doc, line)
+                    .expect("cursor is on a line");
+                range.start..self.doc.clamp_index(range.end + 1)
             }
+        }
+    }
 
-            let w = self.log_screen.width();
-            let log_line = if log_line.len() > w {
-                &log_line[..w]
-            } else {
-                &log_line
-            };
+    /// Copy the selection, or the cursor line with its line ending, to the register
+    /// and the system clipboard.
+    fn yank(&mut self) {
+        let range = self.yank_range();
+        self.register = self.doc.range(range).cloned().collect();
+        self.copy_register();
+    }
 
-            self.log_screen.draw(
-                0,
-                idx,
-                &format!("{idx} - {log_line}"),
-                Style(Color::Red, Color::Black),
-            );
+    /// Move the selection, or the cursor line with its line ending, to the register and
+    /// the system clipboard, leaving the cursor where it started.
+    fn cut(&mut self) -> bool {
+        let range = self.yank_range();
+        if range.is_empty() {
+            return false;
         }
+
+        self.selection = None;
+        self.register = self.take_range(range.clone());
+        self.copy_register();
+        self.move_cursor_to_idx(range.start);
+        true
     }
-    // Draw only a portion of the doc to fill the current screen
-    fn draw_doc(&mut self) {
-        let mut line_count = 0;
-        let mut column_count = 0;
-        let mut line_ending = 0;
 
-        let start = self
-            .doc
-            .line_column_to_idx(0, self.editor_screen.line_offset());
-        self.line_endings.clear();
+    /// Send the register to the system clipboard when enabled.
+    fn copy_register(&mut self) {
+        if self.config.osc52_clipboard {
+            let text = self.register.concat();
+            if let Err(err) = self.editor_screen.write_escape(&clipboard::osc52(&text)) {
+                self.log(
+                    Level::Error,
+                    format!("Failed to copy to the clipboard: {err}"),
+                );
+            }
+        }
+    }
 
-        // Always push line offset - 1 ending in case we need to jump up a line without redrawing
-        {
-            let start = self
-                .doc
-                .line_column_to_idx(0, self.editor_screen.line_offset().saturating_sub(1));
+    /// Remove the elements in `range`, keeping the line index in sync.
+    fn remove_range(&mut self, range: std::ops::Range<usize>) {
+        self.lines.remove(range.clone());
+        self.highlight.shift(range.start, -(range.len() as isize));
+        self.doc.remove_range(range.clone());
+        self.follow_edit(Edit::Remove {
+            start: range.start,
+            end: range.end,
+        });
+    }
 
-            let end = self
-                .doc
-                .range(start..)
-                .enumerate()
-                .find(|(_i, c)| *c == "\n")
-                .map(|(i, _c)| i)
-                .unwrap_or_default();
+    /// Remove the elements in `range` like [`Editor::remove_range`], returning them.
+    fn take_range(&mut self, range: std::ops::Range<usize>) -> Vec<String> {
+        self.lines.remove(range.clone());
+        self.highlight.shift(range.start, -(range.len() as isize));
+        let removed = self.doc.remove_range_collect(range.clone());
+        self.follow_edit(Edit::Remove {
+            start: range.start,
+            end: range.end,
+        });
+        removed
+    }
 
-            self.line_endings.push(end + 1);
-        };
+    /// Replace the elements in `range` with `items`, keeping the line index in sync.
+    fn replace_range(&mut self, range: std::ops::Range<usize>, items: &[String]) {
+        let start = range.start;
+        self.remove_range(range);
+        self.insert_slice(start, items);
+    }
 
-        let mut current_line = Vec::with_capacity(self.editor_screen.width());
-        let mut current_hl: Option<usize> = self.highlight.get(start);
-        let mut color = hl_to_color(current_hl);
+    /// Apply non overlapping `changes` sorted by position, shifting the selection along.
+    fn apply_edits(&mut self, changes: Vec<Change>) {
+        // Apply from the end so the positions of the remaining changes stay valid
+        for change in changes.into_iter().rev() {
+            let edit = change.edit();
+            if let Some(selection) = &mut self.selection {
+                selection.anchor = edit.adjust(selection.anchor);
+                selection.head = edit.adjust(selection.head);
+            }
 
-        for (idx, byte) in self.doc.range(start..).enumerate() {
-            if line_count > self.editor_screen.height() {
-                break;
+            match change {
+                Change::Insert { at, text } => self.insert_slice(at, &text),
+                Change::Remove(range) => self.remove_range(range),
             }
+        }
+    }
 
-            let next_hl: Option<usize> = self.highlight.get(start + idx);
-            // push byte to the current line buffer
-            current_line.push(byte);
+    /// Lines covered by the selection, or the cursor line.
+    fn selected_lines(&self) -> std::ops::Range<usize> {
+        match self.selection {
+            Some(selection) => {
+                let range = selection.range();
+                let first = self.lines.line_of(&self.doc, range.start);
+                // A selection ending at a line start doesn't include that line
+                let last = self
+                    .lines
+                    .line_of(&self.doc, range.end.saturating_sub(1).max(range.start));
+                first..last + 1
+            }
+            None => {
+                let (line, _) = self.cursor_line_column();
+                line..line + 1
+            }
+        }
+    }
 
-            // if the current highlight changed, drain the line buffer
-            // and write it to the screen
-            if current_hl != next_hl {
-                let text = current_line.drain(..);
-                line_ending += text.len();
-
-                let text: Vec<&str> = text.map(String::as_str).collect();
-                let text = text.join("");
-                self.editor_screen.draw(
-                    column_count,
-                    line_count,
-                    &text,
-                    Style(color, screen::DEFAULT_BG),
-                );
+    /// Indent or dedent the selected lines by one level, keeping the cursor on its text.
+    fn shift_lines(&mut self, indent: bool) {
+        let lines = self.selected_lines();
+        let changes = if indent {
+            indent_lines(&self.doc, &self.lines, lines, self.indent)
+        } else {
+            dedent_lines(&self.doc, &self.lines, lines, self.indent)
+        };
+        self.apply_edits_at_cursor(changes);
+    }
 
-                current_hl = next_hl;
-                column_count += text.len();
-                color = hl_to_color(current_hl);
-            }
-
-            if *byte == "\n" {
-                let text = current_line.drain(..);
-                line_ending += text.len();
-                self.line_endings.push(line_ending);
-                let text: Vec<&str> = text.map(String::as_str).collect();
-                let text = text.join("");
-                self.editor_screen.draw(
-                    column_count,
-                    line_count,
-                    &text,
-                    Style(Color::White, screen::DEFAULT_BG),
-                );
-                column_count = 0;
-                line_ending = 0;
-                line_count += 1;
-                continue;
+    /// Rewrite the indentation of the selected lines, or of the whole document without a
+    /// selection, with tabs or spaces only. Converting the whole document also switches
+    /// the indentation style used from then on.
+    fn convert_indentation(&mut self, to_tabs: bool) -> bool {
+        let width = self.indent.width();
+        let lines = match self.selection {
+            Some(_) => self.selected_lines(),
+            None => {
+                self.indent = if to_tabs {
+                    IndentStyle::Tabs
+                } else {
+                    IndentStyle::Spaces(width)
+                };
+                0..self.lines.line_count(&self.doc)
             }
+        };
+
+        let changes = convert_indent(&self.doc, &self.lines, lines, to_tabs, width);
+        if changes.is_empty() {
+            return false;
         }
+        self.apply_edits_at_cursor(changes);
+        true
     }
-}
 
-#[derive(Debug)]
-enum Command {
-    Quit,
-    Char(char),
-    MoveLeft,
-    WordLeft,
-    WordRight,
-    MoveRight,
-    MoveDown,
-    MoveUp,
-    NewLine,
-    DeleteForward,
-    DeleteBackWard,
-    Tab,
-    Mouse(MouseEvent),
-}
+    /// Apply `changes` like [`Editor::apply_edits`], keeping the cursor on its text.
+    fn apply_edits_at_cursor(&mut self, changes: Vec<Change>) {
+        let cursor = changes
+            .iter()
+            .rev()
+            .fold(self.get_cursor_absolute_position(), |cursor, change| {
+                change.edit().adjust(cursor)
+            });
+        self.apply_edits(changes);
+        self.move_cursor_to_idx(cursor);
+    }
 
-async fn handle_events(
-    tx: tokio::sync::mpsc::Sender<Command>,
-    shutdown_rx: tokio::sync::broadcast::Sender<()>,
-) {
-    let mut stream = EventStream::new();
+    /// Remove the selected text, leaving the cursor where it started.
+    fn delete_selection(&mut self) {
+        let Some(selection) = self.selection.take() else {
+            return;
+        };
 
-    loop {
-        let delay = Delay::new(Duration::from_millis(1_000)).fuse();
-        let event = stream.next().fuse();
-        let mut shutdown = shutdown_rx.subscribe();
+        let range = selection.range();
+        self.remove_range(range.clone());
+        self.move_cursor_to_idx(range.start);
+    }
 
-        select! {
-            _ = delay => {},
-            maybe_shutdown = shutdown.recv() => if let Ok(()) = maybe_shutdown {
-                break;
-            },
-            maybe_event = event => {
-                match maybe_event {
-                    Some(Ok(Event::Key(e))) => {
-                        match e.code {
-                            KeyCode::Char(c) => {
-                                tx.send(Command::Char(c)).await.unwrap();
-                            }
-                            KeyCode::Esc => {
-                                tx.send(Command::Quit).await.unwrap();
-                            }
-                            KeyCode::Left if e.modifiers.contains(KeyModifiers::CONTROL) => {
-                                tx.send(Command::WordLeft).await.unwrap()
-                            }
-                            KeyCode::Left => {
-                                tx.send(Command::MoveLeft).await.unwrap()
-                            }
-                            KeyCode::Right if e.modifiers.contains(KeyModifiers::CONTROL) => {
-                                tx.send(Command::WordRight).await.unwrap()
-                            }
-                            KeyCode::Right => {
-                                tx.send(Command::MoveRight).await.unwrap()
-                            }
-                            KeyCode::Up => {
-                                tx.send(Command::MoveUp).await.unwrap()
-                            }
-                            KeyCode::Down => {
-                                tx.send(Command::MoveDown).await.unwrap()
-                            }
-                            KeyCode::Enter => {
-                                tx.send(Command::NewLine).await.unwrap()
-                            }
-                            KeyCode::Tab => {
-                                tx.send(Command::Tab).await.unwrap()
-                            }
-                            KeyCode::Delete => {
-                                tx.send(Command::DeleteForward).await.unwrap()
-                            }
-                            KeyCode::Backspace => {
-                                tx.send(Command::DeleteBackWard).await.unwrap()
-                            }
-                            _ => {}
-                        }
-                    },
-                    Some(Ok(e)) => {
-                        println!("{e:?}");
-                    }
-                    Some(Err(e)) => println!("Error: {:?}\r", e),
-                    None => {
-                        break;
-                    }
-                }
+    /// Remove the element at `idx`, keeping the line index in sync.
+    fn remove(&mut self, idx: usize) {
+        self.lines.remove(idx..idx + 1);
+        self.highlight.shift(idx, -1);
+        self.doc.remove(idx);
+        self.follow_edit(Edit::Remove {
+            start: idx,
+            end: idx + 1,
+        });
+    }
+
+    /// Write the file, logging a failure. `clean_up` trims trailing whitespace and fixes
+    /// the final newline as configured, which saving after a pause in typing doesn't do to
+    /// leave alone what was just typed.
+    fn write_file(&mut self, clean_up: bool) {
+        if let Err(err) = self.save(clean_up) {
+            self.log(
+                Level::Error,
+                format!("Failed to write {}: {err}", self.name()),
+            );
+        }
+    }
+
+    fn save(&mut self, clean_up: bool) -> io::Result<()> {
+        if self.path.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no file name"));
+        }
+
+        if clean_up && self.config.trim_trailing_whitespace {
+            let removed = self.doc.trailing_whitespace();
+            self.apply_edits(removed.into_iter().map(Change::Remove).collect());
+        }
+
+        if clean_up && self.config.ensure_final_newline && !self.doc.is_empty() {
+            let len = self.doc.len();
+            match self.doc.trailing_line_endings() {
+                0 => self.apply_edits(vec![Change::Insert {
+                    at: len,
+                    text: vec!["\n".to_string()],
+                }]),
+                1 => {}
+                n => self.apply_edits(vec![Change::Remove(len - n + 1..len)]),
             }
         }
+
+        fs::write(&self.path, self.content())?;
+        self.saved_hash = self.doc.content_hash();
+        self.modified.set(None);
+        self.log(Level::Info, format!("Written {}", self.path));
+        self.remove_swap();
+        Ok(())
     }
-}
 
-async fn handle_command(
-    path: String,
-    mut rx: tokio::sync::mpsc::Receiver<Command>,
-    _hl_event: tokio::sync::mpsc::Sender<()>,
-    shutdown_tx: tokio::sync::broadcast::Sender<()>,
-) -> io::Result<()> {
-    let (width, height) = terminal::size()?;
-    let log_screen_height = ((height as f32 / 100.0) * 10.0) as usize;
-    let editor_height = ((height as f32 / 100.0) * 90.0) as usize;
-    let width = width as usize;
-    let offset_x = 0;
-    let offset_y = 0;
-    let log_buffer = RefCell::new(vec![
-        format!("Terminal size ({width}, {height})"),
-        format!("Editor dimension ({width}, {editor_height})"),
-        format!("Log dimension ({width}, {log_screen_height})"),
-    ]);
+    fn content(&self) -> String {
+        self.doc.range(..).collect_string()
+    }
 
-    init_panic_hook();
+    /// Snapshot of the document to write to the swap file, if it changed since the last one.
+    fn pending_autosave(&self) -> Option<Swap> {
+        let revision = self.doc.revision();
+        // An unnamed buffer has nowhere to put its swap file
+        if !self.config.autosave || self.path.is_empty() || revision == self.autosaved_revision {
+            return None;
+        }
 
-    let log_screen = Screen::new(
-        width,
-        log_screen_height,
-        offset_x,
-        editor_height,
-        Color::Black,
-    )?;
-    let editor_screen = Screen::new(width, editor_height, offset_x, offset_y, screen::DEFAULT_BG)?;
-    let file = fs::read_to_string(&path)?;
-    let string = file.to_string();
-    let graphemes = string.graphemes(true);
-    let src: Vec<String> = graphemes.map(|s| s.to_string()).collect();
-    let doc = PtBuffer::new(&src);
-    let doc_len = doc.len();
-    let highlight = HlQueue::with_capacity(doc_len);
-    let line_endings = Vec::with_capacity(editor_screen.size());
-    let highlighter = Highlighter::new();
-    let rust = tree_sitter_rust::language();
-    let mut rust_config =
-        HighlightConfiguration::new(rust, "rust", tree_sitter_rust::HIGHLIGHTS_QUERY, "", "")
-            .unwrap();
+        Some(Swap {
+            path: self.path.clone(),
+            revision,
+            content: self.content(),
+        })
+    }
 
-    let hl_names: Vec<String> = rust_config
-        .query
-        .capture_names()
-        .iter()
-        .map(|s| s.to_string())
-        .collect();
-    rust_config.configure(&hl_names);
-
-    let mut editor = Editor {
-        doc,
-        highlighter,
-        rust_config,
-        highlight,
-        editor_screen,
-        log_screen,
-        log_buffer,
-        line_endings,
-    };
+    /// Whether the file should be written after `idle` without input, which a read-only
+    /// or unnamed buffer never is.
+    fn idle_save_due(&self, idle: Duration) -> bool {
+        !self.read_only
+            && !self.path.is_empty()
+            && should_save_when_idle(self.is_modified(), idle, self.config.idle_save_delay)
+    }
 
-    editor.draw_doc();
-    editor.draw_logs();
-    editor.editor_screen.present();
+    async fn autosave(&mut self) {
+        let Some(swap) = self.pending_autosave() else {
+            return;
+        };
 
-    while let Some(message) = rx.recv().await {
-        let redraw = match message {
-            Command::Quit => {
-                shutdown_tx.send(()).unwrap();
-                break;
-            }
+        match tokio::fs::write(swap::swap_path(&self.path), swap.encode()).await {
+            Ok(()) => self.autosaved_revision = swap.revision,
+            Err(err) => self.log(
+                Level::Error,
+                format!("Failed to write the swap file: {err}"),
+            ),
+        }
+    }
 
-            Command::Char(c) => {
-                let pos = editor.get_cursor_absolute_position();
-                editor.doc.insert(pos, c.to_string());
-                editor.cursor_right();
-                true
+    fn remove_swap(&mut self) {
+        self.autosaved_revision = self.doc.revision();
+        if let Err(err) = fs::remove_file(swap::swap_path(&self.path)) {
+            if err.kind() != io::ErrorKind::NotFound {
+                self.log(
+                    Level::Error,
+                    format!("Failed to remove the swap file: {err}"),
+                );
             }
-            Command::MoveLeft => editor.cursor_left(),
-            Command::WordLeft => {
-                let pos = editor.get_cursor_absolute_position();
-                let c = &editor.doc[pos.saturating_sub(1)];
-                if c == " " || c == "\n" {
-                    for c in editor
-                        .doc
-                        .rev_range(editor.doc.len() - pos..editor.doc.len())
-                    {
-                        if c != " " && c != "\n" {
-                            break;
-                        }
+        }
+    }
 
-                        editor.cursor_left();
-                    }
-                } else {
-                    for c in editor
-                        .doc
-                        .rev_range(editor.doc.len() - pos..editor.doc.len())
-                    {
-                        if c == " " || c == "\n" {
-                            break;
-                        }
-                        editor.cursor_left();
-                    }
-                }
+    /// Replace the document with the content of its swap file.
+    fn recover_swap(&mut self) -> io::Result<()> {
+        if self.path.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no file name"));
+        }
 
-                false
-            }
-            Command::WordRight => {
-                let pos = editor.get_cursor_absolute_position();
-                let c = &editor.doc[pos];
-                if c == " " || c == "\n" {
-                    for c in editor.doc.range(pos..) {
-                        if c != " " && c != "\n" {
-                            break;
-                        }
+        let swap_path = swap::swap_path(&self.path);
+        let swap = fs::read_to_string(&swap_path)?;
+        let Some(swap) = Swap::decode(&swap) else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a swap file", swap_path.display()),
+            ));
+        };
 
-                        editor.cursor_right();
-                    }
-                } else {
-                    for c in editor.doc.range(pos..) {
-                        if c == " " || c == "\n" {
-                            break;
-                        }
-                        editor.cursor_right();
+        let content = graphemes(&swap.content);
+        self.remove_range(0..self.doc.len());
+        self.insert_slice(0, &content);
+        self.move_cursor_to_idx(0);
+        self.log(
+            Level::Info,
+            format!("Recovered revision {} of {}", swap.revision, swap.path),
+        );
+        Ok(())
+    }
+
+    fn cursor_line_column(&self) -> (usize, usize) {
+        let idx = self.get_cursor_absolute_position();
+        let line = self.lines.line_of(&self.doc, idx);
+        (
+            line,
+            idx - self.lines.line_start(&self.doc, line).unwrap_or_default(),
+        )
+    }
+
+    fn draw_scrollbar(&self) {
+        let thumb = screen::scrollbar_thumb(
+            self.editor_screen.line_offset(),
+            self.editor_screen.height(),
+            self.lines.line_count(&self.doc),
+        );
+        self.editor_screen
+            .draw_scrollbar(thumb, self.config.colors.scrollbar_thumb);
+    }
+
+    fn draw_whitespace(&self) {
+        if !self.config.render_whitespace {
+            return;
+        }
+
+        let offset = self.editor_screen.line_offset();
+        for y in 0..self.editor_screen.height() {
+            let Some(range) = self.lines.line_range(&self.doc, y + offset) else {
+                break;
+            };
+
+            // Trailing whitespace walked from the line end, which may be far off screen
+            let doc_len = self.doc.len();
+            let trailing_len = self
+                .doc
+                .rev_range(doc_len - range.end..doc_len - range.start)
+                .take_while(|g| *g == " " || *g == "\t")
+                .count();
+            let trailing = range.len() - trailing_len;
+            let line: Vec<&String> = self.doc.range(self.visible_part(range)).collect();
+
+            let mut x = 0;
+            for (column, g) in line.into_iter().enumerate() {
+                let width = screen::cell_width(g, x);
+                let glyph = match g.as_str() {
+                    " " => "·".to_string(),
+                    "\t" => format!("→{}", " ".repeat(width - 1)),
+                    _ => {
+                        x += width;
+                        continue;
                     }
-                }
+                };
 
-                false
+                let colors = &self.config.colors;
+                let bg = if column >= trailing {
+                    colors.trailing_whitespace
+                } else {
+                    colors.bg
+                };
+                x = self
+                    .editor_screen
+                    .draw(x, y, &glyph, Style(colors.whitespace, bg));
             }
-            Command::MoveRight => editor.cursor_right(),
-            Command::MoveDown => editor.cursor_down(),
-            Command::MoveUp => editor.cursor_up(),
-            Command::NewLine => {
-                let pos = editor.get_cursor_absolute_position();
-                editor.doc.insert(pos, "\n".to_string());
-                true
+        }
+    }
+
+    fn draw_selection(&self) {
+        let Some(selection) = self.selection else {
+            return;
+        };
+
+        let selected = selection.range();
+        let offset = self.editor_screen.line_offset();
+        for y in 0..self.editor_screen.height() {
+            let Some(line) = self.lines.line_range(&self.doc, y + offset) else {
+                break;
+            };
+            if line.start > selected.end {
+                break;
             }
-            // FIXME
-            Command::DeleteForward => {
-                let pos = editor.get_cursor_absolute_position();
-                editor.log(format!("del at {pos}"));
-                editor.doc.remove(pos);
-                true
+
+            let visible = self.visible_part(line.clone());
+            let start = selected.start.clamp(visible.start, visible.end) - line.start;
+            let end = selected.end.clamp(visible.start, visible.end) - line.start;
+            let from = cursor::display_x(self.doc.range(visible.clone()), start);
+            let mut to = cursor::display_x(self.doc.range(visible.clone()), end);
+            // Show a selected line ending as one extra cell
+            if selected.contains(&line.end) && line.end < self.doc.len() && visible.end == line.end
+            {
+                to += 1;
             }
 
-            Command::DeleteBackWard => {
-                editor.cursor_left();
-                let pos = editor.get_cursor_absolute_position();
-                editor.doc.remove(pos);
-                true
+            for x in from..to {
+                self.editor_screen
+                    .set_bg(x, y, self.config.colors.selection);
             }
-            Command::Tab => todo!(),
-            Command::Mouse(_) => todo!(),
+        }
+    }
+
+    fn draw_block_selection(&self) {
+        let Some(block) = &self.block_selection else {
+            return;
         };
 
-        if redraw {
-            editor.editor_screen.clear(Color::DarkYellow);
-            editor.draw_doc();
+        let offset = self.editor_screen.line_offset();
+        for y in 0..self.editor_screen.height() {
+            for x in block.cols() {
+                if block.contains(y + offset, x) {
+                    self.editor_screen
+                        .set_bg(x, y, self.config.colors.selection);
+                }
+            }
         }
+    }
 
-        editor.log_screen.clear(Color::Black);
-        editor.draw_logs();
-        editor.log_screen.present();
-        editor.editor_screen.present();
+    /// Path of the edited file, or a placeholder for an unnamed buffer.
+    fn name(&self) -> &str {
+        if self.path.is_empty() {
+            "[No Name]"
+        } else {
+            &self.path
+        }
     }
 
-    Ok(())
-}
+    /// Whether the content differs from the file, undoing back to it counting as unmodified.
+    fn is_modified(&self) -> bool {
+        let revision = self.doc.revision();
+        match self.modified.get() {
+            Some((cached, modified)) if cached == revision => modified,
+            _ => {
+                let modified = self.doc.content_hash() != self.saved_hash;
+                self.modified.set(Some((revision, modified)));
+                modified
+            }
+        }
+    }
+
+    /// Name and state of the buffer, shown on the left of the status line.
+    fn status_line(&self) -> String {
+        let read_only = if self.read_only { " [RO]" } else { "" };
+        let modified = if self.is_modified() { " [+]" } else { "" };
+        let overtype = if self.overtype { " [OVR]" } else { "" };
+        format!("{}{read_only}{modified}{overtype}", self.name())
+    }
+
+    /// Buffer name and state on the left, cursor position on the right.
+    fn draw_status(&self) {
+        let colors = &self.config.colors;
+        let style = Style(colors.status_fg, colors.status_bg);
+        let (line, column) = self.cursor_line_column();
+        self.status_screen.clear(colors.status_bg);
+        self.status_screen.draw(0, 0, &self.status_line(), style);
+        let position = format!(" {}:{} ", line + 1, column + 1);
+        self.status_screen.draw_right(0, &position, style);
+    }
+
+    /// Draw the newest log lines first, wrapping the long ones over several rows.
+    fn draw_logs(&mut self) {
+        let log_buffer = self.log_buffer.borrow();
+        let (width, height) = (self.log_screen.width(), self.log_screen.height());
+        let lines = log::visible(&log_buffer, self.config.log_level, height);
+        let mut y = 0;
+        for (idx, line) in lines.enumerate() {
+            let text = format!("{idx} - {}", line.message);
+            let graphemes: Vec<&str> = text.graphemes(true).collect();
+            let colors = &self.config.colors;
+            let ends = screen::wrap_points(&graphemes, width);
+
+            let mut start = 0;
+            for end in ends.into_iter().chain([graphemes.len()]) {
+                if y == height {
+                    return;
+                }
+
+                // Only a grapheme wider than the whole pane is left to cut
+                let row = graphemes[start..end].concat();
+                self.log_screen.draw(
+                    0,
+                    y,
+                    screen::truncate_to_width(&row, width),
+                    Style(colors.log(line.level), colors.log_bg),
+                );
+                start = end;
+                y += 1;
+            }
+        }
+    }
+
+    /// Perform the state change for one command, returning whether the document needs a redraw.
+    ///
+    /// Everything but quitting and the pane layout happens here, so a headless editor can
+    /// be driven through whole editing sessions without a terminal or the command loop.
+    fn apply(&mut self, message: Command) -> bool {
+        // A pending find takes the next character as its target instead of inserting it
+        if let Some((forward, till)) = self.pending_find.take() {
+            if let Command::Char(c) = message {
+                let find = CharFind {
+                    target: c.to_string(),
+                    forward,
+                    till,
+                };
+                let redraw = self.find_char(&find);
+                self.last_find = Some(find);
+                return redraw;
+            }
+            // Cancelling the find leaves the rest alone
+            if let Command::Cancel = message {
+                return false;
+            }
+        }
+        if let Some(add) = self.pending_surround.take() {
+            match message {
+                Command::Char(c) if add => return self.surround(c),
+                Command::Char(c) => return self.unsurround(c),
+                Command::Cancel => return false,
+                _ => {}
+            }
+        }
+
+        if self.read_only && message.is_edit() {
+            self.log(Level::Warn, format!("{} is read-only", self.path));
+            return false;
+        }
+
+        // While the completion list is open, navigation keys move through it
+        let message = match message {
+            Command::MoveUp if self.completion.is_some() => Command::CompletionPrev,
+            Command::MoveDown if self.completion.is_some() => Command::CompletionNext,
+            Command::NewLine if self.completion.is_some() => Command::AcceptCompletion,
+            message => message,
+        };
+        // Anything else closes it
+        let closed_completion = match message {
+            Command::Complete | Command::CompletionNext | Command::CompletionPrev => None,
+            _ => self.completion.take(),
+        };
+        let completion_closed = closed_completion.is_some();
+        let revision = self.doc.revision();
+        let run_step = message.extends_edit_run().then(|| message.clone());
+        if !matches!(message, Command::ExpandSelection | Command::ShrinkSelection) {
+            self.selection_history.clear();
+        }
+
+        let block_cleared = message.is_motion() && self.block_selection.take().is_some();
+        let selection_cleared = message.is_motion() && self.selection.take().is_some();
+        let redraw = match message {
+            Command::Quit => false,
+            // Backs out of one thing at a time, the completion list having been closed above
+            Command::Cancel if completion_closed => false,
+            Command::Cancel => {
+                self.block_selection.take().is_some() || self.selection.take().is_some()
+            }
+            Command::Char(c) if self.block_selection.is_some() => {
+                let pad = self.config.block_pad_short_lines;
+                let block = self.block_selection.as_mut().unwrap();
+                let text = [c.to_string()];
+                for (idx, padding) in block.insert(&mut self.doc, &text, pad) {
+                    let padding = vec![" ".to_string(); padding];
+                    self.lines.insert(idx, padding.iter().chain(&text));
+                }
+                block.shift_cols(1);
+                self.cursor_right();
+                true
+            }
+            Command::Char(c) if self.selection.is_some() => {
+                let range = self.selection.take().unwrap().range();
+                self.replace_range(range.clone(), &[c.to_string()]);
+                self.move_cursor_to_idx(range.start + 1);
+                true
+            }
+            Command::Char(c) => {
+                let pos = self.get_cursor_absolute_position();
+                let combined = pos
+                    .checked_sub(1)
+                    .and_then(|prev| combine(&self.doc[prev], c));
+                // Overtyping a line ending or the end of the document inserts instead
+                let overtyped =
+                    self.overtype && self.doc.get(pos).is_some_and(|item| !item.ends_with('\n'));
+                match combined {
+                    Some(combined) => {
+                        self.remove(pos - 1);
+                        self.insert(pos - 1, combined);
+                        self.move_cursor_to_idx(pos);
+                    }
+                    None if overtyped => {
+                        self.replace_range(cluster_after(&self.doc, pos), &[c.to_string()]);
+                        self.move_cursor_to_idx(pos + 1);
+                    }
+                    None => {
+                        self.insert(pos, c.to_string());
+                        self.cursor_right();
+                    }
+                }
+                true
+            }
+            Command::MoveLeft => self.cursor_left(),
+            Command::WordLeft => {
+                let mut redraw = false;
+                let pos = self.get_cursor_absolute_position();
+                let Some(c) = self.doc.get(pos.saturating_sub(1)) else {
+                    return false;
+                };
+                if c == " " || c == "\n" {
+                    for c in self.doc.rev_range(self.doc.len() - pos..self.doc.len()) {
+                        if c != " " && c != "\n" {
+                            break;
+                        }
+
+                        redraw |= self.cursor_left();
+                    }
+                } else {
+                    for c in self.doc.rev_range(self.doc.len() - pos..self.doc.len()) {
+                        if c == " " || c == "\n" {
+                            break;
+                        }
+                        redraw |= self.cursor_left();
+                    }
+                }
+
+                redraw
+            }
+            Command::WordRight => {
+                let mut redraw = false;
+                let pos = self.get_cursor_absolute_position();
+                let Some(c) = self.doc.get(pos) else {
+                    return false;
+                };
+                if c == " " || c == "\n" {
+                    for c in self.doc.range(pos..) {
+                        if c != " " && c != "\n" {
+                            break;
+                        }
+
+                        redraw |= self.cursor_right();
+                    }
+                } else {
+                    for c in self.doc.range(pos..) {
+                        if c == " " || c == "\n" {
+                            break;
+                        }
+                        redraw |= self.cursor_right();
+                    }
+                }
+
+                redraw
+            }
+            Command::MoveRight => self.cursor_right(),
+            Command::MoveDown => self.cursor_down(),
+            Command::MoveUp => self.cursor_up(),
+            Command::NewLine => {
+                let pos = self.get_cursor_absolute_position();
+                let (line, column) = self.cursor_line_column();
+                // Splitting the indent only carries the part before the cursor
+                let mut indent = line_indent(&self.doc, &self.lines, line);
+                indent.truncate(column);
+                let cursor = self.insert_newline(pos, &indent);
+                self.move_cursor_to_idx(cursor);
+                true
+            }
+            Command::DeleteForward | Command::DeleteBackWard if self.selection.is_some() => {
+                self.delete_selection();
+                true
+            }
+            Command::DeleteForward => {
+                let pos = self.get_cursor_absolute_position();
+                if pos == self.doc.len() {
+                    return false;
+                }
+
+                self.remove_range(cluster_after(&self.doc, pos));
+                true
+            }
+            Command::DeleteBackWard => {
+                let pos = self.get_cursor_absolute_position();
+                if pos == 0 {
+                    return false;
+                }
+
+                let cluster = cluster_before(&self.doc, pos);
+                let start = cluster.start;
+                self.remove_range(cluster);
+                self.move_cursor_to_idx(start);
+                true
+            }
+            // Tab indents the selected lines rather than replacing them
+            Command::Tab | Command::Indent if self.selection.is_some() => {
+                self.shift_lines(true);
+                true
+            }
+            Command::Indent => {
+                self.shift_lines(true);
+                true
+            }
+            Command::SortLines {
+                reverse,
+                ignore_case,
+            } => self.sort_selected_lines(reverse, ignore_case),
+            Command::ToUpper => self.change_case(Case::Upper),
+            Command::ToLower => self.change_case(Case::Lower),
+            Command::SwapCase => self.change_case(Case::Swap),
+            Command::Dedent => {
+                self.shift_lines(false);
+                true
+            }
+            Command::TabsToSpaces => self.convert_indentation(false),
+            Command::SpacesToTabs => self.convert_indentation(true),
+            Command::Tab => {
+                let pos = self.get_cursor_absolute_position();
+                let unit = self.indent.unit();
+                self.insert_slice(pos, &unit);
+                self.move_cursor_to_idx(pos + unit.len());
+                true
+            }
+            Command::Find { forward, till } => {
+                self.pending_find = Some((forward, till));
+                false
+            }
+            Command::Surround => {
+                self.pending_surround = Some(true);
+                false
+            }
+            Command::Unsurround => {
+                self.pending_surround = Some(false);
+                false
+            }
+            Command::RepeatEdit => self.repeat_edit(),
+            Command::RepeatFind { reverse } => match self.last_find.clone() {
+                Some(mut find) => {
+                    find.forward ^= reverse;
+                    self.find_char(&find)
+                }
+                None => false,
+            },
+            Command::ScrollUp => self.scroll_line(false),
+            Command::ScrollDown => self.scroll_line(true),
+            Command::CenterCursor => self.align_cursor(ViewAlign::Center),
+            Command::GotoTop => self.goto_edge(false),
+            Command::GotoBottom => self.goto_edge(true),
+            Command::ParagraphForward => self.jump_paragraph(true),
+            Command::ParagraphBackward => self.jump_paragraph(false),
+            Command::CursorToTop => self.align_cursor(ViewAlign::Top),
+            Command::CursorToBottom => self.align_cursor(ViewAlign::Bottom),
+            Command::Complete => {
+                let pos = self.get_cursor_absolute_position();
+                self.open_completion(complete_word_at(&self.doc, pos));
+                true
+            }
+            Command::CompletionNext | Command::CompletionPrev => {
+                if let Some(window) = &mut self.completion {
+                    if matches!(message, Command::CompletionNext) {
+                        window.select_next();
+                    } else {
+                        window.select_prev();
+                    }
+                }
+                true
+            }
+            Command::AcceptCompletion => {
+                let Some(text) = closed_completion.as_ref().and_then(|w| w.selected()) else {
+                    return completion_closed;
+                };
+
+                // Candidates start with the word being typed, only insert what's left
+                let pos = self.get_cursor_absolute_position();
+                let text: Vec<String> = text
+                    .graphemes(true)
+                    .skip(prefix_len(&self.doc, pos))
+                    .map(str::to_string)
+                    .collect();
+                self.insert_slice(pos, &text);
+                self.move_cursor_to_idx(pos + text.len());
+                true
+            }
+            Command::Yank => {
+                self.yank();
+                false
+            }
+            Command::Cut => self.cut(),
+            Command::RecoverSwap => {
+                if let Err(err) = self.recover_swap() {
+                    self.log(
+                        Level::Error,
+                        format!("Failed to recover {}: {err}", self.name()),
+                    );
+                }
+                true
+            }
+            Command::ShowStats => {
+                let (scope, stats) = match self.selection {
+                    Some(selection) => ("Selection", self.doc.range_stats(selection.range())),
+                    None => ("Document", self.doc.stats()),
+                };
+                self.log(
+                    Level::Info,
+                    format!(
+                        "{scope}: {} lines, {} words, {} chars, {} bytes",
+                        stats.lines, stats.words, stats.chars, stats.bytes
+                    ),
+                );
+                false
+            }
+            Command::ToggleOvertype => {
+                self.overtype = !self.overtype;
+                true
+            }
+            Command::ToggleHighlight => {
+                self.highlighting = !self.highlighting;
+                self.highlighted_at = None;
+                let state = if self.highlighting { "on" } else { "off" };
+                self.log(Level::Info, format!("Syntax highlighting {state}"));
+                true
+            }
+            Command::SelectAll => {
+                let len = self.doc.len();
+                self.selection = (len > 0).then(|| Selection::new(0, len));
+                self.move_cursor_to_idx(len);
+                true
+            }
+            Command::ExpandSelection => {
+                let pos = self.get_cursor_absolute_position();
+                let current = self.selection.unwrap_or(Selection::new(pos, pos));
+                match expand_range(&self.doc, current.range(), &self.language) {
+                    Some(range) => {
+                        self.selection_history.push(current);
+                        self.selection = Some(Selection::new(range.start, range.end));
+                        self.move_cursor_to_idx(range.end);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            Command::ShrinkSelection => match self.selection_history.pop() {
+                Some(previous) => {
+                    self.selection = (previous.anchor != previous.head).then_some(previous);
+                    self.move_cursor_to_idx(previous.head);
+                    true
+                }
+                None => false,
+            },
+            Command::Paste => {
+                let pos = self.get_cursor_absolute_position();
+                let register = self.register.clone();
+                self.insert_slice(pos, &register);
+                self.move_cursor_to_idx(pos + register.len());
+                true
+            }
+            Command::NewLineBelow | Command::NewLineAbove => {
+                let (line, _) = self.cursor_line_column();
+                let below = matches!(message, Command::NewLineBelow);
+                let (change, cursor) = open_line(&self.doc, &self.lines, line, self.indent, below);
+                self.apply_edits(vec![change]);
+                self.move_cursor_to_idx(cursor);
+                true
+            }
+            Command::DeleteLine => {
+                let (line, column) = self.cursor_line_column();
+                let span = line_span(&self.doc, self.get_cursor_absolute_position());
+                if span.is_empty() {
+                    return false;
+                }
+
+                self.remove_range(span);
+                // The line below moves up under the cursor, past the last one it goes up
+                let line = line.min(self.lines.line_count(&self.doc) - 1);
+                self.move_cursor_to(line, column);
+                true
+            }
+            Command::IncreaseNumber => self.add_to_number(1),
+            Command::DecreaseNumber => self.add_to_number(-1),
+            Command::DuplicateLine => {
+                let (line, column) = self.cursor_line_column();
+                self.duplicate_line(line);
+                self.move_cursor_to(line + 1, column);
+                true
+            }
+            Command::Save if self.read_only => {
+                self.log(
+                    Level::Warn,
+                    format!("{} is read-only, not writing", self.path),
+                );
+                false
+            }
+            Command::Save => {
+                self.write_file(true);
+                true
+            }
+            Command::BlockUp | Command::BlockDown | Command::BlockLeft | Command::BlockRight => {
+                let (line, column) = self.cursor_line_column();
+                let last_line = self.lines.line_count(&self.doc) - 1;
+                let block = self
+                    .block_selection
+                    .get_or_insert_with(|| BlockSelection::at(line, column));
+
+                // The head corner moves, extending past the anchor in any direction
+                let head = &mut block.head;
+                match message {
+                    Command::BlockUp => head.0 = head.0.saturating_sub(1),
+                    Command::BlockDown => head.0 = (head.0 + 1).min(last_line),
+                    Command::BlockLeft => head.1 = head.1.saturating_sub(1),
+                    _ => head.1 += 1,
+                }
+
+                true
+            }
+            // Handled by the command loop
+            Command::ToggleSplit
+            | Command::SwitchPane
+            | Command::ToggleLog
+            | Command::GrowLog
+            | Command::ShrinkLog
+            | Command::Redraw
+            | Command::IdleSave
+            | Command::DumpTimings => false,
+            Command::Mouse(event) => self.mouse(event),
+        };
+
+        // Edits shift the document under the selection, drop it rather than tracking it,
+        // indentation, sorting, case changes and surrounding being the exception
+        let keeps_lines = matches!(
+            message,
+            Command::Tab
+                | Command::Indent
+                | Command::Dedent
+                | Command::TabsToSpaces
+                | Command::SpacesToTabs
+                | Command::SortLines { .. }
+                | Command::ToUpper
+                | Command::ToLower
+                | Command::SwapCase
+                | Command::Surround
+                | Command::Unsurround
+        );
+        if message.is_edit() && !keeps_lines {
+            self.selection = None;
+        }
+
+        // Typing and deleting in a row make up one edit, any other command ends it
+        match run_step {
+            Some(step) if self.doc.revision() != revision => {
+                if !self.edit_run_open {
+                    self.last_edit.clear();
+                    self.edit_run_open = true;
+                }
+                self.last_edit.push(step);
+            }
+            Some(_) => {}
+            None => self.edit_run_open = false,
+        }
+
+        redraw || block_cleared || selection_cleared || completion_closed
+    }
+
+    /// Replay the last edit from the cursor, returning whether anything changed.
+    fn repeat_edit(&mut self) -> bool {
+        let run = std::mem::take(&mut self.last_edit);
+        let mut redraw = false;
+        for step in &run {
+            redraw |= self.apply(step.clone());
+        }
+
+        self.last_edit = run;
+        redraw
+    }
+
+    /// Foreground of text in the highlight `hl`, looked up by its capture name.
+    fn hl_color(&self, hl: Option<usize>) -> Color {
+        let name = hl
+            .zip(self.hl_config.as_ref())
+            .and_then(|(hl, config)| config.names().get(hl))
+            .copied();
+        self.config.colors.highlight(name)
+    }
+
+    /// Open the completion list with `items` right under the cursor.
+    fn open_completion(&mut self, items: Vec<String>) {
+        if items.is_empty() {
+            self.completion = None;
+            return;
+        }
+
+        let (x, y) = self.editor_screen.cursor();
+        let (width, height) = (self.editor_screen.width(), self.editor_screen.height());
+        self.completion = Some(FloatingWindow::new(items, x, y, width, height));
+    }
+
+    /// Rebuild the line index from the document, then highlight and draw it again.
+    fn refresh(&mut self) {
+        self.lines = LineIndex::from_doc(&self.doc);
+        self.update_highlights();
+        self.redraw();
+    }
+
+    /// Whether the document changed since it was last highlighted.
+    fn highlights_outdated(&self) -> bool {
+        self.highlighted_at.map(|(revision, _)| revision) != Some(self.doc.revision())
+    }
+
+    fn redraw(&mut self) {
+        // Edits shift the spans until the command loop highlights again, while scrolling
+        // needs the newly shown lines right away
+        let offset = self.editor_screen.line_offset();
+        let scrolled = self.config.highlight_visible_only
+            && !matches!(self.highlighted_at, Some((_, line)) if line == offset);
+        if self.highlighted_at.is_none() || scrolled {
+            self.update_highlights();
+        }
+        self.editor_screen.clear(self.config.colors.bg);
+        self.draw_doc();
+        self.draw_whitespace();
+        self.draw_scrollbar();
+        self.draw_selection();
+        self.draw_block_selection();
+
+        if let Some(window) = &self.completion {
+            window.draw(&self.config.colors);
+            self.editor_screen.compose(window.screen());
+        }
+    }
+
+    // Draw only a portion of the doc to fill the current screen
+    #[cfg_attr(feature = "trace", tracing::instrument(skip_all))]
+    fn draw_doc(&mut self) {
+        let offset = self.editor_screen.line_offset();
+        for y in 0..self.editor_screen.height() {
+            let end = match self.lines.line_range(&self.doc, offset + y) {
+                Some(line) => self.draw_line(y, self.visible_part(line)),
+                None => 0,
+            };
+
+            self.draw_rulers(y, end);
+        }
+    }
+
+    /// Start of `line` up to the last grapheme fitting on the screen, the rest of a long
+    /// line not even being looked at.
+    fn visible_part(&self, line: Range<usize>) -> Range<usize> {
+        let width = self.editor_screen.width();
+        line.start..line.start + cursor::column_at(self.doc.range(line), width)
+    }
+
+    /// Tint the ruler columns of the row `y` from the cell `from`, leaving the text alone.
+    fn draw_rulers(&self, y: usize, from: usize) {
+        for &ruler in &self.config.rulers {
+            let Some(x) = ruler.checked_sub(1) else {
+                continue;
+            };
+
+            if x >= from && x < self.editor_screen.width() {
+                self.editor_screen.set_bg(x, y, self.config.colors.ruler);
+            }
+        }
+    }
+
+    /// Draw the elements of `line` on the row `y`, one run per highlight, returning the
+    /// column right after it.
+    fn draw_line(&mut self, y: usize, line: Range<usize>) -> usize {
+        let mut x = 0;
+        // Start of the text not yet written to the screen
+        let mut run_start = line.start;
+        let mut current_hl: Option<usize> = self.highlight.get(line.start);
+        let mut spans = self.highlight.range(line.start, line.end).peekable();
+
+        for pos in line.clone() {
+            while spans.next_if(|(_, end, _)| *end <= pos).is_some() {}
+            let next_hl: Option<usize> = spans
+                .peek()
+                .filter(|(span_start, _, _)| *span_start <= pos)
+                .map(|(_, _, hl)| *hl);
+
+            // if the current highlight changed, write the pending run to the screen
+            if current_hl != next_hl {
+                let style = Style(self.hl_color(current_hl), self.config.colors.bg);
+                self.text_scratch.clear();
+                self.doc
+                    .extend_str_into(run_start..pos, &mut self.text_scratch);
+                x = self.editor_screen.draw(x, y, &self.text_scratch, style);
+
+                run_start = pos;
+                current_hl = next_hl;
+            }
+        }
+
+        let style = Style(self.hl_color(current_hl), self.config.colors.bg);
+        self.text_scratch.clear();
+        self.doc
+            .extend_str_into(run_start..line.end, &mut self.text_scratch);
+        self.editor_screen.draw(x, y, &self.text_scratch, style)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Command {
+    Quit,
+    /// Close the completion list, cancel a pending find or drop the selection
+    Cancel,
+    Char(char),
+    MoveLeft,
+    WordLeft,
+    WordRight,
+    MoveRight,
+    MoveDown,
+    MoveUp,
+    NewLine,
+    NewLineBelow,
+    NewLineAbove,
+    DeleteForward,
+    DeleteBackWard,
+    DeleteLine,
+    IncreaseNumber,
+    DecreaseNumber,
+    Tab,
+    Indent,
+    Dedent,
+    /// Indent with spaces only, on the selected lines or the whole document
+    TabsToSpaces,
+    /// Indent with tabs, spaces short of a whole tab left as they are
+    SpacesToTabs,
+    Save,
+    /// Save sent by the command loop after a pause in typing, the text being left as is.
+    /// It leaves the state waiting on the next key alone, not going through `apply`
+    IdleSave,
+    DuplicateLine,
+    Yank,
+    Cut,
+    Paste,
+    SelectAll,
+    ExpandSelection,
+    ShrinkSelection,
+    ShowStats,
+    /// Switch between inserting and replacing typed characters
+    ToggleOvertype,
+    ToggleHighlight,
+    RecoverSwap,
+    /// Reorder the selected lines
+    SortLines {
+        reverse: bool,
+        ignore_case: bool,
+    },
+    ToUpper,
+    ToLower,
+    /// Uppercase letters become lowercase and the other way around
+    SwapCase,
+    /// Replay the last run of typing or deleting at the cursor
+    RepeatEdit,
+    /// Wrap the selection in the delimiters picked by the next typed character
+    Surround,
+    /// Remove the delimiters picked by the next typed character around the cursor
+    Unsurround,
+    /// Jump to the next typed character on the cursor line
+    Find {
+        forward: bool,
+        till: bool,
+    },
+    RepeatFind {
+        reverse: bool,
+    },
+    ScrollUp,
+    ScrollDown,
+    CenterCursor,
+    CursorToTop,
+    CursorToBottom,
+    /// Start of the document
+    GotoTop,
+    /// End of the document
+    GotoBottom,
+    /// Next blank line, or the end of the document
+    ParagraphForward,
+    ParagraphBackward,
+    Complete,
+    CompletionNext,
+    CompletionPrev,
+    AcceptCompletion,
+    ToggleSplit,
+    SwitchPane,
+    ToggleLog,
+    /// Give the log pane one more row, showing it when hidden
+    GrowLog,
+    ShrinkLog,
+    /// Repaint the whole terminal, for when something else wrote over it
+    Redraw,
+    DumpTimings,
+    BlockUp,
+    BlockDown,
+    BlockLeft,
+    BlockRight,
+    Mouse(MouseEvent),
+}
+
+impl Command {
+    /// Commands changing the document content.
+    fn is_edit(&self) -> bool {
+        matches!(
+            self,
+            Command::Char(_)
+                | Command::NewLine
+                | Command::NewLineBelow
+                | Command::NewLineAbove
+                | Command::DeleteForward
+                | Command::DeleteBackWard
+                | Command::Tab
+                | Command::Indent
+                | Command::Dedent
+                | Command::TabsToSpaces
+                | Command::SpacesToTabs
+                | Command::DuplicateLine
+                | Command::DeleteLine
+                | Command::IncreaseNumber
+                | Command::DecreaseNumber
+                | Command::SortLines { .. }
+                | Command::ToUpper
+                | Command::ToLower
+                | Command::SwapCase
+                | Command::Cut
+                | Command::Paste
+                | Command::RepeatEdit
+                | Command::Surround
+                | Command::Unsurround
+                | Command::RecoverSwap
+                | Command::AcceptCompletion
+        )
+    }
+
+    /// Commands making up one repeatable edit as long as nothing else comes between them.
+    fn extends_edit_run(&self) -> bool {
+        matches!(
+            self,
+            Command::Char(_)
+                | Command::NewLine
+                | Command::Tab
+                | Command::DeleteForward
+                | Command::DeleteBackWard
+        )
+    }
+
+    fn is_motion(&self) -> bool {
+        matches!(
+            self,
+            Command::MoveLeft
+                | Command::MoveRight
+                | Command::MoveUp
+                | Command::MoveDown
+                | Command::WordLeft
+                | Command::WordRight
+                | Command::GotoTop
+                | Command::GotoBottom
+                | Command::ParagraphForward
+                | Command::ParagraphBackward
+        )
+    }
+}
+
+/// Turn terminal events into commands until shutdown, or until the command handler is
+/// gone and nothing would read them anymore.
+async fn handle_events(
+    mut events: impl Stream<Item = io::Result<Event>> + Unpin,
+    tx: tokio::sync::mpsc::Sender<Command>,
+    mut shutdown: tokio::sync::broadcast::Receiver<()>,
+) {
+    loop {
+        let delay = Delay::new(Duration::from_millis(1_000)).fuse();
+        let event = events.next().fuse();
+
+        select! {
+            _ = delay => {},
+            // Lagging behind or losing every sender means shutting down as well
+            _ = shutdown.recv() => break,
+            maybe_event = event => {
+                let command = match maybe_event {
+                    Some(Ok(Event::Key(e))) => {
+                        match e.code {
+                            KeyCode::Char('s') if e.modifiers.contains(KeyModifiers::CONTROL) => Command::Save,
+                            KeyCode::Char(' ') if e.modifiers.contains(KeyModifiers::CONTROL) => Command::Complete,
+                            KeyCode::Char('y') if e.modifiers.contains(KeyModifiers::CONTROL) => Command::ScrollUp,
+                            KeyCode::Char('e') if e.modifiers.contains(KeyModifiers::CONTROL) => Command::ScrollDown,
+                            KeyCode::Char('g') if e.modifiers.contains(KeyModifiers::CONTROL) => Command::ShowStats,
+                            KeyCode::Char('l') if e.modifiers.contains(KeyModifiers::CONTROL) => Command::Redraw,
+                            KeyCode::Char('l') if e.modifiers.contains(KeyModifiers::ALT) => Command::CenterCursor,
+                            KeyCode::Char('t') if e.modifiers.contains(KeyModifiers::ALT) => Command::CursorToTop,
+                            KeyCode::Char('b') if e.modifiers.contains(KeyModifiers::ALT) => Command::CursorToBottom,
+                            KeyCode::Char('d') if e.modifiers.contains(KeyModifiers::CONTROL) => Command::DuplicateLine,
+                            KeyCode::Char('k') if e.modifiers.contains(KeyModifiers::CONTROL) => Command::DeleteLine,
+                            KeyCode::Char('q') if e.modifiers.contains(KeyModifiers::CONTROL) => Command::Quit,
+                            KeyCode::Char('a') if e.modifiers.contains(KeyModifiers::CONTROL) => Command::SelectAll,
+                            KeyCode::Char('w') if e.modifiers.contains(KeyModifiers::CONTROL) => Command::ExpandSelection,
+                            KeyCode::Char('w') if e.modifiers.contains(KeyModifiers::ALT) => Command::ShrinkSelection,
+                            KeyCode::Char('c') if e.modifiers.contains(KeyModifiers::CONTROL) => Command::Yank,
+                            KeyCode::Char('v') if e.modifiers.contains(KeyModifiers::CONTROL) => Command::Paste,
+                            KeyCode::Char('x') if e.modifiers.contains(KeyModifiers::CONTROL) => Command::Cut,
+                            KeyCode::Char('f') if e.modifiers.contains(KeyModifiers::ALT) => Command::Find { forward: true, till: false },
+                            KeyCode::Char('F') if e.modifiers.contains(KeyModifiers::ALT) => Command::Find { forward: false, till: false },
+                            KeyCode::Char('g') if e.modifiers.contains(KeyModifiers::ALT) => Command::Find { forward: true, till: true },
+                            KeyCode::Char('G') if e.modifiers.contains(KeyModifiers::ALT) => Command::Find { forward: false, till: true },
+                            KeyCode::Char(';') if e.modifiers.contains(KeyModifiers::ALT) => Command::RepeatFind { reverse: false },
+                            KeyCode::Char('.') if e.modifiers.contains(KeyModifiers::ALT) => Command::RepeatEdit,
+                            KeyCode::Char('}') if e.modifiers.contains(KeyModifiers::ALT) => Command::ParagraphForward,
+                            KeyCode::Char('(') if e.modifiers.contains(KeyModifiers::ALT) => Command::Surround,
+                            KeyCode::Char(')') if e.modifiers.contains(KeyModifiers::ALT) => Command::Unsurround,
+                            KeyCode::Char('{') if e.modifiers.contains(KeyModifiers::ALT) => Command::ParagraphBackward,
+                            KeyCode::Char(',') if e.modifiers.contains(KeyModifiers::ALT) => Command::RepeatFind { reverse: true },
+                            KeyCode::Char('s') if e.modifiers.contains(KeyModifiers::ALT) => Command::SortLines { reverse: false, ignore_case: false },
+                            KeyCode::Char('S') if e.modifiers.contains(KeyModifiers::ALT) => Command::SortLines { reverse: true, ignore_case: false },
+                            KeyCode::Char('i') if e.modifiers.contains(KeyModifiers::ALT) => Command::SortLines { reverse: false, ignore_case: true },
+                            KeyCode::Char('u') if e.modifiers.contains(KeyModifiers::ALT) => Command::ToUpper,
+                            KeyCode::Char('U') if e.modifiers.contains(KeyModifiers::ALT) => Command::ToLower,
+                            KeyCode::Char('~') if e.modifiers.contains(KeyModifiers::ALT) => Command::SwapCase,
+                            KeyCode::Char('o') if e.modifiers.contains(KeyModifiers::ALT) => Command::NewLineBelow,
+                            KeyCode::Char('O') if e.modifiers.contains(KeyModifiers::ALT) => Command::NewLineAbove,
+                            KeyCode::Char('>') if e.modifiers.contains(KeyModifiers::ALT) => Command::Indent,
+                            KeyCode::Char('a') if e.modifiers.contains(KeyModifiers::ALT) => Command::IncreaseNumber,
+                            KeyCode::Char('x') if e.modifiers.contains(KeyModifiers::ALT) => Command::DecreaseNumber,
+                            KeyCode::Char('<') if e.modifiers.contains(KeyModifiers::ALT) => Command::Dedent,
+                            KeyCode::Char('E') if e.modifiers.contains(KeyModifiers::ALT) => Command::TabsToSpaces,
+                            KeyCode::Char('T') if e.modifiers.contains(KeyModifiers::ALT) => Command::SpacesToTabs,
+                            KeyCode::Char(c) => Command::Char(c),
+                            KeyCode::Esc => Command::Cancel,
+                            KeyCode::Left if e.modifiers.contains(KeyModifiers::ALT) => Command::BlockLeft,
+                            KeyCode::Right if e.modifiers.contains(KeyModifiers::ALT) => Command::BlockRight,
+                            KeyCode::Up if e.modifiers.contains(KeyModifiers::ALT) => Command::BlockUp,
+                            KeyCode::Down if e.modifiers.contains(KeyModifiers::ALT) => Command::BlockDown,
+                            KeyCode::Up if e.modifiers.contains(KeyModifiers::CONTROL) => Command::GrowLog,
+                            KeyCode::Down if e.modifiers.contains(KeyModifiers::CONTROL) => Command::ShrinkLog,
+                            KeyCode::Left if e.modifiers.contains(KeyModifiers::CONTROL) => Command::WordLeft,
+                            KeyCode::Left => Command::MoveLeft,
+                            KeyCode::Right if e.modifiers.contains(KeyModifiers::CONTROL) => Command::WordRight,
+                            KeyCode::Right => Command::MoveRight,
+                            KeyCode::Home if e.modifiers.contains(KeyModifiers::CONTROL) => Command::GotoTop,
+                            KeyCode::End if e.modifiers.contains(KeyModifiers::CONTROL) => Command::GotoBottom,
+                            KeyCode::Up => Command::MoveUp,
+                            KeyCode::Down => Command::MoveDown,
+                            KeyCode::Enter => Command::NewLine,
+                            KeyCode::Insert => Command::ToggleOvertype,
+                            KeyCode::F(5) => Command::ToggleHighlight,
+                            KeyCode::F(6) => Command::RecoverSwap,
+                            KeyCode::F(7) => Command::ToggleSplit,
+                            KeyCode::F(8) => Command::SwitchPane,
+                            KeyCode::F(9) => Command::DumpTimings,
+                            KeyCode::F(10) => Command::ToggleLog,
+                            KeyCode::BackTab => Command::Dedent,
+                            KeyCode::Tab => Command::Tab,
+                            KeyCode::Delete => Command::DeleteForward,
+                            KeyCode::Backspace => Command::DeleteBackWard,
+                            _ => continue,
+                        }
+                    },
+                    // Every move of the pointer is reported, none of them doing anything
+                    Some(Ok(Event::Mouse(e))) if e.kind == MouseEventKind::Moved => continue,
+                    Some(Ok(Event::Mouse(e))) => Command::Mouse(e),
+                    Some(Ok(e)) => {
+                        println!("{e:?}");
+                        continue;
+                    }
+                    Some(Err(e)) => {
+                        println!("Error: {:?}\r", e);
+                        continue;
+                    }
+                    None => {
+                        break;
+                    }
+                };
+
+                if tx.send(command).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+async fn handle_command(
+    path: Option<String>,
+    mut rx: tokio::sync::mpsc::Receiver<Command>,
+    _hl_event: tokio::sync::mpsc::Sender<()>,
+    shutdown_tx: tokio::sync::broadcast::Sender<()>,
+) -> io::Result<()> {
+    let (width, height) = terminal::size()?;
+    let mut log_height = height as usize / 10;
+    let (editor_rows, status_rows, log_rows) = pane_rows(height as usize, log_height, true);
+    let width = width as usize;
+    let offset_x = 0;
+    let mut log_buffer = vec![
+        LogLine::new(Level::Debug, format!("Terminal size ({width}, {height})")),
+        LogLine::new(
+            Level::Debug,
+            format!("Editor dimension ({width}, {})", editor_rows.len()),
+        ),
+        LogLine::new(
+            Level::Debug,
+            format!("Log dimension ({width}, {})", log_rows.len()),
+        ),
+    ];
+
+    init_panic_hook();
+    #[cfg(feature = "trace")]
+    let timings = timing::FrameTimings::install();
+
+    let mut config = Config::default();
+    if let Some(path) = colors::scheme_path() {
+        match ColorScheme::load(&path) {
+            Ok(colors) => config.colors = colors,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => log_buffer.push(LogLine::new(
+                Level::Error,
+                format!("Failed to load {}: {err}", path.display()),
+            )),
+        }
+    }
+
+    let colors = &config.colors;
+    let status_screen = Screen::new(width, 1, offset_x, status_rows.start, colors.status_bg)?;
+    let log_screen = Screen::new(
+        width,
+        log_rows.len(),
+        offset_x,
+        log_rows.start,
+        colors.log_bg,
+    )?;
+    let mut editor_screen = Screen::new(
+        width,
+        editor_rows.len(),
+        offset_x,
+        editor_rows.start,
+        colors.bg,
+    )?;
+    if config.scrollbar {
+        editor_screen.reserve_right(1);
+    }
+    let path = path.unwrap_or_default();
+    let file = if path.is_empty() {
+        String::new()
+    } else {
+        match fs::read_to_string(&path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                log_buffer.push(LogLine::new(Level::Info, format!("New file {path}")));
+                String::new()
+            }
+            Err(err) => return Err(err),
+        }
+    };
+    let recoverable = (!path.is_empty())
+        .then(|| swap::find_recoverable(&path))
+        .flatten();
+    let read_only = fs::metadata(&path).is_ok_and(|meta| meta.permissions().readonly());
+    let src: Arc<[String]> = graphemes(&file).into();
+    let mut editor = Editor::new(path, &src, editor_screen, status_screen, log_screen, config);
+    // Lines of huge files take a while to find, the editor scans the document until then
+    let original = Arc::clone(&src);
+    let mut indexing = tokio::task::spawn_blocking(move || line_endings(&original));
+    editor.read_only = read_only;
+    editor.log_buffer.borrow_mut().extend(log_buffer);
+    if let Some(swap) = recoverable {
+        editor.log(
+            Level::Warn,
+            format!(
+                "Found unsaved changes in {}, F6 to recover them",
+                swap.display()
+            ),
+        );
+    }
+    if !should_highlight(file.len(), editor.config.highlight_max_bytes) {
+        editor.highlighting = false;
+        editor.log(
+            Level::Info,
+            format!(
+                "Syntax highlighting off, file is larger than {} bytes (F5 to turn it on)",
+                editor.config.highlight_max_bytes
+            ),
+        );
+    }
+
+    editor.update_highlights();
+    editor.draw_doc();
+    editor.draw_scrollbar();
+    editor.draw_status();
+    editor.draw_logs();
+    editor.status_screen.present();
+    editor.editor_screen.present();
+
+    let columns = offset_x..offset_x + width;
+    let mut divider: Option<Screen> = None;
+    let mut show_log = true;
+    let mut autosave = tokio::time::interval(editor.config.autosave_interval);
+    let mut idle_check = tokio::time::interval(Duration::from_secs(1));
+    let mut last_input = Instant::now();
+    // Revision an idle save was last tried at, a failing one not being retried every second
+    let mut idle_save_tried = None;
+    loop {
+        let message = select! {
+            message = rx.recv() => {
+                last_input = Instant::now();
+                message
+            }
+            _ = autosave.tick() => {
+                editor.autosave().await;
+                continue;
+            }
+            _ = tokio::time::sleep(HIGHLIGHT_DELAY), if editor.highlights_outdated() => {
+                editor.update_highlights();
+                editor.redraw();
+                editor.with_other_pane(|other| {
+                    other.update_highlights();
+                    other.redraw();
+                    other.editor_screen.present();
+                });
+                // Last, for the terminal cursor to end up in the focused pane
+                editor.editor_screen.present();
+                continue;
+            }
+            endings = &mut indexing, if editor.lines.is_pending() => {
+                editor.lines = match endings {
+                    Ok(endings) => LineIndex::from_line_endings(&editor.doc, &endings),
+                    Err(err) => {
+                        editor.log(Level::Error, format!("Failed to index lines: {err}"));
+                        LineIndex::from_doc(&editor.doc)
+                    }
+                };
+                continue;
+            }
+            _ = idle_check.tick() => {
+                let revision = editor.doc.revision();
+                let tried = idle_save_tried == Some(revision);
+                if tried || !editor.idle_save_due(last_input.elapsed()) {
+                    continue;
+                }
+
+                idle_save_tried = Some(revision);
+                Some(Command::IdleSave)
+            }
+        };
+
+        let Some(message) = message else {
+            break;
+        };
+
+        match message {
+            Command::Quit => {
+                // Fails only when the event loop is already gone
+                let _ = shutdown_tx.send(());
+                break;
+            }
+            Command::ToggleSplit => {
+                // The focused pane stays, the other one only being a view of the same document
+                if editor.other_pane.take().is_some() {
+                    divider = None;
+                    editor.editor_screen.set_columns(columns.clone());
+                } else {
+                    let (left, right) = vertical_split(columns.start, columns.len());
+                    editor.editor_screen.set_columns(left.clone());
+                    let colors = &editor.config.colors;
+                    let (rows, _, _) = pane_rows(height as usize, log_height, show_log);
+                    let mut editor_screen = Screen::on_stdout(
+                        right.len(),
+                        rows.len(),
+                        right.start,
+                        rows.start,
+                        colors.bg,
+                    );
+                    if editor.config.scrollbar {
+                        editor_screen.reserve_right(1);
+                    }
+                    divider = Some(divider_screen(left.end, rows.start, rows.len(), colors));
+                    editor.split(editor_screen);
+                }
+                editor.draw_other_pane();
+                editor.redraw();
+            }
+            Command::SwitchPane => {
+                if editor.switch_pane() {
+                    editor.draw_other_pane();
+                    editor.redraw();
+                }
+            }
+            Command::ToggleLog | Command::GrowLog | Command::ShrinkLog => {
+                match message {
+                    Command::ToggleLog => show_log = !show_log,
+                    Command::GrowLog => {
+                        log_height += 1;
+                        show_log = true;
+                    }
+                    _ => log_height = log_height.saturating_sub(1).max(1),
+                }
+
+                let (rows, status_rows, log_rows) =
+                    pane_rows(height as usize, log_height, show_log);
+                // Growing stops short of the editor, keep the height actually shown
+                if show_log {
+                    log_height = log_rows.len();
+                }
+                editor.with_other_pane(|other| {
+                    other.editor_screen.set_rows(rows.clone());
+                    other.redraw();
+                    other.editor_screen.present();
+                });
+                editor.editor_screen.set_rows(rows.clone());
+                editor.status_screen.set_rows(status_rows);
+                editor.log_screen.set_rows(log_rows);
+                editor.redraw();
+                if let Some(divider) = &mut divider {
+                    divider.set_rows(rows);
+                    draw_divider(divider, &editor.config.colors);
+                }
+            }
+            Command::Redraw => {
+                let size = terminal::size()?;
+                if size != (width as u16, height) {
+                    let (columns, rows) = size;
+                    editor.log(
+                        Level::Warn,
+                        format!("Terminal resized to ({columns}, {rows}), restart to use it"),
+                    );
+                }
+                editor.editor_screen.invalidate()?;
+                editor.refresh();
+                editor.with_other_pane(|other| {
+                    other.update_highlights();
+                    other.redraw();
+                    other.editor_screen.present();
+                });
+            }
+            Command::IdleSave => editor.write_file(false),
+            Command::DumpTimings => {
+                #[cfg(feature = "trace")]
+                for line in timings.report() {
+                    editor.log(Level::Info, line);
+                }
+                #[cfg(not(feature = "trace"))]
+                editor.log(Level::Warn, "Frame timings need the trace feature");
+            }
+            message => {
+                let revision = editor.doc.revision();
+                if editor.apply(message) {
+                    editor.redraw();
+                }
+                // Both panes show the document, edits show up in the other one as well
+                if editor.doc.revision() != revision {
+                    editor.draw_other_pane();
+                }
+            }
+        }
+
+        editor.draw_status();
+        editor.status_screen.present();
+        if show_log {
+            editor.log_screen.clear(editor.config.colors.log_bg);
+            editor.draw_logs();
+            editor.log_screen.present();
+        }
+        if let Some(divider) = &divider {
+            divider.present();
+        }
+        editor.editor_screen.present();
+    }
+
+    Ok(())
+}
+
+/// Time without input after an edit before highlighting the document again, the shifted
+/// spans of the previous parse being shown meanwhile.
+const HIGHLIGHT_DELAY: Duration = Duration::from_millis(100);
+
+/// Terminal rows of the editor, the status line and the log pane in a `height` rows
+/// terminal, the log pane taking `log_height` rows at the bottom when shown. The log pane
+/// never takes the last editor row.
+fn pane_rows(
+    height: usize,
+    log_height: usize,
+    show_log: bool,
+) -> (Range<usize>, Range<usize>, Range<usize>) {
+    let log_height = if show_log {
+        log_height.min(height.saturating_sub(2))
+    } else {
+        0
+    };
+    let editor_height = height.saturating_sub(log_height + 1);
+    let status = editor_height..editor_height + 1;
+    (0..editor_height, status, editor_height + 1..height)
+}
+
+/// One column wide screen drawing the line between split panes.
+fn divider_screen(x: usize, y: usize, height: usize, colors: &ColorScheme) -> Screen {
+    let divider = Screen::on_stdout(1, height, x, y, colors.bg);
+    draw_divider(&divider, colors);
+    divider
+}
+
+fn draw_divider(divider: &Screen, colors: &ColorScheme) {
+    for y in 0..divider.height() {
+        divider.draw(0, y, "│", Style(colors.whitespace, colors.bg));
+    }
+}
+
+/// Whether a buffer left alone for `idle` should be written, `delay` being zero when
+/// saving on idle is off.
+fn should_save_when_idle(modified: bool, idle: Duration, delay: Duration) -> bool {
+    modified && !delay.is_zero() && idle >= delay
+}
+
+/// Whether to highlight a `size` bytes file, tree-sitter dominating latency on huge ones.
+fn should_highlight(size: usize, max_bytes: usize) -> bool {
+    size <= max_bytes
+}
+
+impl<'a> Editor<'a> {
+    /// An editor over `src` without any terminal attached, unnamed.
+    #[cfg(test)]
+    fn headless(src: &'a [String]) -> Self {
+        Self::headless_at(String::new(), src)
+    }
+
+    /// An editor over `src` without any terminal attached, saving to `path`.
+    fn headless_at(path: String, src: &'a [String]) -> Self {
+        let config = Config::default();
+        let colors = &config.colors;
+        let editor_screen = Screen::headless(80, 20, 0, 0, colors.bg);
+        let status_screen = Screen::headless(80, 1, 0, 20, colors.status_bg);
+        let log_screen = Screen::headless(80, 4, 0, 21, colors.log_bg);
+        let mut editor = Self::new(path, src, editor_screen, status_screen, log_screen, config);
+        editor.lines = LineIndex::from_doc(&editor.doc);
+        editor
+    }
+
+    /// Apply `commands` as if typed and return the resulting document.
+    fn run_script(&mut self, commands: impl IntoIterator<Item = Command>) -> String {
+        for command in commands {
+            if self.apply(command) {
+                self.redraw();
+            }
+        }
+
+        self.content()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::fs;
+    use std::time::Duration;
+
+    use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
+    use piece_table::graphemes;
+
+    use crate::screen::{vertical_split, Screen, Style};
+    use crossterm::style::Color;
+
+    use crate::colors::ColorScheme;
+    use crate::cursor;
+    use crate::grammar::{Grammar, Rust};
+    use crate::hl::VISIBLE_MARGIN;
+    use crate::indent::IndentStyle;
+    use crate::log::Level;
+    use crate::selection::Selection;
+    use crate::{
+        handle_events, pane_rows, parse_args, should_highlight, should_save_when_idle, Command,
+        Editor, Startup,
+    };
+
+    #[test]
+    fn run_headless_script() {
+        let src = graphemes("hello\nworld");
+        let mut editor = Editor::headless(&src);
+
+        let output = editor.run_script([
+            Command::Char('>'),
+            Command::Char(' '),
+            Command::MoveDown,
+            Command::DeleteForward,
+            Command::Char('W'),
+            Command::MoveRight,
+            Command::DeleteBackWard,
+        ]);
+
+        assert_eq!(output, "> hello\nwoWd");
+    }
+
+    #[test]
+    fn type_at_document_end() {
+        let src = graphemes("ab");
+        let mut editor = Editor::headless(&src);
+
+        let output = editor.run_script([
+            Command::MoveRight,
+            Command::MoveRight,
+            Command::Char('c'),
+            Command::Char('d'),
+        ]);
+
+        assert_eq!(output, "abcd");
+    }
+
+    #[test]
+    fn read_only_rejects_edits() {
+        let src = graphemes("hello");
+        let mut editor = Editor::headless(&src);
+        editor.read_only = true;
+
+        let output = editor.run_script([
+            Command::Char('a'),
+            Command::MoveRight,
+            Command::NewLine,
+            Command::DeleteForward,
+            Command::DeleteBackWard,
+            Command::Save,
+        ]);
+
+        assert_eq!(output, "hello");
+        assert_eq!(editor.cursor_line_column(), (0, 1));
+        assert!(editor.status_line().contains("[RO]"));
+        assert!(editor
+            .log_buffer
+            .borrow()
+            .iter()
+            .all(|log| log.message.contains("read-only")));
+
+        editor.read_only = false;
+        assert_eq!(editor.run_script([Command::Char('a')]), "haello");
+        assert!(!editor.status_line().contains("[RO]"));
+    }
+
+    #[test]
+    fn duplicate_line() {
+        let src = graphemes("one\ntwo\nthree");
+        let mut editor = Editor::headless(&src);
+
+        let inserted = editor.duplicate_line(1);
+        assert_eq!(inserted, 7..11);
+        assert_eq!(editor.run_script([]), "one\ntwo\ntwo\nthree");
+
+        let inserted = editor.duplicate_line(3);
+        assert_eq!(inserted, 17..23);
+        assert_eq!(editor.run_script([]), "one\ntwo\ntwo\nthree\nthree");
+
+        // The cursor follows the copy, keeping its column
+        let output = editor.run_script([
+            Command::MoveRight,
+            Command::MoveRight,
+            Command::DuplicateLine,
+            Command::Char('!'),
+        ]);
+        assert_eq!(output, "one\non!e\ntwo\ntwo\nthree\nthree");
+        assert_eq!(editor.lines.line_count(&editor.doc), 6);
+    }
+
+    #[test]
+    fn render_whitespace() {
+        let src = graphemes("a \tb  \n");
+        let mut editor = Editor::headless(&src);
+        editor.config.render_whitespace = true;
+        editor.redraw();
+
+        let colors = ColorScheme::default();
+        let cell = |x| editor.editor_screen.cell(x, 0).unwrap();
+        let ws = |bg| Style(colors.whitespace, bg);
+        assert_eq!(cell(0).1, "a");
+        assert_eq!(cell(1), (ws(colors.bg), "·".to_string()));
+        assert_eq!(cell(2), (ws(colors.bg), "→".to_string()));
+        assert_eq!(cell(3), (ws(colors.bg), " ".to_string()));
+        assert_eq!(cell(4).1, "b");
+        assert_eq!(cell(5), (ws(colors.trailing_whitespace), "·".to_string()));
+        assert_eq!(cell(6), (ws(colors.trailing_whitespace), "·".to_string()));
+    }
+
+    #[test]
+    fn delete_at_document_edges() {
+        let src = graphemes("ab");
+        let mut editor = Editor::headless(&src);
+
+        let output = editor.run_script([Command::DeleteBackWard, Command::MoveRight]);
+        assert_eq!(output, "ab");
+
+        let output = editor.run_script([
+            Command::MoveRight,
+            Command::DeleteForward,
+            Command::DeleteBackWard,
+            Command::DeleteBackWard,
+            Command::DeleteBackWard,
+        ]);
+        assert_eq!(output, "");
+        assert_eq!(editor.cursor_line_column(), (0, 0));
+    }
+
+    #[test]
+    fn move_by_word_at_document_edges() {
+        let src = graphemes("one two");
+        let mut editor = Editor::headless(&src);
+        editor.move_cursor_to_idx(7);
+        editor.apply(Command::WordRight);
+        assert_eq!(editor.get_cursor_absolute_position(), 7);
+        editor.apply(Command::WordLeft);
+        assert_eq!(editor.get_cursor_absolute_position(), 4);
+
+        let src = vec![];
+        let mut editor = Editor::headless(&src);
+        assert!(!editor.apply(Command::WordLeft));
+        assert!(!editor.apply(Command::WordRight));
+        assert_eq!(editor.get_cursor_absolute_position(), 0);
+    }
+
+    #[test]
+    fn yank_and_paste_line() {
+        let src = graphemes("one\ntwo");
+        let mut editor = Editor::headless(&src);
+
+        let output = editor.run_script([
+            Command::Yank,
+            Command::MoveDown,
+            Command::Paste,
+            Command::Paste,
+        ]);
+        assert_eq!(output, "one\none\none\ntwo");
+        assert_eq!(editor.cursor_line_column(), (3, 0));
+
+        // The last line has no line ending to copy
+        let output = editor.run_script([Command::Yank, Command::Paste]);
+        assert_eq!(output, "one\none\none\ntwotwo");
+    }
+
+    #[test]
+    fn cut_and_paste() {
+        let src = graphemes("one\ntwo\nthree");
+        let mut editor = Editor::headless(&src);
+
+        let output = editor.run_script([Command::Cut, Command::MoveDown, Command::Paste]);
+        assert_eq!(output, "two\none\nthree");
+        assert_eq!(editor.register.concat(), "one\n");
+
+        editor.selection = Some(Selection::new(4, 6));
+        let output = editor.run_script([Command::Cut]);
+        assert_eq!(output, "two\ne\nthree");
+        assert_eq!(editor.register.concat(), "on");
+        assert_eq!(editor.get_cursor_absolute_position(), 4);
+        assert!(editor.selection.is_none());
+    }
+
+    #[test]
+    fn draw_last_line_without_line_ending() {
+        let src = graphemes("ab\ncd");
+        let mut editor = Editor::headless(&src);
+        editor.redraw();
+
+        let row: Vec<String> = (0..3)
+            .map(|x| editor.editor_screen.cell(x, 1).unwrap().1)
+            .collect();
+        assert_eq!(row, ["c", "d", " "]);
+    }
+
+    #[test]
+    fn draw_only_the_visible_part_of_a_long_line() {
+        let mut src = vec!["x".to_string(); 100_000];
+        src.extend(graphemes("\nend  "));
+        let mut editor = Editor::headless(&src);
+        editor.config.render_whitespace = true;
+        editor.selection = Some(Selection::new(10, 100_003));
+
+        // Only as many graphemes as fit on the screen are read
+        let read = std::cell::Cell::new(0);
+        let line = editor
+            .doc
+            .range(0..100_000)
+            .inspect(|_| read.set(read.get() + 1));
+        assert_eq!(cursor::column_at(line, 80), 80);
+        assert_eq!(read.get(), 81);
+        assert_eq!(editor.visible_part(0..100_000), 0..80);
+
+        editor.redraw();
+        let cell = |x, y| editor.editor_screen.cell(x, y).unwrap();
+        assert_eq!(cell(79, 0).1, "x");
+        let selection = editor.config.colors.selection;
+        assert_eq!(cell(79, 0).0 .1, selection);
+        assert_eq!(cell(1, 1).0 .1, selection);
+        assert_ne!(cell(2, 1).0 .1, selection);
+        // Trailing whitespace is still found from the line end
+        assert_eq!(cell(3, 1).1, "·");
+        assert_eq!(cell(4, 1).0 .1, editor.config.colors.trailing_whitespace);
+    }
+
+    #[test]
+    fn select_all_then_delete() {
+        let src = graphemes("ab\nc");
+        let mut editor = Editor::headless(&src);
+
+        editor.run_script([Command::SelectAll]);
+        let selection = ColorScheme::default().selection;
+        let bg = |x, y| editor.editor_screen.cell(x, y).unwrap().0 .1;
+        assert_eq!(bg(0, 0), selection);
+        assert_eq!(bg(2, 0), selection);
+        assert_eq!(bg(0, 1), selection);
+        assert_ne!(bg(1, 1), selection);
+
+        let output = editor.run_script([Command::DeleteBackWard]);
+        assert_eq!(output, "");
+        assert_eq!(editor.cursor_line_column(), (0, 0));
+
+        // Nothing to select in an empty document
+        assert_eq!(
+            editor.run_script([Command::SelectAll, Command::DeleteForward]),
+            ""
+        );
+        assert!(editor.selection.is_none());
+    }
+
+    #[test]
+    fn expand_and_shrink_selection() {
+        let src = graphemes("f(a, [b, c])");
+        let mut editor = Editor::headless(&src);
+        editor.move_cursor_to_idx(6);
+
+        editor.run_script([Command::ExpandSelection, Command::ExpandSelection]);
+        assert_eq!(editor.selection, Some(Selection::new(5, 11)));
+        assert_eq!(editor.get_cursor_absolute_position(), 11);
+
+        editor.run_script([Command::ShrinkSelection]);
+        assert_eq!(editor.selection, Some(Selection::new(6, 10)));
+
+        // Shrinking past the first expansion gets the cursor back
+        editor.run_script([Command::ShrinkSelection, Command::ShrinkSelection]);
+        assert_eq!(editor.selection, None);
+        assert_eq!(editor.get_cursor_absolute_position(), 6);
+    }
+
+    #[test]
+    fn split_into_views_of_the_same_document() {
+        let src = graphemes("one\ntwo");
+        let mut editor = Editor::headless(&src);
+        editor.run_script([Command::MoveDown, Command::Char('2')]);
+
+        let (left_cols, right_cols) = vertical_split(0, 80);
+        editor.editor_screen.set_columns(left_cols);
+        let bg = ColorScheme::default().bg;
+        let screen = Screen::headless(right_cols.len(), 20, right_cols.start, 0, bg);
+        editor.split(screen);
+        assert_eq!(editor.cursor_line_column(), (1, 1));
+        assert_eq!(editor.editor_screen.width(), 40);
+
+        // Typing in the focused pane moves the cursor of the other one past the text
+        editor.move_cursor_to(0, 0);
+        assert_eq!(editor.run_script([Command::Char('!')]), "!one\n2two");
+        assert_eq!(editor.editor_screen.cell(1, 0).unwrap().1, "o");
+        editor.draw_other_pane();
+        editor.with_other_pane(|other| {
+            assert_eq!(other.editor_screen.cell(0, 0).unwrap().1, "!");
+            assert_eq!(other.cursor_line_column(), (1, 1));
+        });
+
+        assert!(editor.switch_pane());
+        assert_eq!(editor.cursor_line_column(), (1, 1));
+        assert_eq!(editor.run_script([Command::DeleteBackWard]), "!one\ntwo");
+        editor.with_other_pane(|other| assert_eq!(other.cursor_line_column(), (0, 1)));
+    }
+
+    #[test]
+    fn draw_logs_above_the_level_by_color() {
+        let mut editor = Editor::headless(&[]);
+        editor.log(Level::Error, "failed");
+        editor.log(Level::Debug, "moving");
+        editor.log(Level::Warn, "read-only");
+        editor.draw_logs();
+
+        let colors = ColorScheme::default();
+        let row = |y| -> (Color, String) {
+            let text = (0..13)
+                .map(|x| editor.log_screen.cell(x, y).unwrap().1)
+                .collect();
+            (editor.log_screen.cell(0, y).unwrap().0 .0, text)
+        };
+        assert_eq!(row(0), (colors.log_warn, "0 - read-only".to_string()));
+        assert_eq!(row(1).0, colors.log_error);
+        assert!(row(1).1.starts_with("1 - failed"));
+    }
+
+    #[test]
+    fn script_an_editing_session() {
+        let src = graphemes("fn main() {\n}\n");
+        let mut editor = Editor::headless(&src);
+
+        let output = editor.run_script([
+            Command::MoveDown,
+            Command::MoveUp,
+            Command::GotoBottom,
+            Command::MoveUp,
+            Command::MoveUp,
+            Command::ToggleOvertype,
+            Command::ToggleOvertype,
+        ]);
+        assert_eq!(output, "fn main() {\n}\n");
+        assert_eq!(editor.cursor_line_column(), (0, 0));
+
+        editor.move_cursor_to_idx(11);
+        let mut commands = vec![Command::NewLine, Command::Tab];
+        commands.extend("let x = 41;".chars().map(Command::Char));
+        commands.extend([
+            Command::MoveLeft,
+            Command::MoveLeft,
+            Command::IncreaseNumber,
+            Command::MoveRight,
+            Command::DeleteForward,
+        ]);
+        let output = editor.run_script(commands);
+        assert_eq!(output, "fn main() {\n    let x = 42\n}\n");
+        assert_eq!(editor.cursor_line_column(), (1, 14));
+
+        // Layout commands are left to the command loop
+        assert!(!editor.apply(Command::ToggleLog));
+        assert!(!editor.apply(Command::Quit));
+    }
+
+    #[test]
+    fn repeat_the_last_edit() {
+        let src = graphemes("one\ntwo\nthree");
+        let mut editor = Editor::headless(&src);
+
+        // Typing in a row is one edit, repeated wherever the cursor went
+        editor.run_script([Command::Char('-'), Command::Char(' ')]);
+        editor.move_cursor_to_idx(6);
+        let output = editor.run_script([Command::RepeatEdit]);
+        assert_eq!(output, "- one\n- two\nthree");
+        assert_eq!(editor.cursor_line_column(), (1, 2));
+        editor.move_cursor_to_idx(12);
+        let output = editor.run_script([Command::RepeatEdit]);
+        assert_eq!(output, "- one\n- two\n- three");
+
+        // A motion in between starts a new edit
+        editor.move_cursor_to_idx(8);
+        let output = editor.run_script([
+            Command::Char('x'),
+            Command::MoveRight,
+            Command::DeleteBackWard,
+            Command::DeleteBackWard,
+            Command::DeleteBackWard,
+        ]);
+        assert_eq!(output, "- one\n-wo\n- three");
+        editor.move_cursor_to_idx(14);
+        let output = editor.run_script([Command::RepeatEdit]);
+        assert_eq!(output, "- one\n-wo\n-ree");
+        assert_eq!(editor.cursor_line_column(), (2, 1));
+
+        // Commands that changed nothing don't replace it
+        editor.move_cursor_to_idx(0);
+        let output = editor.run_script([Command::DeleteBackWard, Command::RepeatEdit]);
+        assert_eq!(output, "- one\n-wo\n-ree");
+        editor.move_cursor_to_idx(editor.doc.len());
+        let output = editor.run_script([Command::RepeatEdit]);
+        assert_eq!(output, "- one\n-wo\n-");
+    }
+
+    #[test]
+    fn lay_out_the_log_pane() {
+        assert_eq!(pane_rows(50, 5, true), (0..44, 44..45, 45..50));
+        // Hidden, the editor takes its rows
+        assert_eq!(pane_rows(50, 5, false), (0..49, 49..50, 50..50));
+        assert_eq!(pane_rows(50, 12, true), (0..37, 37..38, 38..50));
+        // One editor row is always left
+        assert_eq!(pane_rows(10, 30, true), (0..1, 1..2, 2..10));
+        assert_eq!(pane_rows(1, 3, true), (0..0, 0..1, 1..1));
+    }
+
+    #[test]
+    fn wrap_long_log_lines() {
+        let mut editor = Editor::headless(&[]);
+        editor.log(Level::Info, "older");
+        editor.log(Level::Info, format!("{}日本", "x".repeat(75)));
+        editor.draw_logs();
+
+        let row = |y| -> String {
+            (0..80)
+                .filter_map(|x| editor.log_screen.cell(x, y))
+                .map(|(_, g)| g)
+                .collect()
+        };
+        // The wide grapheme not fitting at the end of the row starts the next one
+        assert_eq!(row(0), format!("0 - {} ", "x".repeat(75)));
+        assert!(row(1).starts_with("日本 "));
+        assert!(row(2).starts_with("1 - older "));
+    }
+
+    #[test]
+    fn show_document_or_selection_stats() {
+        let src = graphemes("one two\nthree");
+        let mut editor = Editor::headless(&src);
+        let last_log = |editor: &Editor| editor.log_buffer.borrow().last().unwrap().message.clone();
+
+        editor.apply(Command::ShowStats);
+        assert_eq!(
+            last_log(&editor),
+            "Document: 2 lines, 3 words, 13 chars, 13 bytes"
+        );
+
+        editor.selection = Some(Selection::new(4, 9));
+        editor.apply(Command::ShowStats);
+        assert_eq!(
+            last_log(&editor),
+            "Selection: 2 lines, 2 words, 5 chars, 5 bytes"
+        );
+    }
+
+    #[test]
+    fn combining_mark_is_a_single_cursor_stop() {
+        let src = graphemes("e\u{301}a");
+        assert_eq!(src.len(), 2);
+        let mut editor = Editor::headless(&src);
+
+        editor.apply(Command::MoveRight);
+        assert_eq!(editor.cursor_line_column(), (0, 1));
+        assert_eq!(editor.editor_screen.cursor(), (1, 0));
+
+        // A typed mark joins the grapheme before it
+        let output = editor.run_script([Command::MoveRight, Command::Char('\u{308}')]);
+        assert_eq!(output, "e\u{301}a\u{308}");
+        assert_eq!(editor.doc.len(), 2);
+        assert_eq!(editor.editor_screen.cursor(), (2, 0));
+        editor.run_script([Command::MoveLeft, Command::MoveLeft]);
+        assert_eq!(editor.cursor_line_column(), (0, 0));
+
+        // Without a base before it, a mark stays on its own
+        editor.apply(Command::Char('\u{301}'));
+        assert_eq!(editor.doc.len(), 3);
+        assert_eq!(editor.cursor_line_column(), (0, 1));
+    }
+
+    #[test]
+    fn find_and_repeat_on_the_cursor_line() {
+        let src = graphemes("a,b,c,d\n,");
+        let mut editor = Editor::headless(&src);
+        let find = |forward, till| Command::Find { forward, till };
+
+        editor.run_script([find(true, false), Command::Char(',')]);
+        assert_eq!(editor.get_cursor_absolute_position(), 1);
+        editor.run_script([Command::RepeatFind { reverse: false }]);
+        assert_eq!(editor.get_cursor_absolute_position(), 3);
+        editor.run_script([Command::RepeatFind { reverse: true }]);
+        assert_eq!(editor.get_cursor_absolute_position(), 1);
+
+        editor.run_script([find(true, true), Command::Char('d')]);
+        assert_eq!(editor.get_cursor_absolute_position(), 5);
+
+        // No match leaves the cursor and the document alone
+        let output = editor.run_script([find(false, false), Command::Char('x')]);
+        assert_eq!(output, "a,b,c,d\n,");
+        assert_eq!(editor.get_cursor_absolute_position(), 5);
+    }
+
+    #[test]
+    fn modified_until_back_to_the_saved_content() {
+        let src = graphemes("ab");
+        let mut editor = Editor::headless(&src);
+        assert!(!editor.status_line().contains("[+]"));
+
+        editor.run_script([Command::Char('c')]);
+        assert!(editor.status_line().contains("[+]"));
+        editor.run_script([Command::DeleteBackWard]);
+        assert!(!editor.status_line().contains("[+]"));
+    }
+
+    #[test]
+    fn overtype_until_the_line_end() {
+        let src = graphemes("ab🦀\ncd");
+        let mut editor = Editor::headless(&src);
+        editor.apply(Command::ToggleOvertype);
+        assert!(editor.status_line().contains("[OVR]"));
+
+        editor.apply(Command::MoveRight);
+        assert_eq!(editor.run_script([Command::Char('x')]), "ax🦀\ncd");
+        // A wide grapheme is replaced as a whole, then the line ending is kept
+        assert_eq!(editor.run_script([Command::Char('y')]), "axy\ncd");
+        assert_eq!(editor.editor_screen.cursor(), (3, 0));
+        assert_eq!(editor.run_script([Command::Char('z')]), "axyz\ncd");
+
+        // Same at the end of the document
+        editor.move_cursor_to_idx(7);
+        assert_eq!(editor.run_script([Command::Char('!')]), "axyz\ncd!");
+
+        editor.apply(Command::ToggleOvertype);
+        assert!(!editor.status_line().contains("[OVR]"));
+        editor.move_cursor_to_idx(0);
+        assert_eq!(editor.run_script([Command::Char('>')]), ">axyz\ncd!");
+    }
+
+    #[test]
+    fn tint_rulers_past_the_line_end() {
+        let src = graphemes("ab\n日本語x\na日");
+        let mut editor = Editor::headless(&src);
+        editor.config.rulers = vec![5, 0, 200];
+        editor.redraw();
+
+        let ruler = editor.config.colors.ruler;
+        let bg = |x, y| editor.editor_screen.cell(x, y).map(|(style, _)| style.1);
+        assert_eq!(bg(4, 0), Some(ruler));
+        assert_eq!(bg(3, 0), Some(editor.config.colors.bg));
+        // The wide graphemes reach past the ruler, the text is left alone
+        assert_eq!(editor.editor_screen.cell(4, 1).unwrap().1, "語");
+        assert_ne!(bg(4, 1), Some(ruler));
+        assert_eq!(bg(3, 2), Some(editor.config.colors.bg));
+        assert_eq!(bg(4, 2), Some(ruler));
+        // Below the document too
+        assert_eq!(bg(4, 3), Some(ruler));
+    }
+
+    #[test]
+    fn new_lines_keep_the_indent() {
+        let src = graphemes("    f() {}");
+        let mut editor = Editor::headless(&src);
+        editor.move_cursor_to_idx(9);
+
+        let output = editor.run_script([Command::NewLine]);
+        assert_eq!(output, "    f() {\n    }");
+        assert_eq!(editor.cursor_line_column(), (1, 4));
+
+        // Only the indent before the cursor
+        editor.move_cursor_to_idx(2);
+        let output = editor.run_script([Command::NewLine]);
+        assert_eq!(output, "  \n    f() {\n    }");
+        assert_eq!(editor.cursor_line_column(), (1, 2));
+    }
+
+    #[test]
+    fn cursor_follows_edits_around_it() {
+        let src = graphemes("one\ntwo\nthree");
+        let mut editor = Editor::headless(&src);
+        editor.move_cursor_to_idx(5);
+
+        // Text inserted before the cursor pushes it, lines included
+        editor.insert_slice(0, &graphemes("zero\n"));
+        assert_eq!(editor.get_cursor_absolute_position(), 10);
+        assert_eq!(editor.cursor_line_column(), (2, 1));
+
+        // Edits after the cursor leave it alone
+        editor.remove_range(12..18);
+        assert_eq!(editor.get_cursor_absolute_position(), 10);
+
+        // A removal spanning the cursor brings it to its start
+        editor.remove_range(7..12);
+        assert_eq!(editor.get_cursor_absolute_position(), 7);
+        assert_eq!(editor.content(), "zero\non");
+        assert_eq!(editor.cursor_line_column(), (1, 2));
+
+        assert_eq!(editor.run_script([Command::Char('e')]), "zero\none");
+        assert_eq!(editor.get_cursor_absolute_position(), 8);
+        editor.apply(Command::DeleteBackWard);
+        assert_eq!(editor.get_cursor_absolute_position(), 7);
+        assert_eq!(editor.editor_screen.cursor(), (2, 1));
+    }
+
+    struct Broken;
+
+    impl Grammar for Broken {
+        fn name(&self) -> &'static str {
+            "broken"
+        }
+
+        fn language(&self) -> tree_sitter::Language {
+            tree_sitter_rust::language()
+        }
+
+        fn highlights_query(&self) -> &'static str {
+            "(identifier @variable"
+        }
+
+        fn extensions(&self) -> &'static [&'static str] {
+            &["broken"]
+        }
+    }
+
+    #[test]
+    fn edit_without_highlighting_when_queries_fail_to_load() {
+        let src = graphemes("fn main() {}");
+        let mut editor = Editor::headless(&src);
+        editor.load_highlighting(&Broken);
+
+        assert!(editor.hl_config.is_none());
+        assert!(!editor.highlighting);
+        let logs = editor.log_buffer.borrow().clone();
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].level, Level::Error);
+        assert!(logs[0].message.contains("broken queries failed to load"));
+
+        assert_eq!(editor.run_script([Command::Char('x')]), "xfn main() {}");
+        editor.load_highlighting(&Rust);
+        assert!(editor.hl_config.is_some());
+    }
+
+    #[tokio::test]
+    async fn stop_handling_events_once_commands_are_dropped() {
+        let key = Event::Key(KeyEvent::new(KeyCode::Char('a'), KeyModifiers::NONE));
+        let events = futures::stream::repeat_with(move || Ok(key.clone()));
+        let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
+        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        let handler = tokio::spawn(handle_events(events, tx, shutdown_tx.subscribe()));
+
+        assert!(matches!(rx.recv().await, Some(Command::Char('a'))));
+        drop(rx);
+        let exited = tokio::time::timeout(Duration::from_secs(5), handler).await;
+        assert!(matches!(exited, Ok(Ok(()))));
+    }
+
+    #[tokio::test]
+    async fn stop_handling_events_on_shutdown() {
+        let (shutdown_tx, _) = tokio::sync::broadcast::channel(1);
+        let (tx, _rx) = tokio::sync::mpsc::channel(1);
+        let events = futures::stream::pending();
+        let handler = tokio::spawn(handle_events(events, tx, shutdown_tx.subscribe()));
+
+        shutdown_tx.send(()).unwrap();
+        let exited = tokio::time::timeout(Duration::from_secs(5), handler).await;
+        assert!(matches!(exited, Ok(Ok(()))));
+    }
+
+    #[test]
+    fn cancel_one_thing_at_a_time() {
+        let src = graphemes("one two\nother");
+        let mut editor = Editor::headless(&src);
+        editor.selection = Some(Selection::new(0, 3));
+        editor.open_completion(vec!["one".to_string(), "other".to_string()]);
+
+        // The completion list goes first, then the selection
+        assert!(editor.apply(Command::Cancel));
+        assert!(editor.completion.is_none());
+        assert!(editor.selection.is_some());
+        assert!(editor.apply(Command::Cancel));
+        assert!(editor.selection.is_none());
+
+        // A pending find is dropped without touching the selection
+        editor.selection = Some(Selection::new(0, 3));
+        editor.apply(Command::Find {
+            forward: true,
+            till: false,
+        });
+        assert!(!editor.apply(Command::Cancel));
+        assert!(editor.pending_find.is_none());
+        assert!(editor.selection.is_some());
+        editor.run_script([Command::Cancel, Command::Char('o')]);
+        assert_eq!(editor.get_cursor_absolute_position(), 1);
+
+        // Nothing to back out of
+        assert!(!editor.apply(Command::Cancel));
+        assert_eq!(editor.content(), "oone two\nother");
+    }
+
+    #[test]
+    fn delete_whole_graphemes() {
+        let src = graphemes("a🦀b");
+        let mut editor = Editor::headless(&src);
+        editor.run_script([Command::MoveRight, Command::MoveRight]);
+        assert_eq!(editor.editor_screen.cursor(), (3, 0));
+
+        let output = editor.run_script([Command::DeleteBackWard]);
+        assert_eq!(output, "ab");
+        assert_eq!(editor.editor_screen.cursor(), (1, 0));
+
+        // A cluster split over several elements goes away as a whole
+        let src: Vec<String> = ["x", "e", "\u{301}", "\u{302}", "y"]
+            .map(String::from)
+            .to_vec();
+        let mut editor = Editor::headless(&src);
+        editor.move_cursor_to_idx(4);
+        assert_eq!(editor.run_script([Command::DeleteBackWard]), "xy");
+        assert_eq!(editor.cursor_line_column(), (0, 1));
+
+        let mut editor = Editor::headless(&src);
+        editor.move_cursor_to_idx(1);
+        assert_eq!(editor.run_script([Command::DeleteForward]), "xy");
+        assert_eq!(editor.cursor_line_column(), (0, 1));
+    }
+
+    #[test]
+    fn delete_lines_keeping_the_column() {
+        let src = graphemes("abc\nde\nfghi");
+        let mut editor = Editor::headless(&src);
+        editor.move_cursor_to(0, 2);
+
+        // The next line comes under the cursor, the column clamped to its length
+        assert_eq!(editor.run_script([Command::DeleteLine]), "de\nfghi");
+        assert_eq!(editor.cursor_line_column(), (0, 2));
+
+        editor.move_cursor_to(1, 3);
+        assert_eq!(editor.run_script([Command::DeleteLine]), "de");
+        assert_eq!(editor.cursor_line_column(), (0, 2));
+
+        assert_eq!(editor.run_script([Command::DeleteLine]), "");
+        assert_eq!(editor.cursor_line_column(), (0, 0));
+        assert!(!editor.apply(Command::DeleteLine));
+    }
+
+    #[test]
+    fn highlight_decision() {
+        assert!(should_highlight(0, 1024));
+        assert!(should_highlight(1024, 1024));
+        assert!(!should_highlight(1025, 1024));
+    }
+
+    #[test]
+    fn toggle_highlight() {
+        let src = graphemes("fn main() {}");
+        let mut editor = Editor::headless(&src);
+        editor.hl_config = Some(Rust.highlight_config().unwrap());
+        editor.update_highlights();
+        assert!(editor.highlight.get(0).is_some());
+
+        editor.run_script([Command::ToggleHighlight]);
+        assert!(editor.highlight.get(0).is_none());
+        assert_eq!(editor.editor_screen.cell(0, 0).unwrap().0 .0, Color::White);
+
+        editor.run_script([Command::ToggleHighlight]);
+        assert!(editor.highlight.get(0).is_some());
+    }
+
+    #[test]
+    fn shift_highlights_until_the_next_parse() {
+        let src = graphemes("let é = \"ü\";");
+        let mut editor = Editor::headless(&src);
+        editor.hl_config = Some(Rust.highlight_config().unwrap());
+        editor.update_highlights();
+
+        // Spans are in elements, the string being 8..11 with two bytes long graphemes
+        let string = editor.highlight.get(8);
+        assert!(string.is_some());
+        assert_eq!(editor.highlight.get(10), string);
+        assert_ne!(editor.highlight.get(11), string);
+        assert!(!editor.highlights_outdated());
+
+        // Drawing after an edit keeps the shifted spans rather than parsing again
+        editor.run_script([Command::Char('x'), Command::Char(' ')]);
+        assert!(editor.highlights_outdated());
+        assert_eq!(editor.highlight.get(10), string);
+        assert_ne!(editor.highlight.get(13), string);
+
+        editor.update_highlights();
+        assert!(!editor.highlights_outdated());
+        assert_eq!(editor.highlight.get(12), string);
+    }
+
+    #[test]
+    fn highlight_only_around_the_screen() {
+        let text = "fn f() {}\n".repeat(100);
+        let src = graphemes(&text);
+        let mut editor = Editor::headless(&src);
+        editor.hl_config = Some(Rust.highlight_config().unwrap());
+        editor.editor_screen.set_line_offset(50);
+        editor.update_highlights();
+
+        // The screen is 20 rows high, each line 10 elements long
+        let highlighted = |editor: &Editor, line: usize| editor.highlight.get(line * 10).is_some();
+        assert!(highlighted(&editor, 50));
+        assert!(highlighted(&editor, 50 - VISIBLE_MARGIN));
+        assert!(highlighted(&editor, 69 + VISIBLE_MARGIN));
+        assert!(!highlighted(&editor, 49 - VISIBLE_MARGIN));
+        assert!(!highlighted(&editor, 70 + VISIBLE_MARGIN));
+
+        editor.config.highlight_visible_only = false;
+        editor.update_highlights();
+        assert!(highlighted(&editor, 0));
+        assert!(highlighted(&editor, 99));
+    }
+
+    #[test]
+    fn tab_inserts_the_detected_indent() {
+        let src = graphemes("a {\n  b\n}");
+        let mut editor = Editor::headless(&src);
+        assert_eq!(editor.indent, IndentStyle::Spaces(2));
+
+        let output = editor.run_script([Command::MoveDown, Command::Tab, Command::Char('c')]);
+        assert_eq!(output, "a {\n  c  b\n}");
+
+        editor.indent = IndentStyle::Tabs;
+        let output = editor.run_script([Command::Tab]);
+        assert_eq!(output, "a {\n  c\t  b\n}");
+    }
+
+    #[test]
+    fn save_after_being_idle_long_enough() {
+        let secs = Duration::from_secs;
+        assert!(should_save_when_idle(true, secs(30), secs(30)));
+        assert!(should_save_when_idle(true, secs(45), secs(30)));
+        assert!(!should_save_when_idle(true, secs(29), secs(30)));
+        assert!(!should_save_when_idle(false, secs(45), secs(30)));
+        assert!(!should_save_when_idle(true, secs(45), Duration::ZERO));
+
+        let src = graphemes("ab");
+        let mut editor = Editor::headless(&src);
+        editor.config.idle_save_delay = secs(10);
+        editor.run_script([Command::Char('c')]);
+        // Nowhere to write it
+        assert!(!editor.idle_save_due(secs(20)));
+
+        editor.path = "src/main.rs".to_string();
+        assert!(editor.idle_save_due(secs(20)));
+        assert!(!editor.idle_save_due(secs(5)));
+        editor.read_only = true;
+        assert!(!editor.idle_save_due(secs(20)));
+    }
+
+    #[test]
+    fn idle_save_leaves_trailing_whitespace() {
+        let path = std::env::temp_dir().join(format!("pita-idle-save-{}.txt", std::process::id()));
+        let src = graphemes("a\n");
+        let mut editor = Editor::headless_at(path.display().to_string(), &src);
+        editor.config.trim_trailing_whitespace = true;
+        editor.config.ensure_final_newline = true;
+
+        editor.run_script([Command::MoveRight, Command::Char(' '), Command::NewLine]);
+        let find = Command::Find {
+            forward: true,
+            till: false,
+        };
+        editor.run_script([Command::MoveUp, find]);
+        editor.write_file(false);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a \n\n");
+
+        // The find started before the save still takes the next key
+        editor.run_script([Command::Char(' ')]);
+        assert_eq!(editor.get_cursor_absolute_position(), 1);
+
+        editor.run_script([Command::Save]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\n");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn save_keeps_both_panes_in_the_document() {
+        let path = std::env::temp_dir().join(format!("pita-save-both-{}.txt", std::process::id()));
+        let src = graphemes("a \n\n\n");
+        let mut editor = Editor::headless_at(path.display().to_string(), &src);
+        editor.config.trim_trailing_whitespace = true;
+        editor.config.ensure_final_newline = true;
+        editor.selection = Some(Selection::new(4, 5));
+        editor.move_cursor_to_idx(5);
+        let bg = ColorScheme::default().bg;
+        editor.split(Screen::headless(40, 20, 40, 0, bg));
+
+        editor.run_script([Command::Save]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "a\n");
+        assert_eq!(editor.selection, Some(Selection::new(2, 2)));
+        assert!(editor.switch_pane());
+        assert_eq!(editor.selection, Some(Selection::new(2, 2)));
+        assert_eq!(editor.get_cursor_absolute_position(), 2);
+        assert_eq!(editor.run_script([Command::Char('b')]), "a\nb");
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn autosave_only_changed_revisions() {
+        let src = graphemes("ab");
+        let mut editor = Editor::headless(&src);
+        editor.path = "src/main.rs".to_string();
+        assert!(editor.pending_autosave().is_none());
+
+        editor.run_script([Command::Char('c')]);
+        let swap = editor.pending_autosave().unwrap();
+        assert_eq!(swap.revision, 1);
+        assert_eq!(swap.content, "cab");
+
+        editor.autosaved_revision = swap.revision;
+        assert!(editor.pending_autosave().is_none());
+
+        editor.config.autosave = false;
+        editor.run_script([Command::Char('d')]);
+        assert!(editor.pending_autosave().is_none());
+    }
+
+    #[test]
+    fn type_and_delete_over_the_selection() {
+        let src = graphemes("hello world\nbye");
+        let mut editor = Editor::headless(&src);
+
+        editor.selection = Some(Selection::new(6, 1));
+        let output = editor.run_script([Command::Char('X')]);
+        assert_eq!(output, "hXworld\nbye");
+        assert!(editor.selection.is_none());
+        assert_eq!(editor.get_cursor_absolute_position(), 2);
+
+        // Across lines, the cursor ending on the selection start
+        editor.selection = Some(Selection::new(3, 9));
+        editor.move_cursor_to_idx(9);
+        let output = editor.run_script([Command::DeleteForward]);
+        assert_eq!(output, "hXwye");
+        assert_eq!(editor.get_cursor_absolute_position(), 3);
+
+        editor.selection = Some(Selection::new(1, 4));
+        let output = editor.run_script([Command::DeleteBackWard, Command::Char('!')]);
+        assert_eq!(output, "h!e");
+        assert!(editor.selection.is_none());
+        assert_eq!(editor.get_cursor_absolute_position(), 2);
+    }
+
+    #[test]
+    fn indent_and_dedent_selection() {
+        let src = graphemes("a\n  b\nc\nd");
+        let mut editor = Editor::headless(&src);
+        editor.indent = IndentStyle::Spaces(2);
+
+        // Select from the middle of the first line to the start of the third one
+        editor.selection = Some(Selection::new(1, 6));
+        let output = editor.run_script([Command::Tab]);
+        assert_eq!(output, "  a\n    b\nc\nd");
+        assert_eq!(editor.selection, Some(Selection::new(3, 10)));
+
+        let output = editor.run_script([Command::Dedent, Command::Dedent]);
+        assert_eq!(output, "a\nb\nc\nd");
+        assert_eq!(editor.selection, Some(Selection::new(1, 4)));
+
+        // Without a selection only the cursor line moves
+        editor.selection = None;
+        let output = editor.run_script([Command::MoveDown, Command::Indent]);
+        assert_eq!(output, "a\n  b\nc\nd");
+        assert_eq!(editor.cursor_line_column(), (1, 2));
+    }
+
+    #[test]
+    fn convert_the_indentation() {
+        let src = graphemes("fn a() {\n\tb(\n\t  c);\n}");
+        let mut editor = Editor::headless(&src);
+        editor.indent = IndentStyle::Tabs;
+        editor.move_cursor_to(2, 3);
+
+        let output = editor.run_script([Command::TabsToSpaces]);
+        assert_eq!(output, "fn a() {\n    b(\n      c);\n}");
+        assert_eq!(editor.cursor_line_column(), (2, 6));
+        assert_eq!(editor.indent, IndentStyle::Spaces(4));
+
+        // Only the selected lines with a selection
+        editor.selection = Some(Selection::new(9, 13));
+        let output = editor.run_script([Command::SpacesToTabs]);
+        assert_eq!(output, "fn a() {\n\tb(\n      c);\n}");
+        assert_eq!(editor.selection, Some(Selection::new(9, 10)));
+        assert_eq!(editor.cursor_line_column(), (2, 6));
+        assert_eq!(editor.indent, IndentStyle::Spaces(4));
+    }
+
+    #[test]
+    fn open_lines_around_the_cursor() {
+        let src = graphemes("fn a() {\n}");
+        let mut editor = Editor::headless(&src);
+        editor.indent = IndentStyle::Spaces(4);
+
+        let output = editor.run_script([
+            Command::MoveRight,
+            Command::NewLineBelow,
+            Command::Char('b'),
+            Command::NewLineAbove,
+            Command::Char('c'),
+        ]);
+        assert_eq!(output, "fn a() {\n    c\n    b\n}");
+        assert_eq!(editor.cursor_line_column(), (1, 5));
+    }
+
+    #[test]
+    fn complete_from_buffer_words() {
+        let src = graphemes("foo bar baz\nba");
+        let mut editor = Editor::headless(&src);
+
+        editor.run_script([
+            Command::MoveDown,
+            Command::MoveRight,
+            Command::MoveRight,
+            Command::Complete,
+        ]);
+        // Drawn over the document right under the cursor, the closest candidate first
+        let cell = editor.editor_screen.cell(3, 2).unwrap();
+        assert_eq!(
+            cell,
+            (
+                Style(Color::White, ColorScheme::default().selection),
+                "b".into()
+            )
+        );
+        assert_eq!(editor.editor_screen.cell(5, 3).unwrap().1, "r");
+
+        let output = editor.run_script([Command::MoveDown, Command::NewLine]);
+        assert_eq!(output, "foo bar baz\nbar");
+        assert!(editor.completion.is_none());
+        assert_eq!(editor.cursor_line_column(), (1, 3));
+
+        // Any other command closes the list
+        let output = editor.run_script([Command::Complete, Command::Char('x')]);
+        assert_eq!(output, "foo bar baz\nbarx");
+        assert!(editor.completion.is_none());
+    }
+
+    #[test]
+    fn align_the_cursor_line() {
+        let src = graphemes(&"line\n".repeat(60));
+        let mut editor = Editor::headless(&src);
+        editor.move_cursor_to(30, 2);
+
+        assert!(editor.apply(Command::CenterCursor));
+        assert_eq!(editor.editor_screen.line_offset(), 20);
+        assert_eq!(editor.editor_screen.cursor(), (2, 10));
+        assert!(!editor.apply(Command::CenterCursor));
+
+        editor.apply(Command::CursorToTop);
+        assert_eq!(editor.editor_screen.cursor(), (2, 0));
+        editor.apply(Command::CursorToBottom);
+        assert_eq!(editor.editor_screen.line_offset(), 11);
+        assert_eq!(editor.cursor_line_column(), (30, 2));
+    }
+
+    #[test]
+    fn extend_a_block_above_its_anchor() {
+        let src = graphemes("abc\nabc\nabc\nabc");
+        let mut editor = Editor::headless(&src);
+        editor.move_cursor_to(2, 2);
+
+        editor.run_script([Command::BlockUp, Command::BlockUp, Command::BlockLeft]);
+        let block = editor.block_selection.clone().unwrap();
+        assert_eq!((block.lines(), block.cols()), (0..3, 1..2));
+
+        // Back down past the anchor, then clamped to the last line
+        editor.run_script((0..5).map(|_| Command::BlockDown));
+        let block = editor.block_selection.clone().unwrap();
+        assert_eq!((block.lines(), block.cols()), (2..4, 1..2));
+
+        editor.run_script([Command::BlockUp, Command::BlockUp]);
+        let output = editor.run_script([Command::Char('|')]);
+        assert_eq!(output, "abc\na|bc\na|bc\nabc");
+    }
+
+    #[test]
+    fn go_to_the_document_edges() {
+        let src = graphemes(&"line\n".repeat(60));
+        let mut editor = Editor::headless(&src);
+        editor.move_cursor_to(30, 2);
+
+        // The last screenful, on the empty line after the final line ending
+        assert!(editor.apply(Command::GotoBottom));
+        assert_eq!(editor.editor_screen.line_offset(), 41);
+        assert_eq!(editor.cursor_line_column(), (60, 0));
+        assert_eq!(editor.editor_screen.cursor(), (0, 19));
+        assert!(!editor.apply(Command::GotoBottom));
+
+        assert!(editor.apply(Command::GotoTop));
+        assert_eq!(editor.editor_screen.line_offset(), 0);
+        assert_eq!(editor.get_cursor_absolute_position(), 0);
+
+        // Shorter than the screen, nothing scrolls
+        let src = graphemes("one\ntwo\nthree");
+        let mut editor = Editor::headless(&src);
+        assert!(!editor.apply(Command::GotoBottom));
+        assert_eq!(editor.editor_screen.line_offset(), 0);
+        assert_eq!(editor.cursor_line_column(), (2, 5));
+        assert_eq!(editor.editor_screen.cursor(), (5, 2));
+        assert!(!editor.apply(Command::GotoTop));
+        assert_eq!(editor.editor_screen.cursor(), (0, 0));
+    }
+
+    #[test]
+    fn jump_between_paragraphs() {
+        let src = graphemes(&"text\n\n\n".repeat(20));
+        let mut editor = Editor::headless(&src);
+        editor.move_cursor_to(0, 2);
+
+        // Consecutive blank lines are skipped, the view following the cursor
+        let lines: Vec<usize> = (0..8)
+            .map(|_| {
+                editor.apply(Command::ParagraphForward);
+                editor.cursor_line_column().0
+            })
+            .collect();
+        assert_eq!(lines, [1, 4, 7, 10, 13, 16, 19, 22]);
+        assert_eq!(editor.cursor_line_column(), (22, 0));
+        assert!(editor.editor_screen.line_offset() > 0);
+
+        // Starting from the blank lines at the end
+        editor.apply(Command::GotoBottom);
+        editor.apply(Command::ParagraphBackward);
+        assert_eq!(editor.cursor_line_column(), (56, 0));
+        editor.apply(Command::ParagraphBackward);
+        assert_eq!(editor.cursor_line_column(), (53, 0));
+
+        editor.apply(Command::GotoTop);
+        editor.apply(Command::ParagraphBackward);
+        assert_eq!(editor.cursor_line_column(), (0, 0));
+
+        // No blank line ahead, the cursor goes to the document end
+        let src = graphemes("one\n\ntwo\nthree");
+        let mut editor = Editor::headless(&src);
+        editor.apply(Command::ParagraphForward);
+        assert_eq!(editor.cursor_line_column(), (1, 0));
+        editor.apply(Command::ParagraphForward);
+        assert_eq!(editor.cursor_line_column(), (3, 5));
+        editor.apply(Command::ParagraphBackward);
+        assert_eq!(editor.cursor_line_column(), (1, 0));
+        editor.apply(Command::ParagraphBackward);
+        assert_eq!(editor.cursor_line_column(), (0, 0));
+    }
+
+    #[test]
+    fn scroll_without_moving_the_cursor_line() {
+        let src = graphemes(&"line\n".repeat(60));
+        let mut editor = Editor::headless(&src);
+        editor.move_cursor_to(1, 3);
+
+        assert!(editor.apply(Command::ScrollDown));
+        assert_eq!(editor.cursor_line_column(), (1, 3));
+        editor.apply(Command::ScrollDown);
+        assert_eq!(editor.editor_screen.line_offset(), 2);
+        assert_eq!(editor.cursor_line_column(), (2, 3));
+
+        assert!(editor.apply(Command::ScrollUp));
+        assert_eq!(editor.cursor_line_column(), (2, 3));
+        assert_eq!(editor.editor_screen.cursor(), (3, 1));
+    }
+
+    #[test]
+    fn reach_the_last_line_with_or_without_line_ending() {
+        for (src, last_line) in [("ab\ncd", (1, 2)), ("ab\ncd\n", (2, 0))] {
+            let src = graphemes(src);
+            let mut editor = Editor::headless(&src);
+            editor.redraw();
+            assert_eq!(editor.lines.line_count(&editor.doc), last_line.0 + 1);
+
+            editor.run_script([Command::MoveDown, Command::MoveRight, Command::MoveRight]);
+            assert_eq!(editor.cursor_line_column(), (1, 2));
+            editor.run_script([Command::MoveDown, Command::MoveRight]);
+            assert_eq!(editor.cursor_line_column(), last_line);
+
+            // The end of the document is reachable to type there
+            let mut output = editor.run_script([Command::Char('!')]);
+            output.retain(|c| c != '!');
+            assert_eq!(output, src.concat());
+            assert_eq!(editor.get_cursor_absolute_position(), src.len() + 1);
+        }
+    }
+
+    #[test]
+    fn startup_mode_from_args() {
+        let parse = |args: &[&str]| parse_args(args.iter().map(|arg| arg.to_string()));
+
+        assert_eq!(parse(&["pita"]), Startup::Usage);
+        assert_eq!(parse(&["pita", "--help"]), Startup::Usage);
+        assert_eq!(parse(&["pita", "--new"]), Startup::Unnamed);
+        assert_eq!(
+            parse(&["pita", "--script", "edit.txt", "a.rs"]),
+            Startup::Script {
+                script: "edit.txt".to_string(),
+                path: "a.rs".to_string()
+            }
+        );
+        assert_eq!(parse(&["pita", "--script", "edit.txt"]), Startup::Usage);
+        assert_eq!(
+            parse(&["pita", "src/main.rs"]),
+            Startup::File("src/main.rs".to_string())
+        );
+    }
+
+    #[test]
+    fn unnamed_buffer_is_not_written() {
+        let src = vec![];
+        let mut editor = Editor::headless(&src);
 
-fn hl_to_color(current_hl: Option<usize>) -> Color {
-    match current_hl {
-        Some(0) => Color::from((129, 200, 190)),  // Types
-        Some(10) => Color::from((239, 159, 118)), // Brackets
-        Some(14) => Color::from((234, 153, 156)), // Keywords
-        Some(11) => Color::from((231, 130, 132)), // Punctuation
-        Some(13) => Color::from((244, 184, 228)), // Lifetime
-        Some(4) => Color::from((229, 200, 144)),  // Imported types
-        Some(20) => Color::from((202, 158, 230)), // Ref + lifetime punct
-        _ => Color::White,
+        editor.run_script([Command::Char('a'), Command::Save]);
+        assert!(editor.status_line().starts_with("[No Name] "));
+        editor.draw_status();
+        let cell = |x| editor.status_screen.cell(x, 0).unwrap().1;
+        let position: String = (75..80).map(cell).collect();
+        assert_eq!(position, " 1:2 ");
+        assert!(editor.pending_autosave().is_none());
+        assert_eq!(
+            editor.log_buffer.borrow().last().unwrap().message,
+            "Failed to write [No Name]: no file name"
+        );
     }
 }
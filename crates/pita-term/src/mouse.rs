@@ -0,0 +1,132 @@
+use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+
+use crate::log::Level;
+use crate::selection::Selection;
+use crate::Editor;
+
+impl Editor<'_> {
+    /// Click to move the cursor and drag to select, the view scrolling by a line when the
+    /// drag reaches the top row or goes past the bottom one. Returns whether to redraw.
+    pub(crate) fn mouse(&mut self, event: MouseEvent) -> bool {
+        let (origin_x, origin_y) = self.editor_screen.origin();
+        let x = (event.column as usize).saturating_sub(origin_x);
+        let y = (event.row as usize).saturating_sub(origin_y);
+        let height = self.editor_screen.height();
+
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) if y < height => {
+                self.move_to_cell(x, y);
+                self.drag_anchor = Some(self.get_cursor_absolute_position());
+                self.selection.take().is_some()
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                let Some(anchor) = self.drag_anchor else {
+                    return false;
+                };
+
+                let offset = self.editor_screen.line_offset();
+                let last_line = self.lines.line_count(&self.doc) - 1;
+                let y = if y >= height {
+                    if offset + height <= last_line {
+                        self.editor_screen.set_line_offset(offset + 1);
+                    }
+                    height - 1
+                } else {
+                    if y == 0 && offset > 0 {
+                        self.editor_screen.set_line_offset(offset - 1);
+                    }
+                    y
+                };
+
+                self.move_to_cell(x, y);
+                let head = self.get_cursor_absolute_position();
+                self.selection = (head != anchor).then_some(Selection::new(anchor, head));
+                true
+            }
+            MouseEventKind::Up(MouseButton::Left) => {
+                self.drag_anchor = None;
+                false
+            }
+            // Reported on every move of the pointer, too many to log
+            MouseEventKind::Moved => false,
+            kind => {
+                self.log(Level::Debug, format!("mouse {kind:?}"));
+                false
+            }
+        }
+    }
+
+    /// Move to the cell `x`, `y`, rows below the last line landing on it.
+    fn move_to_cell(&self, x: usize, y: usize) {
+        let offset = self.editor_screen.line_offset();
+        let last_row = (self.lines.line_count(&self.doc) - 1).saturating_sub(offset);
+        self.move_to_row(x, y.min(last_row));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crossterm::event::{KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+    use piece_table::graphemes;
+
+    use crate::selection::Selection;
+    use crate::Editor;
+
+    fn mouse(kind: MouseEventKind, column: u16, row: u16) -> MouseEvent {
+        MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        }
+    }
+
+    #[test]
+    fn drag_to_select() {
+        let src = graphemes("abc\ndef\ng");
+        let mut editor = Editor::headless(&src);
+        let left = MouseButton::Left;
+
+        assert!(!editor.mouse(mouse(MouseEventKind::Down(left), 1, 0)));
+        assert_eq!(editor.selection, None);
+
+        editor.mouse(mouse(MouseEventKind::Drag(left), 2, 1));
+        assert_eq!(editor.selection, Some(Selection::new(1, 6)));
+
+        // Dragging back before the anchor selects backward, past the last line clamps
+        editor.mouse(mouse(MouseEventKind::Drag(left), 0, 0));
+        assert_eq!(editor.selection, Some(Selection::new(1, 0)));
+        editor.mouse(mouse(MouseEventKind::Drag(left), 5, 7));
+        assert_eq!(editor.selection, Some(Selection::new(1, 9)));
+
+        editor.mouse(mouse(MouseEventKind::Up(left), 5, 7));
+        assert!(!editor.mouse(mouse(MouseEventKind::Moved, 0, 0)));
+        assert!(editor.log_buffer.borrow().is_empty());
+        assert!(!editor.mouse(mouse(MouseEventKind::Drag(left), 0, 0)));
+        assert_eq!(editor.selection, Some(Selection::new(1, 9)));
+
+        // A new click drops the selection
+        assert!(editor.mouse(mouse(MouseEventKind::Down(left), 0, 1)));
+        assert_eq!(editor.selection, None);
+        assert_eq!(editor.get_cursor_absolute_position(), 4);
+    }
+
+    #[test]
+    fn drag_past_the_edges_scrolls() {
+        let text = "line\n".repeat(30);
+        let src = graphemes(&text);
+        let mut editor = Editor::headless(&src);
+        let left = MouseButton::Left;
+        let height = editor.editor_screen.height() as u16;
+
+        editor.mouse(mouse(MouseEventKind::Down(left), 0, 0));
+        editor.mouse(mouse(MouseEventKind::Drag(left), 0, height));
+        editor.mouse(mouse(MouseEventKind::Drag(left), 0, height + 3));
+        assert_eq!(editor.editor_screen.line_offset(), 2);
+        assert_eq!(editor.selection, Some(Selection::new(0, 21 * 5)));
+
+        editor.mouse(mouse(MouseEventKind::Drag(left), 0, 0));
+        assert_eq!(editor.editor_screen.line_offset(), 1);
+        assert_eq!(editor.selection, Some(Selection::new(0, 5)));
+    }
+}
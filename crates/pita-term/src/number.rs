@@ -0,0 +1,127 @@
+use std::ops::Range;
+
+use piece_table::PtBuffer;
+
+use crate::Editor;
+
+fn is_digit(g: &str) -> bool {
+    g.len() == 1 && g.as_bytes()[0].is_ascii_digit()
+}
+
+/// Span of the integer under `pos`, or of the next one on its line, a `-` right before
+/// the digits being part of it.
+pub fn number_at(doc: &PtBuffer<String>, pos: usize) -> Option<Range<usize>> {
+    let line_start = doc.line_start(pos);
+    let line_end = doc.line_end(pos);
+
+    let mut start = if doc.get(pos).is_some_and(|g| is_digit(g)) {
+        let before = doc.rev_range(doc.len() - pos..doc.len() - line_start);
+        pos - before.take_while(|g| is_digit(g)).count()
+    } else {
+        let from = pos.min(line_end);
+        from + doc.range(from..line_end).position(|g| is_digit(g))?
+    };
+    let digits = doc.range(start..line_end).take_while(|g| is_digit(g));
+    let end = start + digits.count();
+
+    if start > line_start && doc.get(start - 1).is_some_and(|g| g == "-") {
+        start -= 1;
+    }
+
+    Some(start..end)
+}
+
+/// `number` plus `delta`, keeping as many digits as `number` when it is zero padded.
+/// `None` when either side doesn't fit in an `i64`.
+pub fn add_to_number(number: &str, delta: i64) -> Option<String> {
+    let value = number.parse::<i64>().ok()?.checked_add(delta)?;
+    let digits = number.trim_start_matches('-');
+    let width = if digits.len() > 1 && digits.starts_with('0') {
+        digits.len()
+    } else {
+        0
+    };
+
+    let sign = if value < 0 { "-" } else { "" };
+    Some(format!("{sign}{:0width$}", value.unsigned_abs()))
+}
+
+impl Editor<'_> {
+    /// Add `delta` to the number under or after the cursor on its line, leaving the cursor
+    /// on its last digit. Returns whether there was one.
+    pub(crate) fn add_to_number(&mut self, delta: i64) -> bool {
+        let pos = self.get_cursor_absolute_position();
+        let Some(span) = number_at(&self.doc, pos) else {
+            return false;
+        };
+
+        let number: String = self.doc.range(span.clone()).map(String::as_str).collect();
+        let Some(result) = add_to_number(&number, delta) else {
+            return false;
+        };
+
+        let start = span.start;
+        let items: Vec<String> = result.chars().map(String::from).collect();
+        self.replace_range(span, &items);
+        self.move_cursor_to_idx(start + items.len() - 1);
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use piece_table::{graphemes, PtBuffer};
+
+    use crate::number::{add_to_number, number_at};
+    use crate::{Command, Editor};
+
+    #[test]
+    fn find_the_number_around_the_cursor() {
+        let src = graphemes("x = 120;\nmax-3 -x 9\nnone");
+        let doc = PtBuffer::new(&src);
+
+        // On any of its digits, or before it on the line
+        assert_eq!(number_at(&doc, 5), Some(4..7));
+        assert_eq!(number_at(&doc, 6), Some(4..7));
+        assert_eq!(number_at(&doc, 0), Some(4..7));
+        assert_eq!(number_at(&doc, 7), None);
+
+        // A minus sign right before the digits, not one further away
+        assert_eq!(number_at(&doc, 9), Some(12..14));
+        assert_eq!(number_at(&doc, 14), Some(18..19));
+        assert_eq!(number_at(&doc, 20), None);
+    }
+
+    #[test]
+    fn add_keeping_zero_padding() {
+        assert_eq!(add_to_number("120", 1).as_deref(), Some("121"));
+        assert_eq!(add_to_number("1", -3).as_deref(), Some("-2"));
+        assert_eq!(add_to_number("-1", 1).as_deref(), Some("0"));
+        assert_eq!(add_to_number("007", 1).as_deref(), Some("008"));
+        assert_eq!(add_to_number("-010", 15).as_deref(), Some("005"));
+        assert_eq!(add_to_number("099", 1).as_deref(), Some("100"));
+        assert_eq!(add_to_number("0", -1).as_deref(), Some("-1"));
+        assert_eq!(add_to_number("9223372036854775807", 1), None);
+        assert_eq!(add_to_number("99999999999999999999", 1), None);
+    }
+
+    #[test]
+    fn increase_and_decrease_the_number_after_the_cursor() {
+        let src = graphemes("width: 9px\nheight: -1");
+        let mut editor = Editor::headless(&src);
+
+        let output = editor.run_script([Command::IncreaseNumber]);
+        assert_eq!(output, "width: 10px\nheight: -1");
+        assert_eq!(editor.get_cursor_absolute_position(), 8);
+
+        editor.move_cursor_to_idx(12);
+        let output = editor.run_script([Command::IncreaseNumber, Command::IncreaseNumber]);
+        assert_eq!(output, "width: 10px\nheight: 1");
+        let output = editor.run_script([Command::DecreaseNumber]);
+        assert_eq!(output, "width: 10px\nheight: 0");
+
+        // Nothing left on the line after the cursor
+        editor.move_cursor_to_idx(9);
+        assert!(!editor.apply(Command::IncreaseNumber));
+    }
+}
@@ -0,0 +1,106 @@
+use piece_table::Edit;
+
+use crate::hl::HlQueue;
+use crate::screen::Screen;
+use crate::selection::{BlockSelection, Selection};
+use crate::Editor;
+
+/// The pane without the focus, showing the same document as the focused one on its own
+/// screen, with its own cursor and selection. They trade places with the editor's own
+/// when the focus moves.
+pub struct Pane {
+    editor_screen: Screen,
+    cursor: usize,
+    selection: Option<Selection>,
+    block_selection: Option<BlockSelection>,
+    highlight: HlQueue,
+    highlighted_at: Option<(u64, usize)>,
+}
+
+impl Pane {
+    /// Keep the cursor, the selection and the highlights on the same text across `edit`,
+    /// made from the focused pane.
+    pub fn follow_edit(&mut self, edit: &Edit) {
+        self.cursor = edit.adjust(self.cursor);
+        if let Some(selection) = &mut self.selection {
+            selection.anchor = edit.adjust(selection.anchor);
+            selection.head = edit.adjust(selection.head);
+        }
+        // Block corners are lines and columns, which an edit anywhere may invalidate
+        self.block_selection = None;
+
+        match *edit {
+            Edit::Insert { at, len } => self.highlight.shift(at, len as isize),
+            Edit::Remove { start, end } => self.highlight.shift(start, -((end - start) as isize)),
+        }
+    }
+}
+
+impl Editor<'_> {
+    /// Show the document in a second pane drawn on `editor_screen`, which gets the focus
+    /// with the same cursor and selection as the current pane.
+    pub(crate) fn split(&mut self, editor_screen: Screen) {
+        let cursor = self.get_cursor_absolute_position();
+        let offset = self.editor_screen.line_offset();
+        let editor_screen = std::mem::replace(&mut self.editor_screen, editor_screen);
+        self.other_pane = Some(Pane {
+            editor_screen,
+            cursor,
+            selection: self.selection,
+            block_selection: self.block_selection.clone(),
+            highlight: HlQueue::with_capacity(0),
+            highlighted_at: None,
+        });
+
+        self.editor_screen.set_line_offset(offset);
+        self.highlighted_at = None;
+        self.move_cursor_to_idx(cursor);
+    }
+
+    /// Move the focus to the other pane, returning whether there is one.
+    pub(crate) fn switch_pane(&mut self) -> bool {
+        let Some(mut pane) = self.other_pane.take() else {
+            return false;
+        };
+
+        self.swap_pane(&mut pane);
+        self.other_pane = Some(pane);
+        // Anything waiting on the next key belongs to the pane losing the focus
+        self.selection_history.clear();
+        self.drag_anchor = None;
+        self.pending_find = None;
+        self.pending_surround = None;
+        self.completion = None;
+        self.move_cursor_to_idx(self.get_cursor_absolute_position());
+        true
+    }
+
+    /// Run `f` with the pane without the focus swapped in, to draw it.
+    pub(crate) fn with_other_pane(&mut self, f: impl FnOnce(&mut Self)) {
+        let Some(mut pane) = self.other_pane.take() else {
+            return;
+        };
+
+        self.swap_pane(&mut pane);
+        f(self);
+        self.swap_pane(&mut pane);
+        self.other_pane = Some(pane);
+    }
+
+    /// Draw the pane without the focus on the terminal.
+    pub(crate) fn draw_other_pane(&mut self) {
+        self.with_other_pane(|other| {
+            other.redraw();
+            other.editor_screen.present();
+        });
+    }
+
+    fn swap_pane(&mut self, pane: &mut Pane) {
+        std::mem::swap(&mut self.editor_screen, &mut pane.editor_screen);
+        pane.cursor = self.cursor.replace(pane.cursor);
+        std::mem::swap(&mut self.selection, &mut pane.selection);
+        std::mem::swap(&mut self.block_selection, &mut pane.block_selection);
+        std::mem::swap(&mut self.highlight, &mut pane.highlight);
+        std::mem::swap(&mut self.highlighted_at, &mut pane.highlighted_at);
+    }
+}
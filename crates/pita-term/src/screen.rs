@@ -3,15 +3,15 @@ use crossterm::terminal::EnterAlternateScreen;
 use crossterm::{execute, queue, terminal};
 use std::cell::{Cell, RefCell};
 use std::io;
-use std::io::{BufWriter, Stdout, Write};
+use std::io::{BufWriter, Write};
+use std::ops::Range;
 use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthStr;
 
-pub const DEFAULT_BG: Color = Color::Rgb {
-    r: 59,
-    g: 56,
-    b: 73,
-};
+use crate::colors::ColorSupport;
+
+/// Tabs advance to the next multiple of this column.
+pub const TAB_WIDTH: usize = 4;
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub(crate) struct Style(pub Color, pub Color); // Fg, Bg
@@ -19,13 +19,18 @@ pub(crate) struct Style(pub Color, pub Color); // Fg, Bg
 pub struct Screen {
     width: usize,
     height: usize,
+    reserved_right: usize,
     offset_x: usize,
     offset_y: usize,
-    out: RefCell<BufWriter<Stdout>>,
+    out: RefCell<Box<dyn Write + Send>>,
+    /// Whether this screen switched the terminal to raw mode and must restore it
+    terminal: bool,
     buf: RefCell<Vec<Option<(Style, String)>>>,
     cursor: Cell<(u16, u16)>,
     line_offset: Cell<usize>,
-    bg: Color
+    bg: Color,
+    /// Colors the terminal can show, the others being brought down to them on `present`
+    color_support: ColorSupport,
 }
 
 impl Screen {
@@ -33,39 +38,71 @@ impl Screen {
         // TODO: produce error if size + offset < terminal size
         let mut out = BufWriter::new(io::stdout());
         execute!(out, EnterAlternateScreen)?;
-        // execute!(out, crossterm::event::EnableMouseCapture)?;
+        execute!(out, crossterm::event::EnableMouseCapture)?;
         queue!(out, crossterm::cursor::SetCursorStyle::SteadyBar)?;
         terminal::enable_raw_mode()?;
-        let buf = std::iter::repeat(Some((Style(Color::White, bg), " ".into())))
-            .take(width as usize * height as usize)
-            .collect();
 
-        Ok(Self {
+        let mut screen = Self::with_output(width, height, x, y, bg, Box::new(out), true);
+        screen.color_support = ColorSupport::detect();
+        Ok(screen)
+    }
+
+    /// A screen presented to the terminal set up by another one, leaving it as is when dropped.
+    pub fn on_stdout(width: usize, height: usize, x: usize, y: usize, bg: Color) -> Self {
+        let out = BufWriter::new(io::stdout());
+        let mut screen = Self::with_output(width, height, x, y, bg, Box::new(out), false);
+        screen.color_support = ColorSupport::detect();
+        screen
+    }
+
+    /// A screen drawing into its buffer only, without any terminal side effect, to be
+    /// composed into another one with [`Screen::compose`] or read back in tests.
+    pub fn headless(width: usize, height: usize, x: usize, y: usize, bg: Color) -> Self {
+        Self::with_output(width, height, x, y, bg, Box::new(io::sink()), false)
+    }
+
+    fn with_output(
+        width: usize,
+        height: usize,
+        x: usize,
+        y: usize,
+        bg: Color,
+        out: Box<dyn Write + Send>,
+        terminal: bool,
+    ) -> Self {
+        let buf = vec![Some((Style(Color::White, bg), " ".into())); width * height];
+
+        Self {
             width,
             height,
+            reserved_right: 0,
             offset_x: x,
             offset_y: y,
             out: RefCell::new(out),
+            terminal,
             buf: RefCell::new(buf),
             cursor: Cell::new((x as u16, y as u16)),
             line_offset: Cell::new(0),
             bg,
-        })
+            color_support: ColorSupport::TrueColor,
+        }
     }
 
+    #[cfg_attr(feature = "trace", tracing::instrument(skip_all))]
     pub(crate) fn present(&self) {
         let mut out = self.out.borrow_mut();
         let buf = self.buf.borrow();
 
-        let mut last_style = Style(Color::White, DEFAULT_BG);
+        let mut last_style = Style(Color::White, self.bg);
 
+        let fit = |color| self.color_support.fit(color);
         queue!(
             out,
-            crossterm::style::SetForegroundColor(last_style.0),
-            crossterm::style::SetBackgroundColor(last_style.1),
+            crossterm::style::SetForegroundColor(fit(last_style.0)),
+            crossterm::style::SetBackgroundColor(fit(last_style.1)),
             crossterm::cursor::Hide
         )
-            .unwrap();
+        .unwrap();
 
         // Write everything to the buffered output.
         for y in 0..self.height {
@@ -78,10 +115,10 @@ impl Screen {
                     if style != last_style {
                         queue!(
                             out,
-                            crossterm::style::SetForegroundColor(style.0),
-                            crossterm::style::SetBackgroundColor(style.1),
+                            crossterm::style::SetForegroundColor(fit(style.0)),
+                            crossterm::style::SetBackgroundColor(fit(style.1)),
                         )
-                            .unwrap();
+                        .unwrap();
                         last_style = style;
                     }
                     queue!(out, Print(text)).unwrap();
@@ -98,18 +135,30 @@ impl Screen {
         out.flush().unwrap();
     }
 
-    pub(crate) fn draw(&self, x: usize, y: usize, text: &str, style: Style) {
+    /// Draw `text` from the cell `x`, returning the column right after it.
+    pub(crate) fn draw(&self, x: usize, y: usize, text: &str, style: Style) -> usize {
+        let mut x = x;
         if y < self.height {
             let mut buf = self.buf.borrow_mut();
-            let mut x = x;
             for g in text.graphemes(true) {
-                if x < self.width {
+                if x < self.width() {
                     let width = UnicodeWidthStr::width(g);
-                    if width > 0 {
+                    if g == "\t" {
+                        for _ in 0..cell_width(g, x) {
+                            if x < self.width() {
+                                buf[y * self.width + x] = Some((style, " ".into()));
+                            }
+                            x += 1;
+                        }
+                    } else if x + width > self.width() {
+                        // A wide grapheme cut by the edge would spill past the screen
+                        buf[y * self.width + x] = Some((style, " ".into()));
+                        x += 1;
+                    } else if width > 0 {
                         buf[y * self.width + x] = Some((style, g.into()));
                         x += 1;
                         for _ in 1..width {
-                            if x < self.width {
+                            if x < self.width() {
                                 buf[y * self.width + x] = None;
                             }
                             x += 1;
@@ -128,6 +177,91 @@ impl Screen {
                 }
             }
         }
+
+        x
+    }
+
+    /// Draw `text` from the column `col`, leaving out whatever doesn't fit entirely
+    /// rather than cutting a wide grapheme at the edge. Returns the column right after it.
+    pub(crate) fn draw_at_col(&self, col: usize, y: usize, text: &str, style: Style) -> usize {
+        let room = self.width().saturating_sub(col);
+        self.draw(col, y, truncate_to_width(text, room), style)
+    }
+
+    /// Draw `text` flush to the right edge, from the first column when it is wider than
+    /// the screen. Returns the column it starts at.
+    pub(crate) fn draw_right(&self, y: usize, text: &str, style: Style) -> usize {
+        let col = self.width().saturating_sub(display_width(text));
+        self.draw_at_col(col, y, text, style);
+        col
+    }
+
+    /// Content and style of the cell at `x`, `y`, `None` for cells covered by a wide grapheme.
+    #[cfg(test)]
+    pub(crate) fn cell(&self, x: usize, y: usize) -> Option<(Style, String)> {
+        self.buf.borrow()[y * self.width + x].clone()
+    }
+
+    /// Copy the cells of `layer` over this screen, at the layer position relative to this one.
+    pub(crate) fn compose(&self, layer: &Screen) {
+        let mut buf = self.buf.borrow_mut();
+        let layer_buf = layer.buf.borrow();
+        for y in 0..layer.height {
+            for x in 0..layer.width {
+                let (Some(to_x), Some(to_y)) = (
+                    (layer.offset_x + x).checked_sub(self.offset_x),
+                    (layer.offset_y + y).checked_sub(self.offset_y),
+                ) else {
+                    continue;
+                };
+
+                if to_x < self.width && to_y < self.height {
+                    buf[to_y * self.width + to_x] = layer_buf[y * layer.width + x].clone();
+                }
+            }
+        }
+    }
+
+    /// Change the background of an already drawn cell, keeping its content.
+    pub(crate) fn set_bg(&self, x: usize, y: usize, bg: Color) {
+        if x < self.width && y < self.height {
+            if let Some((style, _)) = &mut self.buf.borrow_mut()[y * self.width + x] {
+                style.1 = bg;
+            }
+        }
+    }
+
+    /// Paint the reserved rightmost column as a scrollbar, `thumb` being the covered rows.
+    pub(crate) fn draw_scrollbar(&self, thumb: Range<usize>, thumb_color: Color) {
+        if self.reserved_right == 0 {
+            return;
+        }
+
+        let mut buf = self.buf.borrow_mut();
+        let x = self.width - 1;
+        for y in 0..self.height {
+            let bg = if thumb.contains(&y) {
+                thumb_color
+            } else {
+                self.bg
+            };
+
+            buf[y * self.width + x] = Some((Style(bg, bg), " ".into()));
+        }
+    }
+
+    /// Send an escape sequence straight to the terminal, bypassing the cell buffer.
+    pub(crate) fn write_escape(&self, sequence: &str) -> io::Result<()> {
+        let mut out = self.out.borrow_mut();
+        out.write_all(sequence.as_bytes())?;
+        out.flush()
+    }
+
+    /// Blank the whole terminal, every screen on it having to be presented again.
+    pub(crate) fn invalidate(&self) -> io::Result<()> {
+        let mut out = self.out.borrow_mut();
+        queue!(out, terminal::Clear(terminal::ClearType::All))?;
+        out.flush()
     }
 
     pub(crate) fn clear(&self, col: Color) {
@@ -147,7 +281,7 @@ impl Screen {
 
     pub fn set_cursor(&self, x: usize, y: usize) {
         self.cursor.set((
-            (x + self.offset_x).min((self.width + self.offset_x).saturating_sub(1)) as u16,
+            (x + self.offset_x).min((self.width() + self.offset_x).saturating_sub(1)) as u16,
             (y + self.offset_y).min((self.height + self.offset_y).saturating_sub(1)) as u16,
         ));
     }
@@ -156,12 +290,45 @@ impl Screen {
         self.height
     }
 
-    pub fn size(&self) -> usize {
-        self.height * self.width
+    /// Number of columns available for text, excluding reserved ones.
+    pub fn width(&self) -> usize {
+        self.width - self.reserved_right
     }
 
-    pub fn width(&self) -> usize {
-        self.width
+    /// Keep the `cols` rightmost columns out of reach of `draw`.
+    pub fn reserve_right(&mut self, cols: usize) {
+        self.reserved_right = cols.min(self.width);
+    }
+
+    /// Move the screen to the terminal columns `cols`, blanking its cells. The cursor keeps
+    /// its position relative to the screen, as far as the new width allows.
+    pub fn set_columns(&mut self, cols: Range<usize>) {
+        let cursor = self.cursor();
+        self.width = cols.len();
+        self.offset_x = cols.start;
+        self.reserved_right = self.reserved_right.min(self.width);
+        self.reset_cells(cursor);
+    }
+
+    /// Move the screen to the terminal rows `rows`, like [`Screen::set_columns`].
+    pub fn set_rows(&mut self, rows: Range<usize>) {
+        let cursor = self.cursor();
+        self.height = rows.len();
+        self.offset_y = rows.start;
+        self.reset_cells(cursor);
+    }
+
+    fn reset_cells(&mut self, (x, y): (usize, usize)) {
+        self.buf = RefCell::new(vec![
+            Some((Style(Color::White, self.bg), " ".into()));
+            self.width * self.height
+        ]);
+        self.set_cursor(x, y);
+    }
+
+    /// Terminal cell of the top left corner of the screen.
+    pub fn origin(&self) -> (usize, usize) {
+        (self.offset_x, self.offset_y)
     }
 
     pub fn cursor(&self) -> (usize, usize) {
@@ -173,29 +340,282 @@ impl Screen {
         self.line_offset.get()
     }
 
-    pub fn inc_offset(&self) {
-        let offset = self.line_offset.get();
-        self.line_offset.set(offset + 1);
+    pub fn set_line_offset(&self, offset: usize) {
+        self.line_offset.set(offset);
+    }
+}
+
+/// Number of cells `draw` uses for a grapheme drawn at column `x`, tabs advancing to the
+/// next tab stop and zero-width graphemes being given a leading space.
+pub fn cell_width(g: &str, x: usize) -> usize {
+    if g == "\t" {
+        TAB_WIDTH - x % TAB_WIDTH
+    } else {
+        UnicodeWidthStr::width(g).max(1)
+    }
+}
+
+/// Number of cells `text` takes when drawn from the first column.
+pub fn display_width(text: &str) -> usize {
+    text.graphemes(true)
+        .fold(0, |columns, g| columns + cell_width(g, columns))
+}
+
+/// Longest start of `text` fitting in `width` columns without cutting a grapheme.
+pub fn truncate_to_width(text: &str, width: usize) -> &str {
+    let mut columns = 0;
+    for (offset, g) in text.grapheme_indices(true) {
+        columns += cell_width(g, columns);
+        if columns > width {
+            return &text[..offset];
+        }
     }
 
-    pub fn dec_offset(&self) {
-        let offset = self.line_offset.get();
-        self.line_offset.set(offset.saturating_sub(1));
+    text
+}
+
+/// Indexes of the graphemes of `line` starting each row after the first when wrapping it
+/// at `width` columns. Rows break before a grapheme whose cells would go past `width`, a
+/// grapheme wider than the whole row getting a row of its own rather than being split.
+pub fn wrap_points<S: AsRef<str>>(line: &[S], width: usize) -> Vec<usize> {
+    let mut points = vec![];
+    let mut column = 0;
+    for (idx, g) in line.iter().enumerate() {
+        let mut cells = cell_width(g.as_ref(), column);
+        if column > 0 && column + cells > width {
+            points.push(idx);
+            column = 0;
+            cells = cell_width(g.as_ref(), column);
+        }
+        column += cells;
     }
+
+    points
+}
+
+/// Columns of the left and right screens splitting the `width` columns from `x`, the column
+/// between them being left for a divider.
+pub fn vertical_split(x: usize, width: usize) -> (Range<usize>, Range<usize>) {
+    let left = width.saturating_sub(1) / 2;
+    (x..x + left, x + left + 1..x + width)
+}
+
+/// Rows of a `height` tall scrollbar covered by the thumb while lines `offset..offset + height`
+/// of a `total` lines document are visible.
+pub fn scrollbar_thumb(offset: usize, height: usize, total: usize) -> Range<usize> {
+    if total <= height {
+        return 0..height;
+    }
+
+    let len = (height * height).div_ceil(total).clamp(1, height);
+    let max_start = height - len;
+    let start = if offset + height >= total {
+        max_start
+    } else {
+        (offset * height / total).min(max_start)
+    };
+
+    start..start + len
 }
 
 impl Drop for Screen {
     fn drop(&mut self) {
+        if !self.terminal {
+            return;
+        }
+
         terminal::disable_raw_mode().unwrap();
         let mut out = self.out.borrow_mut();
         execute!(
             out,
             terminal::Clear(terminal::ClearType::All),
+            crossterm::event::DisableMouseCapture,
             crossterm::style::ResetColor,
             terminal::LeaveAlternateScreen,
             crossterm::cursor::Show,
         )
-            .unwrap();
+        .unwrap();
         out.flush().unwrap();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::io;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    use crossterm::style::Color;
+    use unicode_segmentation::UnicodeSegmentation;
+
+    use crate::colors::ColorSupport;
+    use crate::screen::{
+        display_width, scrollbar_thumb, truncate_to_width, vertical_split, wrap_points, Screen,
+        Style,
+    };
+
+    #[test]
+    fn thumb_fills_the_track_for_short_documents() {
+        assert_eq!(scrollbar_thumb(0, 10, 4), 0..10);
+        assert_eq!(scrollbar_thumb(0, 10, 10), 0..10);
+    }
+
+    #[test]
+    fn thumb_is_proportional_to_the_viewport() {
+        assert_eq!(scrollbar_thumb(0, 10, 100), 0..1);
+        assert_eq!(scrollbar_thumb(0, 10, 40), 0..3);
+        assert_eq!(scrollbar_thumb(20, 10, 40), 5..8);
+    }
+
+    #[test]
+    fn thumb_reaches_the_bottom_at_document_end() {
+        assert_eq!(scrollbar_thumb(30, 10, 40), 7..10);
+        assert_eq!(scrollbar_thumb(95, 10, 100), 9..10);
+    }
+
+    #[test]
+    fn truncate_by_display_width() {
+        let line = "é – 日本 ok";
+        assert_eq!(truncate_to_width(line, 0), "");
+        assert_eq!(truncate_to_width(line, 1), "é");
+        assert_eq!(truncate_to_width(line, 4), "é – ");
+        // The second column of a wide grapheme is out of reach
+        assert_eq!(truncate_to_width(line, 5), "é – ");
+        assert_eq!(truncate_to_width(line, 6), "é – 日");
+        assert_eq!(truncate_to_width(line, 11), line);
+        assert_eq!(truncate_to_width(line, 80), line);
+        assert_eq!(display_width(line), 11);
+    }
+
+    #[test]
+    fn draw_flush_to_the_right_edge() {
+        let screen = Screen::headless(8, 3, 0, 0, Color::Black);
+        let style = Style(Color::White, Color::Black);
+        let row = |y| -> String {
+            (0..8)
+                .filter_map(|x| screen.cell(x, y))
+                .map(|c| c.1)
+                .collect()
+        };
+
+        assert_eq!(screen.draw_right(0, "1:42", style), 4);
+        assert_eq!(row(0), "    1:42");
+        // Wide graphemes take their two cells up to the edge
+        assert_eq!(screen.draw_right(1, "日本", style), 4);
+        assert_eq!(row(1), "    日本");
+        assert_eq!(screen.cell(7, 1), None);
+        // Too wide, the start is kept and the wide grapheme past the edge left out
+        assert_eq!(screen.draw_right(2, "abcdef日本", style), 0);
+        assert_eq!(row(2), "abcdef日");
+
+        assert_eq!(screen.draw_at_col(6, 0, "日", style), 8);
+        assert_eq!(screen.draw_at_col(7, 1, "日", style), 7);
+    }
+
+    #[test]
+    fn wrap_by_display_width() {
+        let wrap = |line: &str, width| {
+            let graphemes: Vec<&str> = line.graphemes(true).collect();
+            wrap_points(&graphemes, width)
+        };
+
+        assert_eq!(wrap("abcdefg", 3), [3, 6]);
+        assert_eq!(wrap("abc", 3), []);
+        assert_eq!(wrap("", 3), []);
+        // A wide grapheme not fitting in what is left of the row goes to the next one
+        assert_eq!(wrap("abc日本", 4), [3]);
+        assert_eq!(wrap("a日b", 2), [1, 2]);
+        // Combining marks stay with their base
+        assert_eq!(wrap("e\u{301}e\u{301}e\u{301}", 2), [2]);
+        // Tabs take the cells up to the next stop of their row
+        assert_eq!(wrap("ab\tc", 4), [3]);
+        assert_eq!(wrap("abcde\tf", 4), [4, 6]);
+        // Too wide for any row, alone on its own
+        assert_eq!(wrap("a日b", 1), [1, 2]);
+    }
+
+    #[test]
+    fn wide_grapheme_does_not_cross_the_edge() {
+        let screen = Screen::headless(3, 1, 0, 0, Color::Black);
+        let style = Style(Color::White, Color::Black);
+
+        assert_eq!(screen.draw(0, 0, "a日本", style), 3);
+        assert_eq!(screen.cell(1, 0).unwrap().1, "日");
+        assert_eq!(screen.cell(2, 0), None);
+
+        screen.draw(0, 0, "ab日", style);
+        assert_eq!(screen.cell(2, 0).unwrap().1, " ");
+    }
+
+    #[test]
+    fn split_screens_side_by_side() {
+        let (left, right) = vertical_split(0, 11);
+        assert_eq!((left.clone(), right.clone()), (0..5, 6..11));
+
+        let terminal = Screen::headless(11, 2, 0, 0, Color::Black);
+        let mut left_screen = Screen::headless(11, 2, 0, 0, Color::Black);
+        left_screen.set_columns(left);
+        let right_screen = Screen::headless(right.len(), 2, right.start, 0, Color::Black);
+        let style = Style(Color::White, Color::Black);
+        left_screen.draw(0, 0, "left side", style);
+        right_screen.draw(0, 1, "right", style);
+
+        terminal.compose(&left_screen);
+        terminal.compose(&right_screen);
+
+        let row = |y| -> String { (0..11).map(|x| terminal.cell(x, y).unwrap().1).collect() };
+        // Each side is clipped to its own columns, the divider column staying blank
+        assert_eq!(row(0), "left       ");
+        assert_eq!(row(1), "      right");
+        right_screen.set_cursor(2, 1);
+        assert_eq!(right_screen.cursor(), (2, 1));
+    }
+
+    /// Output shared with the test once the screen owns it.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn present_everything_again_after_invalidating() {
+        let out = Captured::default();
+        let screen = Screen::with_output(4, 1, 0, 0, Color::Black, Box::new(out.clone()), false);
+        screen.draw(0, 0, "αβ", Style(Color::White, Color::Black));
+        let clear = "\x1b[2J";
+        let take = || String::from_utf8(std::mem::take(&mut *out.0.lock().unwrap())).unwrap();
+
+        screen.present();
+        let first = take();
+        assert!(first.contains('α') && first.contains('β') && !first.contains(clear));
+
+        // Nothing changed, the cells still follow the cleared terminal
+        screen.invalidate().unwrap();
+        screen.present();
+        let redrawn = take();
+        let cleared_at = redrawn.find(clear).unwrap();
+        assert!(redrawn[cleared_at..].contains('α') && redrawn[cleared_at..].contains('β'));
+    }
+
+    #[test]
+    fn present_rgb_colors_the_terminal_can_show() {
+        let out = Captured::default();
+        let mut screen =
+            Screen::with_output(2, 1, 0, 0, Color::Black, Box::new(out.clone()), false);
+        screen.color_support = ColorSupport::Ansi256;
+        screen.draw(0, 0, "x", Style(Color::from((255, 0, 0)), Color::Black));
+        screen.present();
+
+        let output = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("\x1b[38;5;196m"));
+        assert!(!output.contains("38;2;"));
+    }
+}
@@ -0,0 +1,156 @@
+use crate::Command;
+
+/// Commands of an editing script, one per line, as run by `pita --script`.
+///
+/// A line holds a command name followed by its flags, like `SortLines reverse`. `Type`
+/// types the rest of the line and `Char` a single character. Blank lines and lines
+/// starting with `#` are skipped.
+pub fn parse_script(script: &str) -> Result<Vec<Command>, String> {
+    let mut commands = vec![];
+    for (idx, line) in script.lines().enumerate() {
+        let line = line.trim_start().trim_end_matches('\r');
+        if line.trim_end().is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (name, args) = line.split_once(' ').unwrap_or((line.trim_end(), ""));
+        if name == "Type" {
+            commands.extend(args.chars().map(Command::Char));
+            continue;
+        }
+
+        let command =
+            parse_command(name, args).map_err(|err| format!("line {}: {err}", idx + 1))?;
+        commands.push(command);
+    }
+
+    Ok(commands)
+}
+
+fn parse_command(name: &str, args: &str) -> Result<Command, String> {
+    if name == "Char" {
+        let mut chars = args.chars();
+        return match (chars.next(), chars.next()) {
+            (Some(c), None) => Ok(Command::Char(c)),
+            _ => Err(format!("`Char` takes a single character, got `{args}`")),
+        };
+    }
+
+    let flags: Vec<&str> = args.split_whitespace().collect();
+    if let Some(unknown) = flags.iter().find(|flag| !KNOWN_FLAGS.contains(flag)) {
+        return Err(format!("unknown flag `{unknown}` for `{name}`"));
+    }
+    let flag = |name: &str| flags.contains(&name);
+
+    let command = match name {
+        "SortLines" => Command::SortLines {
+            reverse: flag("reverse"),
+            ignore_case: flag("ignore_case"),
+        },
+        "Find" => Command::Find {
+            forward: !flag("backward"),
+            till: flag("till"),
+        },
+        "RepeatFind" => Command::RepeatFind {
+            reverse: flag("reverse"),
+        },
+        "Quit" => Command::Quit,
+        "Cancel" => Command::Cancel,
+        "MoveLeft" => Command::MoveLeft,
+        "WordLeft" => Command::WordLeft,
+        "WordRight" => Command::WordRight,
+        "MoveRight" => Command::MoveRight,
+        "MoveDown" => Command::MoveDown,
+        "MoveUp" => Command::MoveUp,
+        "NewLine" => Command::NewLine,
+        "NewLineBelow" => Command::NewLineBelow,
+        "NewLineAbove" => Command::NewLineAbove,
+        "DeleteForward" => Command::DeleteForward,
+        "DeleteBackWard" => Command::DeleteBackWard,
+        "DeleteLine" => Command::DeleteLine,
+        "IncreaseNumber" => Command::IncreaseNumber,
+        "DecreaseNumber" => Command::DecreaseNumber,
+        "Tab" => Command::Tab,
+        "Indent" => Command::Indent,
+        "Dedent" => Command::Dedent,
+        "TabsToSpaces" => Command::TabsToSpaces,
+        "SpacesToTabs" => Command::SpacesToTabs,
+        "Save" => Command::Save,
+        "DuplicateLine" => Command::DuplicateLine,
+        "Yank" => Command::Yank,
+        "Cut" => Command::Cut,
+        "Paste" => Command::Paste,
+        "SelectAll" => Command::SelectAll,
+        "ExpandSelection" => Command::ExpandSelection,
+        "ShrinkSelection" => Command::ShrinkSelection,
+        "ToggleOvertype" => Command::ToggleOvertype,
+        "ToUpper" => Command::ToUpper,
+        "ToLower" => Command::ToLower,
+        "SwapCase" => Command::SwapCase,
+        "RepeatEdit" => Command::RepeatEdit,
+        "Surround" => Command::Surround,
+        "Unsurround" => Command::Unsurround,
+        "GotoTop" => Command::GotoTop,
+        "GotoBottom" => Command::GotoBottom,
+        "ParagraphForward" => Command::ParagraphForward,
+        "ParagraphBackward" => Command::ParagraphBackward,
+        "Complete" => Command::Complete,
+        "CompletionNext" => Command::CompletionNext,
+        "CompletionPrev" => Command::CompletionPrev,
+        "AcceptCompletion" => Command::AcceptCompletion,
+        "BlockUp" => Command::BlockUp,
+        "BlockDown" => Command::BlockDown,
+        "BlockLeft" => Command::BlockLeft,
+        "BlockRight" => Command::BlockRight,
+        _ => return Err(format!("unknown command `{name}`")),
+    };
+
+    Ok(command)
+}
+
+const KNOWN_FLAGS: &[&str] = &["reverse", "ignore_case", "backward", "till"];
+
+#[cfg(test)]
+mod test {
+    use crate::script::parse_script;
+    use crate::Editor;
+
+    #[test]
+    fn parse_commands_and_typed_text() {
+        let script = "# Rename and move down\nType fn a()\n\nNewLine\nSortLines reverse\nChar  \n";
+        let commands = parse_script(script).unwrap();
+        let debug: Vec<String> = commands.iter().map(|c| format!("{c:?}")).collect();
+        assert_eq!(
+            debug,
+            [
+                "Char('f')",
+                "Char('n')",
+                "Char(' ')",
+                "Char('a')",
+                "Char('(')",
+                "Char(')')",
+                "NewLine",
+                "SortLines { reverse: true, ignore_case: false }",
+                "Char(' ')",
+            ]
+        );
+
+        assert_eq!(
+            parse_script("MoveUp\nJump").unwrap_err(),
+            "line 2: unknown command `Jump`"
+        );
+        assert_eq!(
+            parse_script("Find sideways").unwrap_err(),
+            "line 1: unknown flag `sideways` for `Find`"
+        );
+        assert!(parse_script("Char ab").is_err());
+    }
+
+    #[test]
+    fn run_a_parsed_script() {
+        let src = vec![];
+        let mut editor = Editor::headless(&src);
+        let commands = parse_script("Type b\nNewLine\nType a\nSelectAll\nSortLines\n").unwrap();
+        assert_eq!(editor.run_script(commands), "a\nb");
+    }
+}
@@ -0,0 +1,209 @@
+use std::ops::Range;
+
+use piece_table::PtBuffer;
+
+use crate::language::LanguageConfig;
+
+/// A contiguous range of the document, `head` being where the cursor is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selection {
+    pub anchor: usize,
+    pub head: usize,
+}
+
+impl Selection {
+    pub fn new(anchor: usize, head: usize) -> Self {
+        Self { anchor, head }
+    }
+
+    /// Selected indexes, whichever direction the selection was made in.
+    pub fn range(&self) -> Range<usize> {
+        self.anchor.min(self.head)..self.anchor.max(self.head)
+    }
+}
+
+/// Smallest region strictly enclosing `range`: the inside of a bracket pair, the pair
+/// itself, the lines it spans and finally the whole document.
+pub fn expand_range(
+    doc: &PtBuffer<String>,
+    range: Range<usize>,
+    language: &LanguageConfig,
+) -> Option<Range<usize>> {
+    let mut openers: Vec<(usize, &'static str)> = vec![];
+    let mut candidates = vec![];
+    let mut line_start = 0;
+    let mut line_end = None;
+
+    for (idx, g) in doc.iter().enumerate() {
+        match g.as_str() {
+            "\n" if idx < range.start => line_start = idx + 1,
+            "\n" if idx >= range.end && line_end.is_none() => line_end = Some(idx),
+            g => {
+                // Stray closing brackets are ignored
+                if openers.last().is_some_and(|&(_, close)| close == g) {
+                    let (open, _) = openers.pop().expect("checked above");
+                    candidates.push(open + 1..idx);
+                    candidates.push(open..idx + 1);
+                } else if let Some(close) = language.closing(g) {
+                    openers.push((idx, close));
+                }
+            }
+        }
+    }
+    candidates.push(line_start..line_end.unwrap_or(doc.len()));
+    candidates.push(0..doc.len());
+
+    candidates
+        .into_iter()
+        .filter(|c| c.start <= range.start && c.end >= range.end && *c != range)
+        .min_by_key(|c| c.len())
+}
+
+/// A rectangular region of the document between two `(line, column)` corners. Like
+/// [`Selection`], the head moves while the anchor stays where the block was started.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlockSelection {
+    pub anchor: (usize, usize),
+    pub head: (usize, usize),
+}
+
+impl BlockSelection {
+    pub fn new(anchor: (usize, usize), head: (usize, usize)) -> Self {
+        Self { anchor, head }
+    }
+
+    /// An empty-width block on a single line, ready to be extended.
+    pub fn at(line: usize, column: usize) -> Self {
+        Self::new((line, column), (line, column))
+    }
+
+    /// Lines of the block, both corner lines included.
+    pub fn lines(&self) -> Range<usize> {
+        self.anchor.0.min(self.head.0)..self.anchor.0.max(self.head.0) + 1
+    }
+
+    /// Columns of the block, whichever direction it was made in.
+    pub fn cols(&self) -> Range<usize> {
+        self.anchor.1.min(self.head.1)..self.anchor.1.max(self.head.1)
+    }
+
+    pub fn contains(&self, line: usize, column: usize) -> bool {
+        self.lines().contains(&line) && self.cols().contains(&column)
+    }
+
+    /// Move both corners `delta` columns right, following text inserted in the block.
+    pub fn shift_cols(&mut self, delta: usize) {
+        self.anchor.1 += delta;
+        self.head.1 += delta;
+    }
+
+    /// Absolute index where a block insert lands on each line of the block, along with the
+    /// padding needed to reach the block column. Lines shorter than the block column are
+    /// skipped unless `pad` is set.
+    pub fn insert_positions(&self, doc: &PtBuffer<String>, pad: bool) -> Vec<(usize, usize)> {
+        let column = self.cols().start;
+        self.lines()
+            .map_while(|line| doc.line_range(line))
+            .filter_map(|range| {
+                let len = range.len();
+                if len >= column {
+                    Some((range.start + column, 0))
+                } else if pad {
+                    Some((range.end, column - len))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Insert `text` at the block column on every line of the block, returning the
+    /// edited positions and their padding in the order they were applied.
+    pub fn insert(
+        &self,
+        doc: &mut PtBuffer<String>,
+        text: &[String],
+        pad: bool,
+    ) -> Vec<(usize, usize)> {
+        let mut positions = self.insert_positions(doc, pad);
+        // Edit from the bottom up so the positions computed above stay valid
+        positions.reverse();
+        for &(idx, padding) in &positions {
+            doc.insert_slice(idx, text);
+            doc.insert_slice(idx, &vec![" ".to_string(); padding]);
+        }
+
+        positions
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use piece_table::{graphemes, PtBuffer};
+    use unicode_segmentation::UnicodeSegmentation;
+
+    use crate::language::LanguageConfig;
+    use crate::selection::{expand_range, BlockSelection};
+
+    #[test]
+    fn block_insert_positions() {
+        let src: Vec<String> = "let a;\nb\n\nlet c;"
+            .graphemes(true)
+            .map(str::to_string)
+            .collect();
+        let doc = PtBuffer::new(&src);
+        let block = BlockSelection::new((0, 4), (3, 5));
+
+        assert_eq!(block.insert_positions(&doc, false), vec![(4, 0), (14, 0)]);
+        assert_eq!(
+            block.insert_positions(&doc, true),
+            vec![(4, 0), (8, 3), (9, 4), (14, 0)]
+        );
+    }
+
+    #[test]
+    fn block_insert_pads_short_lines() {
+        let src = graphemes("abc\na\nabc");
+        let mut doc = PtBuffer::new(&src);
+        // Started from the bottom right corner
+        let block = BlockSelection::new((2, 2), (0, 2));
+
+        block.insert(&mut doc, &["|".to_string()], true);
+
+        let content: Vec<&str> = doc.iter().map(String::as_str).collect();
+        assert_eq!(content.join(""), "ab|c\na |\nab|c");
+    }
+
+    #[test]
+    fn expand_to_enclosing_brackets_then_line() {
+        let src: Vec<String> = "f(a, [b, c])\nnext"
+            .graphemes(true)
+            .map(str::to_string)
+            .collect();
+        let doc = PtBuffer::new(&src);
+
+        let mut range = 6..6;
+        let mut steps = vec![];
+        while let Some(next) = expand_range(&doc, range, &LanguageConfig::plain_text()) {
+            steps.push(next.clone());
+            range = next;
+        }
+
+        assert_eq!(steps, vec![6..10, 5..11, 2..11, 1..12, 0..12, 0..17]);
+    }
+
+    #[test]
+    fn expand_ignores_unbalanced_brackets() {
+        let src = graphemes("a) (b");
+        let doc = PtBuffer::new(&src);
+
+        assert_eq!(
+            expand_range(&doc, 4..4, &LanguageConfig::plain_text()),
+            Some(0..5)
+        );
+        assert_eq!(
+            expand_range(&doc, 0..5, &LanguageConfig::plain_text()),
+            None
+        );
+    }
+}
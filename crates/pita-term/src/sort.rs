@@ -0,0 +1,110 @@
+use crate::Editor;
+
+/// Lines of `text` reordered, the line endings between them staying where they were.
+/// Equal lines keep their relative order.
+pub fn sort_lines(text: &[String], reverse: bool, ignore_case: bool) -> Vec<String> {
+    let mut lines: Vec<(String, &[String])> = text
+        .split(|g| g == "\n")
+        .map(|line| {
+            let key = line.concat();
+            let key = if ignore_case { key.to_lowercase() } else { key };
+            (key, line)
+        })
+        .collect();
+
+    lines.sort_by(|(a, _), (b, _)| {
+        let order = a.cmp(b);
+        if reverse {
+            order.reverse()
+        } else {
+            order
+        }
+    });
+
+    let mut sorted = Vec::with_capacity(text.len());
+    for (idx, (_, line)) in lines.into_iter().enumerate() {
+        if idx > 0 {
+            sorted.push("\n".to_string());
+        }
+        sorted.extend_from_slice(line);
+    }
+
+    sorted
+}
+
+impl Editor<'_> {
+    /// Sort the selected lines, or do nothing without a selection.
+    pub(crate) fn sort_selected_lines(&mut self, reverse: bool, ignore_case: bool) -> bool {
+        if self.selection.is_none() {
+            return false;
+        }
+
+        let lines = self.selected_lines();
+        let start = self
+            .lines
+            .line_range(&self.doc, lines.start)
+            .expect("line is within bounds");
+        let end = self
+            .lines
+            .line_range(&self.doc, lines.end - 1)
+            .expect("line is within bounds");
+        let range = start.start..end.end;
+
+        let text: Vec<String> = self.doc.range(range.clone()).cloned().collect();
+        let sorted = sort_lines(&text, reverse, ignore_case);
+        if sorted == text {
+            return false;
+        }
+
+        let cursor = self.get_cursor_absolute_position();
+        self.replace_range(range, &sorted);
+        self.move_cursor_to_idx(cursor);
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use piece_table::graphemes;
+
+    use crate::selection::Selection;
+    use crate::sort::sort_lines;
+    use crate::Editor;
+
+    fn sort(s: &str, reverse: bool, ignore_case: bool) -> String {
+        sort_lines(&graphemes(s), reverse, ignore_case).concat()
+    }
+
+    #[test]
+    fn sort_lines_keeping_line_endings() {
+        let text = "pear\nApple\nbanana\napple";
+
+        assert_eq!(sort(text, false, false), "Apple\napple\nbanana\npear");
+        assert_eq!(sort(text, true, false), "pear\nbanana\napple\nApple");
+        // Apple and apple compare equal and stay in their original order
+        assert_eq!(sort(text, false, true), "Apple\napple\nbanana\npear");
+        assert_eq!(sort(text, true, true), "pear\nbanana\nApple\napple");
+
+        // An empty line sorts first, a single line is left alone
+        assert_eq!(sort("b\n\na", false, false), "\na\nb");
+        assert_eq!(sort("b", false, false), "b");
+    }
+
+    #[test]
+    fn sort_the_selected_lines() {
+        let src = graphemes("zz\nc\nb\na\nyy\n");
+        let mut editor = Editor::headless(&src);
+        editor.move_cursor_to_idx(4);
+        // From the end of the second line to the start of the fifth
+        editor.selection = Some(Selection::new(4, 9));
+
+        assert!(editor.sort_selected_lines(false, false));
+        assert_eq!(editor.content(), "zz\na\nb\nc\nyy\n");
+        assert_eq!(editor.get_cursor_absolute_position(), 4);
+        assert!(!editor.sort_selected_lines(false, false));
+
+        editor.selection = None;
+        assert!(!editor.sort_selected_lines(true, false));
+        assert_eq!(editor.content(), "zz\na\nb\nc\nyy\n");
+    }
+}
@@ -0,0 +1,260 @@
+use std::ops::Range;
+
+use piece_table::{graphemes, PtBuffer};
+
+use crate::edit::Change;
+use crate::language::LanguageConfig;
+use crate::selection::Selection;
+use crate::Editor;
+
+/// Delimiters picked by typing `key`: the bracket pair it opens or closes, the block
+/// comment of the language for `/`, or `key` itself on both sides.
+pub fn delimiters(key: char, language: &LanguageConfig) -> (Vec<String>, Vec<String>) {
+    let key = key.to_string();
+    let (open, close) = language
+        .brackets
+        .iter()
+        .find(|(open, close)| *open == key || *close == key)
+        .copied()
+        .or(language.block_comment.filter(|_| key == "/"))
+        .unwrap_or((&key, &key));
+
+    (graphemes(open), graphemes(close))
+}
+
+/// Insertions wrapping `range` between `open` and `close`, in document order.
+pub fn surround_changes(range: Range<usize>, open: &[String], close: &[String]) -> Vec<Change> {
+    vec![
+        Change::Insert {
+            at: range.start,
+            text: open.to_vec(),
+        },
+        Change::Insert {
+            at: range.end,
+            text: close.to_vec(),
+        },
+    ]
+}
+
+/// Spans of the closest `open` and `close` delimiters around `pos`, the cursor being
+/// allowed to sit on either of them. Nested pairs are skipped, and delimiters opening
+/// and closing alike are paired from the start of the line like quotes.
+pub fn find_surrounding(
+    doc: &PtBuffer<String>,
+    pos: usize,
+    open: &[String],
+    close: &[String],
+) -> Option<(Range<usize>, Range<usize>)> {
+    if open == close {
+        return find_pair_on_line(doc, pos, open);
+    }
+
+    let mut depth = 0;
+    let mut start = None;
+    for idx in (0..=pos.min(doc.len())).rev() {
+        if is_at(doc, idx, open) {
+            if depth == 0 {
+                start = Some(idx);
+                break;
+            }
+            depth -= 1;
+        } else if idx < pos && is_at(doc, idx, close) {
+            depth += 1;
+        }
+    }
+    let start = start?;
+
+    let mut depth = 0;
+    for idx in pos.max(start + open.len())..doc.len() {
+        if is_at(doc, idx, close) {
+            if depth == 0 {
+                return Some((start..start + open.len(), idx..idx + close.len()));
+            }
+            depth -= 1;
+        } else if is_at(doc, idx, open) {
+            depth += 1;
+        }
+    }
+
+    None
+}
+
+/// Spans of the pair of `delimiter` around `pos`, pairing the unescaped ones of the line
+/// left to right.
+fn find_pair_on_line(
+    doc: &PtBuffer<String>,
+    pos: usize,
+    delimiter: &[String],
+) -> Option<(Range<usize>, Range<usize>)> {
+    let end = doc.line_end(pos);
+    let mut found = vec![];
+    let mut escaped = false;
+    let mut idx = doc.line_start(pos);
+    while idx < end {
+        if !escaped && is_at(doc, idx, delimiter) {
+            found.push(idx..idx + delimiter.len());
+            idx += delimiter.len();
+            continue;
+        }
+        escaped = doc[idx] == "\\" && !escaped;
+        idx += 1;
+    }
+
+    found
+        .chunks_exact(2)
+        .find(|pair| (pair[0].start..pair[1].end).contains(&pos))
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+}
+
+fn is_at(doc: &PtBuffer<String>, idx: usize, delimiter: &[String]) -> bool {
+    idx + delimiter.len() <= doc.len() && doc.range(idx..idx + delimiter.len()).eq(delimiter)
+}
+
+impl Editor<'_> {
+    /// Wrap the selection in the delimiters picked by `key`, the selection then covering
+    /// the same text. Without a selection, the cursor ends up between the delimiters.
+    pub(crate) fn surround(&mut self, key: char) -> bool {
+        let (open, close) = delimiters(key, &self.language);
+        let selection = self.selection.take();
+        let range = match selection {
+            Some(selection) => selection.range(),
+            None => {
+                let pos = self.get_cursor_absolute_position();
+                pos..pos
+            }
+        };
+
+        let start = range.start;
+        self.apply_edits(surround_changes(range, &open, &close));
+        let cursor = match selection {
+            Some(selection) => {
+                let selection =
+                    Selection::new(selection.anchor + open.len(), selection.head + open.len());
+                self.selection = Some(selection);
+                selection.head
+            }
+            None => start + open.len(),
+        };
+        self.move_cursor_to_idx(cursor);
+        true
+    }
+
+    /// Remove the delimiters picked by `key` around the cursor, returning whether there
+    /// were any.
+    pub(crate) fn unsurround(&mut self, key: char) -> bool {
+        let (open, close) = delimiters(key, &self.language);
+        let pos = self.get_cursor_absolute_position();
+        let Some((open, close)) = find_surrounding(&self.doc, pos, &open, &close) else {
+            return false;
+        };
+
+        // Off the removed delimiters, the cursor stays on the text they held
+        let cursor = pos
+            .saturating_sub(open.len())
+            .clamp(open.start, close.start - open.len());
+        self.apply_edits(vec![Change::Remove(open), Change::Remove(close)]);
+        self.move_cursor_to_idx(cursor);
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use piece_table::{graphemes, PtBuffer};
+
+    use crate::edit::Change;
+    use crate::language::LanguageConfig;
+    use crate::selection::Selection;
+    use crate::surround::{delimiters, find_surrounding, surround_changes};
+    use crate::{Command, Editor};
+
+    #[test]
+    fn surround_a_range() {
+        let rust = LanguageConfig::rust();
+        let (open, close) = delimiters(')', &rust);
+        assert_eq!((open.concat(), close.concat()), ("(".into(), ")".into()));
+        let (open, close) = delimiters('/', &rust);
+        assert_eq!((open.concat(), close.concat()), ("/*".into(), "*/".into()));
+        let (open, close) = delimiters('"', &rust);
+        assert_eq!(
+            surround_changes(4..9, &open, &close),
+            [
+                Change::Insert {
+                    at: 4,
+                    text: graphemes("\""),
+                },
+                Change::Insert {
+                    at: 9,
+                    text: graphemes("\""),
+                },
+            ]
+        );
+        let (open, close) = delimiters('/', &LanguageConfig::plain_text());
+        assert_eq!((open.concat(), close.concat()), ("/".into(), "/".into()));
+    }
+
+    #[test]
+    fn find_the_closest_delimiters() {
+        let src = graphemes("f(a, (b), c) /* d */");
+        let doc = PtBuffer::new(&src);
+        let (open, close) = (graphemes("("), graphemes(")"));
+
+        // Around, on the opening or on the closing one, skipping nested pairs
+        for pos in [2, 1, 11, 9] {
+            assert_eq!(
+                find_surrounding(&doc, pos, &open, &close),
+                Some((1..2, 11..12)),
+                "{pos}"
+            );
+        }
+        assert_eq!(find_surrounding(&doc, 6, &open, &close), Some((5..6, 7..8)));
+        assert_eq!(find_surrounding(&doc, 0, &open, &close), None);
+        assert_eq!(find_surrounding(&doc, 13, &open, &close), None);
+
+        let (open, close) = (graphemes("/*"), graphemes("*/"));
+        assert_eq!(
+            find_surrounding(&doc, 16, &open, &close),
+            Some((13..15, 18..20))
+        );
+    }
+
+    #[test]
+    fn surround_and_unsurround() {
+        let src = graphemes("let a = b + c;");
+        let mut editor = Editor::headless(&src);
+        editor.language = LanguageConfig::rust();
+
+        editor.selection = Some(Selection::new(13, 8));
+        let output = editor.run_script([Command::Surround, Command::Char('(')]);
+        assert_eq!(output, "let a = (b + c);");
+        assert_eq!(editor.selection, Some(Selection::new(14, 9)));
+        assert_eq!(editor.get_cursor_absolute_position(), 9);
+
+        let output = editor.run_script([Command::Surround, Command::Char('/')]);
+        assert_eq!(output, "let a = (/*b + c*/);");
+        assert_eq!(editor.selection, Some(Selection::new(16, 11)));
+
+        let output = editor.run_script([Command::Cancel, Command::Unsurround, Command::Char('/')]);
+        assert_eq!(output, "let a = (b + c);");
+        assert_eq!(editor.get_cursor_absolute_position(), 9);
+        let output = editor.run_script([Command::Unsurround, Command::Char(']')]);
+        assert_eq!(output, "let a = (b + c);");
+        let output = editor.run_script([Command::Unsurround, Command::Char(')')]);
+        assert_eq!(output, "let a = b + c;");
+        assert_eq!(editor.get_cursor_absolute_position(), 8);
+
+        // Without a selection, the cursor goes between the delimiters
+        editor.move_cursor_to_idx(13);
+        let output = editor.run_script([Command::Surround, Command::Char('"')]);
+        assert_eq!(output, "let a = b + c\"\";");
+        assert_eq!(editor.get_cursor_absolute_position(), 14);
+
+        // Quotes pair from the line start, the cursor on the closing one of the first pair
+        let src = graphemes("\"a\" \"b\"");
+        let mut editor = Editor::headless(&src);
+        editor.move_cursor_to_idx(2);
+        let output = editor.run_script([Command::Unsurround, Command::Char('"')]);
+        assert_eq!(output, "a \"b\"");
+        assert_eq!(editor.get_cursor_absolute_position(), 1);
+    }
+}
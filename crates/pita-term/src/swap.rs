@@ -0,0 +1,93 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Snapshot of an unsaved document, written next to the file to survive a crash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Swap {
+    pub path: String,
+    pub revision: u64,
+    pub content: String,
+}
+
+impl Swap {
+    /// The source path and revision on their own lines, followed by the content.
+    pub fn encode(&self) -> String {
+        format!("{}\n{}\n{}", self.path, self.revision, self.content)
+    }
+
+    pub fn decode(swap: &str) -> Option<Self> {
+        let mut parts = swap.splitn(3, '\n');
+        let path = parts.next()?.to_string();
+        let revision = parts.next()?.parse().ok()?;
+        let content = parts.next()?.to_string();
+        Some(Self {
+            path,
+            revision,
+            content,
+        })
+    }
+}
+
+/// Sidecar swap file of `path`: `dir/file.rs` is backed by `dir/.file.rs.swp`.
+pub fn swap_path(path: impl AsRef<Path>) -> PathBuf {
+    let path = path.as_ref();
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{name}.swp"))
+}
+
+/// A swap file is worth recovering when it was written after the file itself was saved.
+pub fn is_recoverable(
+    file_modified: Option<SystemTime>,
+    swap_modified: Option<SystemTime>,
+) -> bool {
+    match (file_modified, swap_modified) {
+        (_, None) => false,
+        (None, Some(_)) => true,
+        (Some(file), Some(swap)) => swap > file,
+    }
+}
+
+/// The swap file of `path` if it holds edits newer than the file.
+pub fn find_recoverable(path: impl AsRef<Path>) -> Option<PathBuf> {
+    let modified = |path: &Path| fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    let swap = swap_path(&path);
+    is_recoverable(modified(path.as_ref()), modified(&swap)).then_some(swap)
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    use crate::swap::{is_recoverable, swap_path, Swap};
+
+    #[test]
+    fn detect_recoverable_swap() {
+        let saved = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let later = saved + Duration::from_secs(5);
+
+        assert!(is_recoverable(Some(saved), Some(later)));
+        assert!(!is_recoverable(Some(later), Some(saved)));
+        assert!(!is_recoverable(Some(saved), Some(saved)));
+        assert!(!is_recoverable(Some(saved), None));
+        // The file was never saved or got deleted, the swap is all there is
+        assert!(is_recoverable(None, Some(saved)));
+    }
+
+    #[test]
+    fn swap_roundtrip() {
+        let swap = Swap {
+            path: "src/main.rs".to_string(),
+            revision: 42,
+            content: "fn main() {\n}\n".to_string(),
+        };
+
+        assert_eq!(Swap::decode(&swap.encode()), Some(swap));
+        assert_eq!(Swap::decode("src/main.rs\nnot a revision\n"), None);
+        assert_eq!(swap_path("src/main.rs"), PathBuf::from("src/.main.rs.swp"));
+    }
+}
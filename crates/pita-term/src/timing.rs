@@ -0,0 +1,115 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::span::Id;
+use tracing::Subscriber;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+/// Time spent in each instrumented span, aggregated over the session.
+#[derive(Debug, Clone, Default)]
+pub struct FrameTimings {
+    /// In the order spans were first entered
+    totals: Arc<Mutex<Vec<SpanTotal>>>,
+}
+
+#[derive(Debug)]
+struct SpanTotal {
+    name: &'static str,
+    runs: u32,
+    total: Duration,
+}
+
+/// When the span was entered, stored in its extensions.
+struct Entered(Instant);
+
+impl FrameTimings {
+    /// Collect timings from every thread for the rest of the session.
+    pub fn install() -> Self {
+        let timings = Self::default();
+        tracing_subscriber::registry().with(timings.clone()).init();
+        timings
+    }
+
+    /// One line per span, in the order they first ran.
+    pub fn report(&self) -> Vec<String> {
+        self.totals
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|SpanTotal { name, runs, total }| {
+                let average = *total / *runs;
+                format!("{name}: {runs} runs, {average:?} average, {total:?} total")
+            })
+            .collect()
+    }
+
+    fn record(&self, name: &'static str, elapsed: Duration) {
+        let mut totals = self.totals.lock().unwrap();
+        match totals.iter_mut().find(|span| span.name == name) {
+            Some(span) => {
+                span.runs += 1;
+                span.total += elapsed;
+            }
+            None => totals.push(SpanTotal {
+                name,
+                runs: 1,
+                total: elapsed,
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for FrameTimings
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().replace(Entered(Instant::now()));
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+
+        let entered = span.extensions_mut().remove::<Entered>();
+        if let Some(Entered(start)) = entered {
+            self.record(span.name(), start.elapsed());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use crate::timing::FrameTimings;
+    use crate::{Command, Editor};
+
+    #[test]
+    fn time_spans_in_frame_order() {
+        let src: Vec<String> = vec!["a".to_string()];
+        let mut editor = Editor::headless(&src);
+        let timings = FrameTimings::default();
+        let subscriber = tracing_subscriber::registry().with(timings.clone());
+
+        tracing::subscriber::with_default(subscriber, || {
+            assert!(editor.apply(Command::Char('b')));
+            editor.redraw();
+            editor.editor_screen.present();
+        });
+
+        let names: Vec<String> = timings
+            .report()
+            .iter()
+            .map(|line| line.split(':').next().unwrap().to_string())
+            .collect();
+        assert_eq!(names, ["update_highlights", "draw_doc", "present"]);
+        assert!(timings.report()[0].starts_with("update_highlights: 1 runs"));
+    }
+}